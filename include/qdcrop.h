@@ -0,0 +1,68 @@
+/* The C interface of qdcrop, built with `cargo build --release --features ffi`. */
+
+#ifndef QDCROP_H
+#define QDCROP_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Error codes. Every function that can fail returns one of these, and the message of the error
+ * is in qdcrop_last_error(). */
+#define QDCROP_OK 0
+#define QDCROP_PANICKED -1
+#define QDCROP_DETECTION_FAILED 1
+#define QDCROP_LOW_CONFIDENCE 2
+#define QDCROP_DEGENERATE_QUAD 3
+#define QDCROP_WARP_FAILED 4
+#define QDCROP_AUTO_ROTATE_FAILED 5
+#define QDCROP_DECODE_ERROR 6
+#define QDCROP_ENCODE_ERROR 7
+#define QDCROP_INVALID_OPTIONS 8
+#define QDCROP_IO 9
+
+typedef struct QdcropOptions {
+    /* The WebP quality of outputs, from 0 to 100. */
+    float quality;
+    /* The proportions of the photos as width divided by height, or 0 to estimate them. */
+    double aspect;
+    /* The widest output, or 0 for no limit. */
+    double max_width;
+    /* The tallest output, or 0 for no limit. */
+    double max_height;
+} QdcropOptions;
+
+/* The default settings, the same as the defaults of the qdcrop command. */
+QdcropOptions qdcrop_default_options(void);
+
+/* Find the corners of the most confident photo in a PNG or JPEG file.
+ *
+ * options may be NULL for the defaults. corners receives eight floats, the x and y of each
+ * corner clockwise from the top left. confidence may be NULL. */
+int32_t qdcrop_detect(const uint8_t *input, size_t input_len, const QdcropOptions *options,
+                      float *corners, double *confidence);
+
+/* Crop the photo out of a PNG or JPEG file and encode it as WebP.
+ *
+ * The photo is cropped at corners, eight floats as written by qdcrop_detect, or at the corners of
+ * the most confident photo if corners is NULL. Those corners are written to found_corners unless
+ * it is NULL. The output must be freed with qdcrop_free. */
+int32_t qdcrop_crop(const uint8_t *input, size_t input_len, const QdcropOptions *options,
+                    const float *corners, float *found_corners, uint8_t **output,
+                    size_t *output_len);
+
+/* Free an output returned by qdcrop_crop. */
+void qdcrop_free(uint8_t *output, size_t output_len);
+
+/* The message of the last error on the calling thread, or "" if the last call succeeded. It stays
+ * valid until the next call on the same thread. */
+const char *qdcrop_last_error(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
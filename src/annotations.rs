@@ -0,0 +1,313 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::{anyhow, bail, Context};
+use qdcrop::{contour, Quad};
+use serde_json::{json, Value};
+
+/// The label given to photos in annotation files.
+const LABEL: &str = "photo";
+
+/// A format used by image labeling tools.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Format {
+    /// One COCO JSON file for every screenshot, with each photo as a segmentation polygon.
+    #[default]
+    Coco,
+    /// One LabelMe JSON file for each screenshot, with each photo as a polygon shape.
+    LabelMe,
+}
+
+impl FromStr for Format {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "coco" => Ok(Format::Coco),
+            "labelme" => Ok(Format::LabelMe),
+            _ => Err(anyhow!("Unknown annotation format {}", s)),
+        }
+    }
+}
+
+impl Format {
+    /// The names accepted by `from_str`.
+    pub const NAMES: &'static [&'static str] = &["coco", "labelme"];
+}
+
+/// The photos found in one screenshot.
+#[derive(Clone, Debug)]
+pub struct Annotation<'a> {
+    /// The screenshot.
+    pub input: &'a Path,
+    /// The size of the screenshot.
+    pub size: (u32, u32),
+    /// The corners of each photo, clockwise from the top left.
+    pub quads: Vec<Quad>,
+}
+
+/// The absolute path of a screenshot, so annotation files can be opened from anywhere.
+fn absolute(path: &Path) -> String {
+    path.canonicalize()
+        .as_deref()
+        .unwrap_or(path)
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Build a COCO dataset.
+fn coco(annotations: &[Annotation]) -> Value {
+    let mut images = Vec::new();
+    let mut shapes = Vec::new();
+    for (image_id, annotation) in (1..).zip(annotations) {
+        images.push(json!({
+            "id": image_id,
+            "file_name": absolute(annotation.input),
+            "width": annotation.size.0,
+            "height": annotation.size.1,
+        }));
+        for quad in &annotation.quads {
+            let (mut left, mut top) = quad[0];
+            let (mut right, mut bottom) = quad[0];
+            for (x, y) in quad {
+                left = left.min(*x);
+                top = top.min(*y);
+                right = right.max(*x);
+                bottom = bottom.max(*y);
+            }
+            shapes.push(json!({
+                "id": shapes.len() + 1,
+                "image_id": image_id,
+                "category_id": 1,
+                "segmentation": [quad.iter().flat_map(|p| [p.0, p.1]).collect::<Vec<_>>()],
+                "area": contour::area(quad),
+                "bbox": [left, top, right - left, bottom - top],
+                "iscrowd": 0,
+            }));
+        }
+    }
+    json!({
+        "images": images,
+        "annotations": shapes,
+        "categories": [{ "id": 1, "name": LABEL }],
+    })
+}
+
+/// Build a LabelMe annotation for one screenshot.
+fn labelme(annotation: &Annotation) -> Value {
+    let shapes: Vec<_> = annotation
+        .quads
+        .iter()
+        .map(|quad| {
+            json!({
+                "label": LABEL,
+                "points": quad.map(|p| [p.0, p.1]),
+                "group_id": null,
+                "shape_type": "polygon",
+                "flags": {},
+            })
+        })
+        .collect();
+    json!({
+        "version": "5.0.1",
+        "flags": {},
+        "shapes": shapes,
+        "imagePath": absolute(annotation.input),
+        "imageData": null,
+        "imageWidth": annotation.size.0,
+        "imageHeight": annotation.size.1,
+    })
+}
+
+/// Save detected photos in a format used by image labeling tools.
+///
+/// # Arguments
+///
+/// * `path` - The COCO file to write, or the directory to write LabelMe files into. LabelMe files
+///   are named after their screenshots.
+/// * `format` - The format to write.
+/// * `annotations` - The photos found in each screenshot.
+///
+/// # Errors
+///
+/// An error is returned if a file cannot be written.
+pub fn export(path: &Path, format: Format, annotations: &[Annotation]) -> anyhow::Result<()> {
+    match format {
+        Format::Coco => {
+            let text = serde_json::to_string_pretty(&coco(annotations))?;
+            fs::write(path, text).context("Could not write annotations")
+        }
+        Format::LabelMe => {
+            fs::create_dir_all(path).context("Could not create annotation directory")?;
+            for annotation in annotations {
+                let mut name = annotation.input.file_stem().unwrap_or_default().to_owned();
+                name.push(".json");
+                let text = serde_json::to_string_pretty(&labelme(annotation))?;
+                fs::write(path.join(name), text).context("Could not write annotations")?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// The key used to look up a screenshot in imported annotations.
+pub fn key(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_owned())
+}
+
+/// Put the corners of a polygon clockwise from the top left, whatever order they were drawn in.
+///
+/// # Errors
+///
+/// An error is returned if the polygon does not have exactly four corners.
+fn quad(points: &[(f32, f32)]) -> anyhow::Result<Quad> {
+    let mut corners: Quad = points.try_into().map_err(|_| {
+        anyhow!(
+            "Expected a polygon with 4 corners, but found {}",
+            points.len()
+        )
+    })?;
+    let center = (
+        corners.iter().map(|p| p.0).sum::<f32>() / 4.0,
+        corners.iter().map(|p| p.1).sum::<f32>() / 4.0,
+    );
+    // Clockwise on screen, where y points down.
+    corners.sort_by(|a, b| {
+        let angle = |p: &(f32, f32)| (p.1 - center.1).atan2(p.0 - center.0);
+        angle(a).total_cmp(&angle(b))
+    });
+    let top_left = (0..4)
+        .min_by(|&a, &b| (corners[a].0 + corners[a].1).total_cmp(&(corners[b].0 + corners[b].1)))
+        .unwrap();
+    corners.rotate_left(top_left);
+    Ok(corners)
+}
+
+/// Read the numbers of a JSON array as points.
+fn points(values: &[Value]) -> anyhow::Result<Vec<(f32, f32)>> {
+    let numbers = values
+        .iter()
+        .map(|v| v.as_f64().map(|n| n as f32))
+        .collect::<Option<Vec<_>>>()
+        .context("Expected numbers")?;
+    if numbers.len() % 2 != 0 {
+        bail!("Expected pairs of coordinates");
+    }
+    Ok(numbers.chunks(2).map(|p| (p[0], p[1])).collect())
+}
+
+/// Resolve the path of a screenshot named in an annotation file.
+fn image_path(annotation_file: &Path, name: &str) -> PathBuf {
+    key(&annotation_file
+        .parent()
+        .unwrap_or_else(|| Path::new(""))
+        .join(name))
+}
+
+/// Read the photos in a COCO dataset.
+fn import_coco(path: &Path, json: &Value) -> anyhow::Result<HashMap<PathBuf, Vec<Quad>>> {
+    let mut files = HashMap::new();
+    for image in json["images"].as_array().context("Missing images")? {
+        let id = image["id"].as_u64().context("Image without an ID")?;
+        let name = image["file_name"]
+            .as_str()
+            .context("Image without a file name")?;
+        files.insert(id, image_path(path, name));
+    }
+    let mut quads: HashMap<PathBuf, Vec<Quad>> = HashMap::new();
+    for annotation in json["annotations"]
+        .as_array()
+        .context("Missing annotations")?
+    {
+        let image = annotation["image_id"]
+            .as_u64()
+            .and_then(|id| files.get(&id))
+            .context("Annotation for an unknown image")?;
+        let polygon = annotation["segmentation"][0]
+            .as_array()
+            .context("Annotation without a polygon")?;
+        let quad = quad(&points(polygon)?)
+            .with_context(|| format!("Invalid photo in {}", image.to_string_lossy()))?;
+        quads.entry(image.clone()).or_default().push(quad);
+    }
+    Ok(quads)
+}
+
+/// Read the photos in one LabelMe file.
+fn import_labelme(path: &Path, json: &Value) -> anyhow::Result<(PathBuf, Vec<Quad>)> {
+    let image = image_path(
+        path,
+        json["imagePath"].as_str().context("Missing imagePath")?,
+    );
+    let mut quads = Vec::new();
+    for shape in json["shapes"].as_array().context("Missing shapes")? {
+        let points = shape["points"]
+            .as_array()
+            .context("Shape without points")?
+            .iter()
+            .map(|p| points(p.as_array().map(Vec::as_slice).unwrap_or_default()))
+            .collect::<anyhow::Result<Vec<_>>>()?
+            .concat();
+        let points = match shape["shape_type"].as_str() {
+            Some("rectangle") if points.len() == 2 => {
+                let ((x0, y0), (x1, y1)) = (points[0], points[1]);
+                vec![(x0, y0), (x1, y0), (x1, y1), (x0, y1)]
+            }
+            _ => points,
+        };
+        quads.push(
+            quad(&points)
+                .with_context(|| format!("Invalid photo in {}", path.to_string_lossy()))?,
+        );
+    }
+    Ok((image, quads))
+}
+
+/// Read one annotation file, detecting whether it is COCO or LabelMe.
+fn import_file(path: &Path) -> anyhow::Result<HashMap<PathBuf, Vec<Quad>>> {
+    let text = fs::read_to_string(path)
+        .with_context(|| format!("Could not read {}", path.to_string_lossy()))?;
+    let json: Value = serde_json::from_str(&text)
+        .with_context(|| format!("Invalid JSON in {}", path.to_string_lossy()))?;
+    if json.get("images").is_some() {
+        import_coco(path, &json)
+    } else if json.get("shapes").is_some() {
+        let (image, quads) = import_labelme(path, &json)?;
+        Ok(HashMap::from([(image, quads)]))
+    } else {
+        bail!("{} is not a COCO or LabelMe file", path.to_string_lossy())
+    }
+}
+
+/// Read corrected photo corners from annotation files.
+///
+/// # Arguments
+///
+/// * `path` - A COCO file, a LabelMe file, or a directory of LabelMe files. Relative image
+///   paths are relative to the file that names them.
+///
+/// Returns the corners of the photos in each screenshot, keyed by `key`.
+///
+/// # Errors
+///
+/// An error is returned if a file cannot be read or a photo does not have four corners.
+pub fn import(path: &Path) -> anyhow::Result<HashMap<PathBuf, Vec<Quad>>> {
+    if !path.is_dir() {
+        return import_file(path);
+    }
+    let mut quads = HashMap::new();
+    for entry in fs::read_dir(path).context("Could not read annotation directory")? {
+        let file = entry.context("Could not read annotation directory")?.path();
+        if file
+            .extension()
+            .is_some_and(|e| e.eq_ignore_ascii_case("json"))
+        {
+            quads.extend(import_file(&file)?);
+        }
+    }
+    Ok(quads)
+}
@@ -0,0 +1,61 @@
+use crate::Quad;
+
+/// The cross product of two homogeneous points.
+fn cross(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+/// Estimate the true width-to-height ratio of a rectangle photographed in perspective.
+///
+/// This is the single-view rectification from Zhang and He's "Whiteboard scanning and image
+/// enhancement": the vanishing points of the sides give the focal length of the camera, which
+/// recovers the proportions of the rectangle. The camera's principal point is assumed to be the
+/// center of the screenshot. When the sides are parallel in the screenshot, there is no
+/// perspective to measure and the ratio of the side lengths is used.
+///
+/// # Arguments
+///
+/// * `quad` - The corners of the photo, clockwise from the top left.
+/// * `size` - The size of the screenshot.
+///
+/// Returns `None` if the corners do not fit any camera, which happens with poorly detected
+/// corners.
+pub fn estimate(quad: &Quad, size: (u32, u32)) -> Option<f64> {
+    let (cx, cy) = (size.0 as f64 / 2.0, size.1 as f64 / 2.0);
+    let point = |(x, y): (f32, f32)| [x as f64 - cx, y as f64 - cy, 1.0];
+    // Zhang and He number the corners top left, top right, bottom left, bottom right.
+    let (m1, m2, m3, m4) = (
+        point(quad[0]),
+        point(quad[1]),
+        point(quad[3]),
+        point(quad[2]),
+    );
+    let k2 = dot(cross(m1, m4), m3) / dot(cross(m2, m4), m3);
+    let k3 = dot(cross(m1, m4), m2) / dot(cross(m3, m4), m2);
+    let n2 = [k2 * m2[0] - m1[0], k2 * m2[1] - m1[1], k2 * m2[2] - m1[2]];
+    let n3 = [k3 * m3[0] - m1[0], k3 * m3[1] - m1[1], k3 * m3[2] - m1[2]];
+
+    // With almost no perspective, the focal length cannot be measured.
+    let perspective = n2[2] * n3[2];
+    let scale = n2[0].abs().max(n2[1].abs()) * n3[0].abs().max(n3[1].abs());
+    let ratio_squared = if perspective.abs() <= scale * 1e-6 {
+        (n2[0] * n2[0] + n2[1] * n2[1]) / (n3[0] * n3[0] + n3[1] * n3[1])
+    } else {
+        let focal_squared = -(n2[0] * n3[0] + n2[1] * n3[1]) / perspective;
+        if focal_squared <= 0.0 {
+            return None;
+        }
+        (n2[0] * n2[0] / focal_squared + n2[1] * n2[1] / focal_squared + n2[2] * n2[2])
+            / (n3[0] * n3[0] / focal_squared + n3[1] * n3[1] / focal_squared + n3[2] * n3[2])
+    };
+    let ratio = ratio_squared.sqrt();
+    ratio.is_finite().then_some(ratio).filter(|r| *r > 0.0)
+}
@@ -0,0 +1,187 @@
+use std::str::FromStr;
+
+use anyhow::anyhow;
+use image::{Rgb, RgbImage};
+use imageproc::geometric_transformations::Projection;
+
+use crate::Quad;
+
+/// How the warp samples the source image between pixels.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Interpolation {
+    /// Use the closest source pixel. This is the fastest, for quick previews.
+    Nearest,
+    /// Blend the four closest source pixels. This does not ring around sharp edges like text.
+    Bilinear,
+    /// Blend the sixteen closest source pixels with a cubic curve.
+    #[default]
+    Bicubic,
+    /// Blend many source pixels with a Lanczos kernel that widens where the photo is shrunk.
+    /// This is the slowest, but fine patterns do not alias.
+    Lanczos,
+}
+
+impl FromStr for Interpolation {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "nearest" => Ok(Interpolation::Nearest),
+            "bilinear" => Ok(Interpolation::Bilinear),
+            "bicubic" => Ok(Interpolation::Bicubic),
+            "lanczos" => Ok(Interpolation::Lanczos),
+            _ => Err(anyhow!("Unknown interpolation {}", s)),
+        }
+    }
+}
+
+impl Interpolation {
+    /// The names accepted by `from_str`.
+    pub const NAMES: &'static [&'static str] = &["nearest", "bilinear", "bicubic", "lanczos"];
+}
+
+/// Find the projective map from the unit square to a quad, as `[a, b, c, d, e, f, g, h]`.
+///
+/// A point `(u, v)` maps to `((a u + b v + c) / w, (d u + e v + f) / w)` with
+/// `w = g u + h v + 1`. This is Heckbert's square-to-quad mapping.
+pub fn square_to_quad(quad: &Quad) -> [f32; 8] {
+    let [(x0, y0), (x1, y1), (x2, y2), (x3, y3)] = *quad;
+    let (dx1, dx2, dx3) = (x1 - x2, x3 - x2, x0 - x1 + x2 - x3);
+    let (dy1, dy2, dy3) = (y1 - y2, y3 - y2, y0 - y1 + y2 - y3);
+    let det = dx1 * dy2 - dx2 * dy1;
+    let (g, h) = if det.abs() <= f32::EPSILON {
+        (0.0, 0.0)
+    } else {
+        ((dx3 * dy2 - dx2 * dy3) / det, (dx1 * dy3 - dx3 * dy1) / det)
+    };
+    [
+        x1 - x0 + g * x1,
+        x3 - x0 + h * x3,
+        x0,
+        y1 - y0 + g * y1,
+        y3 - y0 + h * y3,
+        y0,
+        g,
+        h,
+    ]
+}
+
+/// An implementation of the perspective warp.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Backend {
+    /// The scalar warp from imageproc. This is the reference implementation.
+    #[default]
+    Cpu,
+    /// The same bicubic warp with SSE4.1 or AVX2, chosen by what the processor supports. Other
+    /// interpolations, and processors without SSE4.1, use `Cpu`.
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    Simd,
+    /// A compute shader on the graphics card, through wgpu. This needs the `gpu` feature.
+    #[cfg(feature = "gpu")]
+    Gpu,
+}
+
+impl FromStr for Backend {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(Backend::detect()),
+            "cpu" => Ok(Backend::Cpu),
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            "simd" => Ok(Backend::Simd),
+            #[cfg(feature = "gpu")]
+            "gpu" => Ok(Backend::Gpu),
+            _ => Err(anyhow!("Unknown backend {}", s)),
+        }
+    }
+}
+
+impl Backend {
+    /// Every backend compiled into this binary. The reference backend is first.
+    pub const ALL: &'static [Backend] = &[
+        Backend::Cpu,
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        Backend::Simd,
+        #[cfg(feature = "gpu")]
+        Backend::Gpu,
+    ];
+
+    /// The names accepted by `from_str` in a build with every feature.
+    pub const NAMES: &'static [&'static str] = &["auto", "cpu", "simd", "gpu"];
+
+    /// The fastest backend on the processor that needs no options: `Simd` if it is supported,
+    /// and `Cpu` otherwise.
+    pub fn detect() -> Self {
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        if crate::simd::is_supported() {
+            return Backend::Simd;
+        }
+        Backend::Cpu
+    }
+
+    /// The name of the backend as shown to users.
+    pub fn name(self) -> &'static str {
+        match self {
+            Backend::Cpu => "cpu",
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            Backend::Simd => "simd",
+            #[cfg(feature = "gpu")]
+            Backend::Gpu => "gpu",
+        }
+    }
+
+    /// Reverse a perspective projection.
+    ///
+    /// # Arguments
+    ///
+    /// * `img` - The source image.
+    /// * `projection` - The projection from source coordinates to output coordinates.
+    /// * `size` - The width and height of the output image.
+    /// * `interpolation` - How to sample the source image.
+    ///
+    /// # Errors
+    ///
+    /// An error is returned if the backend is unable to run on this machine.
+    pub fn warp(
+        self,
+        img: &RgbImage,
+        projection: &Projection,
+        size: (u32, u32),
+        interpolation: Interpolation,
+    ) -> anyhow::Result<RgbImage> {
+        match self {
+            Backend::Cpu => {
+                use imageproc::geometric_transformations::Interpolation as Sampling;
+
+                let sampling = match interpolation {
+                    Interpolation::Nearest => Sampling::Nearest,
+                    Interpolation::Bilinear => Sampling::Bilinear,
+                    Interpolation::Bicubic => Sampling::Bicubic,
+                    Interpolation::Lanczos => {
+                        return Ok(crate::lanczos::warp(img, projection, size))
+                    }
+                };
+                let mut out_img = crate::pool::rgb(size.0, size.1);
+                imageproc::geometric_transformations::warp_into(
+                    img,
+                    projection,
+                    sampling,
+                    Rgb([0, 0, 0]),
+                    &mut out_img,
+                );
+                Ok(out_img)
+            }
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            Backend::Simd => match interpolation {
+                Interpolation::Bicubic => match crate::simd::warp(img, projection, size) {
+                    Some(out_img) => Ok(out_img),
+                    None => Backend::Cpu.warp(img, projection, size, interpolation),
+                },
+                _ => Backend::Cpu.warp(img, projection, size, interpolation),
+            },
+            #[cfg(feature = "gpu")]
+            Backend::Gpu => crate::gpu::warp(img, projection, size, interpolation, 1),
+        }
+    }
+}
@@ -0,0 +1,180 @@
+use std::{collections::HashMap, str::FromStr};
+
+use anyhow::{anyhow, Context};
+use image::{GrayImage, Luma, Rgb, RgbImage};
+
+use crate::{timings, CropOptions};
+
+/// How many of the most common colors are tried when the border color is chosen automatically.
+const AUTO_CANDIDATES: usize = 3;
+/// How many low bits of each channel are ignored when counting colors.
+const AUTO_QUANTIZE: u32 = 3;
+/// The brightest channel of a pixel along the edges that still looks like a black border, when
+/// no black threshold is given.
+const EDGE_BLACK: u8 = 24;
+/// The largest fraction of the pixels along the edges that may look like the border in a
+/// picture without one, which allows for dark details that touch the edges.
+const MAX_EDGE_BORDER: f64 = 0.01;
+
+/// The color of the frame around the photo.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BorderColor {
+    /// Dark borders, found with the threshold mode.
+    #[default]
+    Black,
+    /// Try black and the most common colors in the screenshot.
+    Auto,
+    /// Pixels within the tolerance of this color.
+    Rgb([u8; 3]),
+}
+
+impl FromStr for BorderColor {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "black" => Ok(BorderColor::Black),
+            "auto" => Ok(BorderColor::Auto),
+            _ => {
+                let hex = s.strip_prefix('#').unwrap_or(s);
+                if hex.len() != 6 || !hex.is_ascii() {
+                    return Err(anyhow!(
+                        "Unknown border color {}. Expected black, auto, or a hex color like #ffffff",
+                        s
+                    ));
+                }
+                let channel = |i: usize| {
+                    u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16)
+                        .with_context(|| format!("Invalid border color {}", s))
+                };
+                Ok(BorderColor::Rgb([channel(0)?, channel(1)?, channel(2)?]))
+            }
+        }
+    }
+}
+
+/// Make a black and white image where pixels close to `color` are black.
+///
+/// # Arguments
+///
+/// * `img` - The screenshot.
+/// * `color` - The color of the border.
+/// * `tolerance` - The largest difference in any channel that still matches.
+pub fn mask(img: &RgbImage, color: [u8; 3], tolerance: u8) -> GrayImage {
+    let mut mask = crate::pool::gray(img.width(), img.height());
+    for (m, pixel) in mask.pixels_mut().zip(img.pixels()) {
+        let matches = pixel
+            .0
+            .iter()
+            .zip(color)
+            .all(|(p, c)| p.abs_diff(c) <= tolerance);
+        *m = Luma([if matches { 0 } else { 255 }]);
+    }
+    mask
+}
+
+/// Check that the pixels along the edges of a screenshot do not look like the border, so that
+/// it has none left around the photo.
+///
+/// Automatic border colors are checked as black, the usual color.
+pub fn is_borderless(img: &RgbImage, options: &CropOptions) -> bool {
+    let is_border = |pixel: &Rgb<u8>| match options.border_color {
+        BorderColor::Black | BorderColor::Auto => {
+            let level = options.black_threshold.unwrap_or(EDGE_BLACK);
+            pixel.0.iter().all(|&c| c <= level)
+        }
+        BorderColor::Rgb(color) => pixel
+            .0
+            .iter()
+            .zip(color)
+            .all(|(p, c)| p.abs_diff(c) <= options.border_tolerance),
+    };
+    let (width, height) = img.dimensions();
+    if width == 0 || height == 0 {
+        return false;
+    }
+    let edges = (0..width)
+        .flat_map(|x| [(x, 0), (x, height - 1)])
+        .chain((0..height).flat_map(|y| [(0, y), (width - 1, y)]));
+    let (mut total, mut border) = (0usize, 0usize);
+    for (x, y) in edges {
+        total += 1;
+        if is_border(img.get_pixel(x, y)) {
+            border += 1;
+        }
+    }
+    border as f64 <= total as f64 * MAX_EDGE_BORDER
+}
+
+/// Find the most common colors in an image, most common first.
+fn common_colors(img: &RgbImage, count: usize) -> Vec<[u8; 3]> {
+    let mut histogram = HashMap::new();
+    for pixel in img.pixels() {
+        *histogram
+            .entry(pixel.0.map(|c| c >> AUTO_QUANTIZE))
+            .or_insert(0u32) += 1;
+    }
+    let mut colors: Vec<_> = histogram.into_iter().collect();
+    colors.sort_by(|a, b| b.1.cmp(&a.1));
+    colors
+        .into_iter()
+        .take(count)
+        // Use the middle of each bucket.
+        .map(|(color, _)| color.map(|c| (c << AUTO_QUANTIZE) | (1 << (AUTO_QUANTIZE - 1))))
+        .collect()
+}
+
+/// Make a black and white image where dark border pixels are black.
+///
+/// Pixels no brighter than the black threshold are border pixels even if the threshold mode
+/// disagrees, which catches the dark gray ring that JPEG compression leaves around borders.
+pub(crate) fn threshold(luma: &GrayImage, options: &CropOptions) -> GrayImage {
+    let mut threshold = options.threshold_mode.apply(luma, options.threshold_radius);
+    if let Some(level) = options.black_threshold {
+        for (t, l) in threshold.pixels_mut().zip(luma.pixels()) {
+            if l.0[0] <= level {
+                t.0[0] = 0;
+            }
+        }
+    }
+    threshold
+}
+
+/// Make the images that detectors search for border pixels, in the order they are tried.
+///
+/// Border pixels are black in each image. The images are made one at a time as they are
+/// needed, so that only one is kept in memory when the first one is enough.
+///
+/// # Arguments
+///
+/// * `img` - The screenshot.
+/// * `luma` - The screenshot in grayscale.
+/// * `options` - Settings that describe the border.
+pub fn masks<'a>(
+    img: &'a RgbImage,
+    luma: &'a GrayImage,
+    options: &'a CropOptions,
+) -> impl Iterator<Item = GrayImage> + 'a {
+    let black = matches!(options.border_color, BorderColor::Black | BorderColor::Auto);
+    // The common colors are only counted if the black border is not enough.
+    let colors = std::iter::once(()).flat_map(move |()| match options.border_color {
+        BorderColor::Black => Vec::new(),
+        BorderColor::Rgb(color) => vec![color],
+        BorderColor::Auto => common_colors(img, AUTO_CANDIDATES),
+    });
+    black
+        .then_some(None)
+        .into_iter()
+        .chain(colors.map(Some))
+        .map(move |color| {
+            let mut mask = timings::time(timings::Stage::Threshold, || match color {
+                None => threshold(luma, options),
+                Some(color) => mask(img, color, options.border_tolerance),
+            });
+            // Excluded regions never contain border pixels.
+            for region in &options.exclude {
+                region.fill(&mut mask, 255);
+            }
+            mask
+        })
+}
@@ -0,0 +1,405 @@
+use std::{
+    collections::HashMap,
+    fmt, fs,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use anyhow::Context;
+use qdcrop::CropOptions;
+use serde_json::{json, Value};
+
+/// The version of the manifest format. Manifests of other versions are ignored.
+const VERSION: u64 = 2;
+
+/// Hash the settings that change outputs, so that screenshots are cropped again when they
+/// change.
+///
+/// Settings that do not change the outputs themselves, such as the conflict policy, the debug
+/// folder, or the backend, which makes the same pixels, are left out. The model is hashed by its
+/// path and when it last changed.
+pub fn options_hash(options: &CropOptions) -> u64 {
+    // Every field is named, so that a new one cannot be added without deciding whether it is
+    // hashed.
+    let CropOptions {
+        conflict: _,
+        threshold_mode,
+        threshold_radius,
+        black_threshold,
+        border_color,
+        border_tolerance,
+        detectors,
+        aspect,
+        orientation,
+        max_size,
+        scale_mode,
+        resize_filter,
+        interpolation,
+        backend: _,
+        model,
+        distortion,
+        exclude,
+        corners,
+        debug_dir: _,
+        min_area,
+        min_confidence,
+        low_confidence,
+        verify_outputs: _,
+        rounded_corners,
+        straighten,
+        margin,
+        deskew_only,
+        no_warp,
+        inscribed,
+        trim_edges,
+        rotate,
+        flip_horizontal,
+        flip_vertical,
+        auto_rotate,
+        warp_only,
+        multiple,
+        quality,
+        fast_encode,
+        strip_metadata,
+        json_sidecar,
+        use_sidecar: _,
+        xmp_sidecar,
+        preserve_times: _,
+        tile_pyramid,
+        detect_size,
+        corner_window,
+        skip_cropped,
+    } = options;
+    let model = model
+        .as_ref()
+        .map(|model| (model.path(), stamp(model.path())));
+    let fields: [&dyn fmt::Debug; 41] = [
+        threshold_mode,
+        threshold_radius,
+        black_threshold,
+        border_color,
+        border_tolerance,
+        detectors,
+        aspect,
+        orientation,
+        max_size,
+        scale_mode,
+        resize_filter,
+        interpolation,
+        distortion,
+        exclude,
+        corners,
+        min_area,
+        min_confidence,
+        low_confidence,
+        rounded_corners,
+        straighten,
+        margin,
+        deskew_only,
+        no_warp,
+        inscribed,
+        trim_edges,
+        rotate,
+        flip_horizontal,
+        flip_vertical,
+        auto_rotate,
+        warp_only,
+        multiple,
+        quality,
+        fast_encode,
+        strip_metadata,
+        json_sidecar,
+        xmp_sidecar,
+        tile_pyramid,
+        detect_size,
+        corner_window,
+        skip_cropped,
+        &model,
+    ];
+    // FNV-1a, which unlike the hasher of the standard library is the same in every build.
+    format!("{:?}", fields)
+        .bytes()
+        .fold(0xcbf29ce484222325, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        })
+}
+
+/// The size and modification time of a file, in nanoseconds since 1970.
+fn stamp(path: &Path) -> Option<(u64, u64)> {
+    let metadata = fs::metadata(path).ok()?;
+    let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    Some((metadata.len(), modified.as_nanos() as u64))
+}
+
+/// The key of a screenshot in the manifest, which is its full path when it can be found.
+fn key(input: &Path) -> PathBuf {
+    fs::canonicalize(input).unwrap_or_else(|_| input.to_owned())
+}
+
+/// The full path of an output, which may not exist, such as the path given to several photos
+/// that are written with numbers.
+fn output_key(output: &Path) -> PathBuf {
+    match (output.parent(), output.file_name()) {
+        (Some(parent), Some(name)) => {
+            let parent = if parent == Path::new("") {
+                Path::new(".")
+            } else {
+                parent
+            };
+            key(parent).join(name)
+        }
+        _ => key(output),
+    }
+}
+
+/// What was recorded about a screenshot when it was cropped.
+struct Entry {
+    /// The size of the screenshot.
+    size: u64,
+    /// The modification time of the screenshot, in nanoseconds since 1970.
+    modified: u64,
+    /// The `options_hash` of the settings it was cropped with.
+    options: u64,
+    /// Where it was asked to be written, by `output_key`.
+    output: PathBuf,
+    /// The files that were written.
+    outputs: Vec<PathBuf>,
+}
+
+impl Entry {
+    /// Read an entry from the manifest, or `None` if it is not valid.
+    fn read(json: &Value) -> Option<Entry> {
+        let outputs = json["outputs"]
+            .as_array()?
+            .iter()
+            .map(|output| output.as_str().map(PathBuf::from))
+            .collect::<Option<_>>()?;
+        Some(Entry {
+            size: json["size"].as_u64()?,
+            modified: json["modified"].as_u64()?,
+            options: json["options"].as_u64()?,
+            output: PathBuf::from(json["output"].as_str()?),
+            outputs,
+        })
+    }
+}
+
+/// A manifest of the screenshots that were cropped, so that running qdcrop again over the same
+/// folder only crops screenshots that changed, or all of them when the settings changed.
+pub struct Cache {
+    /// Where the manifest is saved.
+    path: PathBuf,
+    /// The screenshots that were cropped, by `key`.
+    entries: HashMap<PathBuf, Entry>,
+}
+
+impl Cache {
+    /// Load the manifest at `path`, or start an empty one if there is none.
+    ///
+    /// A manifest that cannot be read is reported and replaced, since it only saves time.
+    pub fn load(path: &Path) -> Cache {
+        let mut cache = Cache {
+            path: path.to_owned(),
+            entries: HashMap::new(),
+        };
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(_) => return cache,
+        };
+        let json: Value = match serde_json::from_str(&text) {
+            Ok(json) if json["version"].as_u64() == Some(VERSION) => json,
+            _ => {
+                eprintln!(
+                    "Ignoring the cache {}, which cannot be read",
+                    path.to_string_lossy()
+                );
+                return cache;
+            }
+        };
+        for (input, entry) in json["entries"].as_object().into_iter().flatten() {
+            if let Some(entry) = Entry::read(entry) {
+                cache.entries.insert(PathBuf::from(input), entry);
+            }
+        }
+        cache
+    }
+
+    /// Whether a screenshot was cropped to `output` with settings of `options_hash` since it
+    /// last changed, and all of its outputs still exist.
+    pub fn is_current(&self, input: &Path, output: &Path, options_hash: u64) -> bool {
+        let Some(entry) = self.entries.get(&key(input)) else {
+            return false;
+        };
+        entry.options == options_hash
+            && entry.output == output_key(output)
+            && stamp(input) == Some((entry.size, entry.modified))
+            && entry.outputs.iter().all(|output| output.exists())
+    }
+
+    /// Record that a screenshot was cropped to `output` with settings of `options_hash`, and
+    /// wrote `outputs`.
+    pub fn record(&mut self, input: &Path, output: &Path, options_hash: u64, outputs: &[PathBuf]) {
+        let Some((size, modified)) = stamp(input) else {
+            return;
+        };
+        let entry = Entry {
+            size,
+            modified,
+            options: options_hash,
+            output: output_key(output),
+            outputs: outputs.iter().map(|output| key(output)).collect(),
+        };
+        self.entries.insert(key(input), entry);
+    }
+
+    /// Save the manifest.
+    ///
+    /// It is written next to its path and then renamed over it, so that a manifest is never
+    /// left half written.
+    ///
+    /// # Errors
+    ///
+    /// An error is returned if the manifest cannot be written.
+    pub fn save(&self) -> anyhow::Result<()> {
+        let entries: serde_json::Map<_, _> = self
+            .entries
+            .iter()
+            .map(|(input, entry)| {
+                let outputs: Vec<_> = entry
+                    .outputs
+                    .iter()
+                    .map(|output| output.to_string_lossy())
+                    .collect();
+                let value = json!({
+                    "size": entry.size,
+                    "modified": entry.modified,
+                    "options": entry.options,
+                    "output": entry.output.to_string_lossy(),
+                    "outputs": outputs,
+                });
+                (input.to_string_lossy().into_owned(), value)
+            })
+            .collect();
+        let json = json!({ "version": VERSION, "entries": entries });
+        let mut temporary = self.path.clone().into_os_string();
+        temporary.push(".tmp");
+        fs::write(&temporary, serde_json::to_string(&json)?)
+            .and_then(|_| fs::rename(&temporary, &self.path))
+            .with_context(|| format!("Could not save the cache {}", self.path.to_string_lossy()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use qdcrop::{
+        backend::Interpolation,
+        border::BorderColor,
+        confidence::LowConfidence,
+        distortion::Distortion,
+        resize::{ResizeFilter, ScaleMode},
+        Aspect, AspectMode, CornerDetector, CropOptions, Detector, Margin, MaxSize, Orientation,
+        Region, ThresholdMode,
+    };
+
+    use super::options_hash;
+
+    #[test]
+    fn output_settings_change_the_hash() {
+        // The model is left out, since one cannot be loaded without the `ml` feature and a
+        // model file.
+        let changes: &[(&str, fn(&mut CropOptions))] = &[
+            ("threshold_mode", |o| o.threshold_mode = ThresholdMode::Otsu),
+            ("threshold_radius", |o| o.threshold_radius += 1),
+            ("black_threshold", |o| o.black_threshold = Some(10)),
+            ("border_color", |o| {
+                o.border_color = BorderColor::Rgb([255; 3])
+            }),
+            ("border_tolerance", |o| o.border_tolerance += 1),
+            ("detectors", |o| {
+                o.detectors = vec![Arc::new(Detector::Hough) as Arc<dyn CornerDetector>]
+            }),
+            ("aspect", |o| {
+                o.aspect = AspectMode::Fixed(Aspect(4.0 / 3.0))
+            }),
+            ("orientation", |o| o.orientation = Orientation::Portrait),
+            ("max_size", |o| {
+                o.max_size = MaxSize {
+                    width: None,
+                    height: None,
+                }
+            }),
+            ("scale_mode", |o| o.scale_mode = ScaleMode::Fused),
+            ("resize_filter", |o| {
+                o.resize_filter = ResizeFilter::Triangle
+            }),
+            ("interpolation", |o| {
+                o.interpolation = Interpolation::Nearest
+            }),
+            ("distortion", |o| {
+                o.distortion = Some(Distortion { k1: 0.1, k2: 0.0 })
+            }),
+            ("exclude", |o| {
+                o.exclude = vec![Region {
+                    x: 0,
+                    y: 0,
+                    width: 10,
+                    height: 10,
+                }]
+            }),
+            ("corners", |o| {
+                o.corners = vec![[(0.0, 0.0), (10.0, 0.0), (10.0, 10.0), (0.0, 10.0)]]
+            }),
+            ("min_area", |o| o.min_area += 1.0),
+            ("min_confidence", |o| o.min_confidence += 0.1),
+            ("low_confidence", |o| o.low_confidence = LowConfidence::Copy),
+            ("rounded_corners", |o| o.rounded_corners = true),
+            ("straighten", |o| o.straighten = true),
+            ("margin", |o| o.margin = Margin::Percent(1.0)),
+            ("deskew_only", |o| o.deskew_only = true),
+            ("no_warp", |o| o.no_warp = true),
+            ("inscribed", |o| o.inscribed = true),
+            ("trim_edges", |o| o.trim_edges = true),
+            ("rotate", |o| o.rotate = 90),
+            ("flip_horizontal", |o| o.flip_horizontal = true),
+            ("flip_vertical", |o| o.flip_vertical = true),
+            ("auto_rotate", |o| o.auto_rotate = true),
+            ("warp_only", |o| o.warp_only = true),
+            ("multiple", |o| o.multiple = true),
+            ("quality", |o| o.quality -= 1.0),
+            ("fast_encode", |o| o.fast_encode = true),
+            ("strip_metadata", |o| o.strip_metadata = true),
+            ("json_sidecar", |o| o.json_sidecar = true),
+            ("xmp_sidecar", |o| o.xmp_sidecar = true),
+            ("tile_pyramid", |o| o.tile_pyramid = true),
+            ("detect_size", |o| o.detect_size = Some(512)),
+            ("corner_window", |o| o.corner_window = Some(10.0)),
+            ("skip_cropped", |o| o.skip_cropped = true),
+        ];
+        let base = CropOptions::default();
+        let hash = options_hash(&base);
+        for (field, change) in changes {
+            let mut options = base.clone();
+            change(&mut options);
+            assert_ne!(
+                options_hash(&options),
+                hash,
+                "{} does not change the hash",
+                field
+            );
+        }
+    }
+
+    #[test]
+    fn other_settings_do_not_change_the_hash() {
+        let base = CropOptions::default();
+        let options = CropOptions {
+            debug_dir: Some("debug".into()),
+            verify_outputs: true,
+            preserve_times: true,
+            ..base.clone()
+        };
+        assert_eq!(options_hash(&options), options_hash(&base));
+    }
+}
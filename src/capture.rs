@@ -0,0 +1,109 @@
+use std::{path::Path, time::SystemTime};
+
+/// When a screenshot was taken, as a civil date and time.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CaptureTime {
+    pub year: i64,
+    pub month: u32,
+    pub day: u32,
+    pub hour: u32,
+    pub minute: u32,
+    pub second: u32,
+}
+
+impl CaptureTime {
+    /// Read the time from a VRChat screenshot's name.
+    ///
+    /// VRChat names screenshots like `VRChat_2024-05-12_23-41-07.123_1920x1080.png`, and older
+    /// versions like `VRChat_1920x1080_2022-01-01_12-00-00.000.png`, in local time.
+    ///
+    /// Returns `None` if the name has no such time.
+    pub fn from_vrchat_name(name: &str) -> Option<Self> {
+        let rest = name.strip_prefix("VRChat_")?;
+        // YYYY-MM-DD_HH-MM-SS
+        const PATTERN: &[u8] = b"0000-00-00_00-00-00";
+        let bytes = rest.as_bytes();
+        let start = (0..=bytes.len().checked_sub(PATTERN.len())?).find(|&start| {
+            bytes[start..start + PATTERN.len()]
+                .iter()
+                .zip(PATTERN)
+                .all(|(b, p)| {
+                    if *p == b'0' {
+                        b.is_ascii_digit()
+                    } else {
+                        b == p
+                    }
+                })
+        })?;
+        let number = |from: usize, to: usize| rest[start + from..start + to].parse::<u32>().ok();
+        let time = CaptureTime {
+            year: number(0, 4)? as i64,
+            month: number(5, 7)?,
+            day: number(8, 10)?,
+            hour: number(11, 13)?,
+            minute: number(14, 16)?,
+            second: number(17, 19)?,
+        };
+        let valid = (1..=12).contains(&time.month)
+            && (1..=31).contains(&time.day)
+            && time.hour < 24
+            && time.minute < 60
+            && time.second < 61;
+        valid.then_some(time)
+    }
+
+    /// Convert a system time to UTC.
+    pub fn from_system_time(time: SystemTime) -> Self {
+        let seconds = match time.duration_since(SystemTime::UNIX_EPOCH) {
+            Ok(since) => since.as_secs() as i64,
+            Err(before) => -(before.duration().as_secs() as i64),
+        };
+        let days = seconds.div_euclid(86400);
+        let of_day = seconds.rem_euclid(86400) as u32;
+        // Convert days to a civil date, from Howard Hinnant's `civil_from_days`.
+        let z = days + 719468;
+        let era = z.div_euclid(146097);
+        let doe = z - era * 146097;
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+        CaptureTime {
+            year: yoe + era * 400 + (month <= 2) as i64,
+            month,
+            day,
+            hour: of_day / 3600,
+            minute: of_day / 60 % 60,
+            second: of_day % 60,
+        }
+    }
+
+    /// Find when a screenshot was taken, from its VRChat name or else its modification time.
+    pub fn of(path: &Path) -> Option<Self> {
+        path.file_name()
+            .and_then(|name| CaptureTime::from_vrchat_name(&name.to_string_lossy()))
+            .or_else(|| {
+                let modified = path.metadata().and_then(|m| m.modified()).ok()?;
+                Some(CaptureTime::from_system_time(modified))
+            })
+    }
+
+    /// The date as `YYYY-MM-DD`.
+    pub fn date(&self) -> String {
+        format!("{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+
+    /// The time of day as `HH-MM-SS`, which can be used in file names.
+    pub fn time(&self) -> String {
+        format!("{:02}-{:02}-{:02}", self.hour, self.minute, self.second)
+    }
+
+    /// The date and time as written in EXIF, `YYYY:MM:DD HH:MM:SS`.
+    pub fn exif(&self) -> String {
+        format!(
+            "{:04}:{:02}:{:02} {:02}:{:02}:{:02}",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
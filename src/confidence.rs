@@ -0,0 +1,94 @@
+use std::str::FromStr;
+
+use anyhow::anyhow;
+use image::GrayImage;
+
+use crate::{contour, Quad};
+
+/// What to do with a file whose detection confidence is too low.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LowConfidence {
+    /// Fail the job with an error.
+    #[default]
+    Report,
+    /// Copy the input to the output directory without changing it.
+    Copy,
+}
+
+impl FromStr for LowConfidence {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "report" => Ok(LowConfidence::Report),
+            "copy" => Ok(LowConfidence::Copy),
+            _ => Err(anyhow!("Unknown low confidence action {}", s)),
+        }
+    }
+}
+
+impl LowConfidence {
+    /// The names accepted by `from_str`.
+    pub const NAMES: &'static [&'static str] = &["report", "copy"];
+}
+
+/// Measure how much of the outline of the quad lies on border pixels.
+fn edge_coverage(threshold: &GrayImage, quad: &Quad) -> f64 {
+    contour::sample_outline(quad, threshold.dimensions(), |x, y| {
+        threshold.get_pixel(x, y).0[0] == 0
+    })
+}
+
+/// Score how close every corner of the quad is to a right angle.
+///
+/// A photo seen from an angle has corners that are not right angles, but corners that are
+/// more than 45 degrees off are scored 0.
+fn angle_sanity(quad: &Quad) -> f64 {
+    let mut total = 0.0;
+    for i in 0..4 {
+        let (x, y) = quad[i];
+        let (px, py) = quad[(i + 3) % 4];
+        let (nx, ny) = quad[(i + 1) % 4];
+        let (ax, ay) = ((px - x) as f64, (py - y) as f64);
+        let (bx, by) = ((nx - x) as f64, (ny - y) as f64);
+        let lengths = ax.hypot(ay) * bx.hypot(by);
+        if lengths == 0.0 {
+            return 0.0;
+        }
+        let angle = ((ax * bx + ay * by) / lengths)
+            .clamp(-1.0, 1.0)
+            .acos()
+            .to_degrees();
+        total += f64::max(0.0, 1.0 - (angle - 90.0).abs() / 45.0);
+    }
+    total / 4.0
+}
+
+/// Score how close the shape of the quad is to the expected aspect ratio.
+fn aspect_plausibility(quad: &Quad, aspect: f64) -> f64 {
+    let distance = |a: (f32, f32), b: (f32, f32)| ((b.0 - a.0) as f64).hypot((b.1 - a.1) as f64);
+    let width = (distance(quad[0], quad[1]) + distance(quad[3], quad[2])) / 2.0;
+    let height = (distance(quad[0], quad[3]) + distance(quad[1], quad[2])) / 2.0;
+    if width == 0.0 || height == 0.0 {
+        return 0.0;
+    }
+    let ratio = width / height / aspect;
+    f64::min(ratio, 1.0 / ratio)
+}
+
+/// Estimate how likely it is that a quad is really the outline of the photo.
+///
+/// The score is between 0 and 1. It combines how much of the outline lies on border pixels,
+/// how close the corners are to right angles, and how close the shape is to the expected aspect
+/// ratio.
+///
+/// # Arguments
+///
+/// * `threshold` - The image searched by detection, where border pixels are black.
+/// * `quad` - The detected corners.
+/// * `aspect` - The expected width of the photo divided by its height.
+pub fn score(threshold: &GrayImage, quad: &Quad, aspect: f64) -> f64 {
+    0.5 * edge_coverage(threshold, quad)
+        + 0.25 * angle_sanity(quad)
+        + 0.25 * aspect_plausibility(quad, aspect)
+}
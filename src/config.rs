@@ -0,0 +1,247 @@
+use std::{
+    collections::HashMap,
+    env,
+    ffi::OsStr,
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    process::Command,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use anyhow::{anyhow, bail, Context};
+
+/// The name of the directory next to the executable used in portable mode.
+const PORTABLE_DIR: &str = "qdcrop-data";
+
+/// Whether `--portable` was given.
+static PORTABLE: AtomicBool = AtomicBool::new(false);
+
+/// The name of the default configuration file in the configuration directory.
+const CONFIG_FILE: &str = "config.toml";
+
+/// The configuration file written by `qdcrop config edit` when there is none yet.
+const TEMPLATE: &str = "\
+# Default settings for qdcrop.
+#
+# Keys are the long command line options without the leading dashes. Options given on the
+# command line take precedence. Flags are enabled with `true`.
+#
+# on-conflict = \"rename-with-suffix\"
+# detector = \"contour,nearest\"
+# min-confidence = 0.6
+# verify-outputs = true
+";
+
+/// Keep all of qdcrop's files next to the executable instead of in the user's directories.
+pub fn set_portable(portable: bool) {
+    PORTABLE.store(portable, Ordering::Relaxed);
+}
+
+/// The directory next to the executable used in portable mode.
+fn portable_dir() -> anyhow::Result<PathBuf> {
+    let exe = env::current_exe().context("Could not find the executable")?;
+    let exe = exe.canonicalize().unwrap_or(exe);
+    Ok(exe
+        .parent()
+        .context("Could not find the executable's directory")?
+        .join(PORTABLE_DIR))
+}
+
+/// The directory holding the default configuration, frame profiles, LUTs, history, and
+/// temporary files.
+///
+/// This is `%APPDATA%\qdcrop` on Windows, `~/Library/Application Support/qdcrop` on macOS, and
+/// `$XDG_CONFIG_HOME/qdcrop` or `~/.config/qdcrop` elsewhere. In portable mode, or if a
+/// `qdcrop-data` directory already exists next to the executable, that directory is used instead.
+pub fn dir() -> anyhow::Result<PathBuf> {
+    if PORTABLE.load(Ordering::Relaxed) {
+        return portable_dir();
+    }
+    if let Ok(dir) = portable_dir() {
+        if dir.is_dir() {
+            return Ok(dir);
+        }
+    }
+    let base = if cfg!(windows) {
+        env::var_os("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        env::var_os("HOME").map(|home| Path::new(&home).join("Library/Application Support"))
+    } else {
+        env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+    };
+    base.map(|base| base.join("qdcrop"))
+        .ok_or_else(|| anyhow!("Could not find the configuration directory"))
+}
+
+/// The path of the default configuration file.
+pub fn path() -> anyhow::Result<PathBuf> {
+    Ok(dir()?.join(CONFIG_FILE))
+}
+
+/// Default values for command line options.
+#[derive(Clone, Debug, Default)]
+pub struct Config {
+    values: HashMap<String, String>,
+}
+
+/// Parse one value. Strings may be quoted, and everything after an unquoted `#` is a comment.
+fn parse_value(value: &str) -> anyhow::Result<String> {
+    let value = value.trim();
+    for quote in ['"', '\''] {
+        if let Some(rest) = value.strip_prefix(quote) {
+            let end = rest
+                .find(quote)
+                .ok_or_else(|| anyhow!("Unterminated string"))?;
+            let after = rest[end + 1..].trim();
+            if !after.is_empty() && !after.starts_with('#') {
+                bail!("Unexpected {} after string", after);
+            }
+            return Ok(rest[..end].to_owned());
+        }
+    }
+    let value = value.split('#').next().unwrap().trim();
+    if value.is_empty() {
+        bail!("Missing value");
+    }
+    Ok(value.to_owned())
+}
+
+impl Config {
+    /// Parse a configuration file.
+    ///
+    /// The format is the subset of TOML made of `key = value` lines and comments.
+    pub fn parse(text: &str) -> anyhow::Result<Self> {
+        let mut values = HashMap::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| anyhow!("Line {}: expected key = value", number + 1))?;
+            let value = parse_value(value).with_context(|| format!("Line {}", number + 1))?;
+            values.insert(key.trim().to_owned(), value);
+        }
+        Ok(Config { values })
+    }
+
+    /// Load the default configuration file, or an empty configuration if there is none.
+    pub fn load() -> anyhow::Result<Self> {
+        let path = path()?;
+        match fs::read_to_string(&path) {
+            Ok(text) => Config::parse(&text)
+                .with_context(|| format!("Invalid configuration in {}", path.to_string_lossy())),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Config::default()),
+            Err(e) => Err(e).context("Could not read configuration"),
+        }
+    }
+}
+
+/// Command line options with defaults taken from the configuration file.
+pub struct Settings<'a> {
+    matches: &'a clap::ArgMatches<'a>,
+    config: Config,
+}
+
+impl<'a> Settings<'a> {
+    pub fn new(matches: &'a clap::ArgMatches<'a>, config: Config) -> Self {
+        Settings { matches, config }
+    }
+
+    /// The value of an option, from the command line if it was given there, otherwise from the
+    /// configuration, otherwise its default.
+    pub fn value_of(&self, name: &str) -> Option<&str> {
+        if self.matches.occurrences_of(name) == 0 {
+            if let Some(value) = self.config.values.get(name) {
+                return Some(value);
+            }
+        }
+        self.matches.value_of(name)
+    }
+
+    /// The value of an option that may not be valid UTF-8, such as a path.
+    pub fn value_of_os(&self, name: &str) -> Option<&OsStr> {
+        if self.matches.occurrences_of(name) == 0 {
+            if let Some(value) = self.config.values.get(name) {
+                return Some(value.as_ref());
+            }
+        }
+        self.matches.value_of_os(name)
+    }
+
+    /// The values of an option that may be repeated, from the command line if it was given
+    /// there, otherwise from the configuration, where they are separated by `;`.
+    pub fn values_of(&self, name: &str) -> Vec<&str> {
+        if self.matches.occurrences_of(name) == 0 {
+            if let Some(values) = self.config.values.get(name) {
+                return values
+                    .split(';')
+                    .map(str::trim)
+                    .filter(|value| !value.is_empty())
+                    .collect();
+            }
+        }
+        self.matches.values_of(name).unwrap_or_default().collect()
+    }
+
+    /// Whether an option was given on the command line or in the configuration, rather than
+    /// left at its default.
+    pub fn is_given(&self, name: &str) -> bool {
+        self.matches.occurrences_of(name) > 0 || self.config.values.contains_key(name)
+    }
+
+    /// Whether a flag is enabled on the command line or in the configuration.
+    pub fn is_present(&self, name: &str) -> anyhow::Result<bool> {
+        if self.matches.is_present(name) {
+            return Ok(true);
+        }
+        match self.config.values.get(name).map(String::as_str) {
+            None | Some("false") => Ok(false),
+            Some("true") => Ok(true),
+            Some(value) => bail!("Invalid value {} for {} in configuration", value, name),
+        }
+    }
+}
+
+/// Open the configuration file in the user's editor, creating it first if needed.
+fn edit() -> anyhow::Result<()> {
+    let path = path()?;
+    if !path.exists() {
+        fs::create_dir_all(dir()?).context("Could not create configuration directory")?;
+        fs::write(&path, TEMPLATE).context("Could not create configuration")?;
+    }
+    let editor = env::var_os("VISUAL")
+        .or_else(|| env::var_os("EDITOR"))
+        .unwrap_or_else(|| if cfg!(windows) { "notepad" } else { "vi" }.into());
+    let status = Command::new(&editor)
+        .arg(&path)
+        .status()
+        .with_context(|| format!("Could not start {}", editor.to_string_lossy()))?;
+    if !status.success() {
+        bail!("{} exited with {}", editor.to_string_lossy(), status);
+    }
+    Config::load()?;
+    Ok(())
+}
+
+/// Run the `config` subcommand.
+pub fn run(matches: &clap::ArgMatches) -> anyhow::Result<()> {
+    match matches.subcommand_name() {
+        Some("path") => println!("{}", path()?.to_string_lossy()),
+        Some("show") => match fs::read_to_string(path()?) {
+            Ok(text) => print!("{}", text),
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                eprintln!("There is no configuration file yet.")
+            }
+            Err(e) => return Err(e).context("Could not read configuration"),
+        },
+        Some("edit") => edit()?,
+        _ => unreachable!(),
+    }
+    Ok(())
+}
@@ -0,0 +1,130 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::{anyhow, bail, Context};
+
+/// What to do when an output file already exists.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    /// Fail the job.
+    Error,
+    /// Leave the existing file alone and skip the job.
+    Skip,
+    /// Replace the existing file.
+    #[default]
+    Overwrite,
+    /// Write to a new file named with a numeric suffix, like `image-1.webp`.
+    RenameWithSuffix,
+    /// Replace the existing file only if the input is newer than it.
+    KeepNewest,
+}
+
+impl FromStr for ConflictPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "error" => Ok(ConflictPolicy::Error),
+            "skip" => Ok(ConflictPolicy::Skip),
+            "overwrite" => Ok(ConflictPolicy::Overwrite),
+            // `rename` was the name before `rename-with-suffix`, and is still accepted.
+            "rename-with-suffix" | "rename" => Ok(ConflictPolicy::RenameWithSuffix),
+            "keep-newest" => Ok(ConflictPolicy::KeepNewest),
+            _ => Err(anyhow!("Unknown conflict policy {}", s)),
+        }
+    }
+}
+
+impl ConflictPolicy {
+    /// The names accepted by `from_str`.
+    pub const NAMES: &'static [&'static str] = &[
+        "error",
+        "skip",
+        "overwrite",
+        "rename-with-suffix",
+        "rename",
+        "keep-newest",
+    ];
+
+    /// Decide whether a job should run at all.
+    ///
+    /// This is checked before the input is loaded so skipped jobs are cheap.
+    ///
+    /// # Errors
+    ///
+    /// An error is returned if the policy is `Error` and the output exists, or if the
+    /// modification times needed by `KeepNewest` cannot be read.
+    pub fn should_process(self, input: &Path, output: &Path) -> anyhow::Result<bool> {
+        if !output.exists() {
+            return Ok(true);
+        }
+        match self {
+            ConflictPolicy::Error => bail!("{} already exists", output.to_string_lossy()),
+            ConflictPolicy::Skip => Ok(false),
+            ConflictPolicy::Overwrite | ConflictPolicy::RenameWithSuffix => Ok(true),
+            ConflictPolicy::KeepNewest => {
+                let input_time = input
+                    .metadata()
+                    .and_then(|m| m.modified())
+                    .context("Could not read input modification time")?;
+                let output_time = output
+                    .metadata()
+                    .and_then(|m| m.modified())
+                    .context("Could not read output modification time")?;
+                Ok(input_time > output_time)
+            }
+        }
+    }
+
+    /// Create an output file.
+    ///
+    /// Returns the path that was actually created, which differs from `output` when the
+    /// policy is `RenameWithSuffix`.
+    ///
+    /// # Errors
+    ///
+    /// An error is returned if the file cannot be created, or if it appeared after
+    /// `should_process` and the policy does not allow replacing it.
+    pub fn create(self, output: &Path) -> anyhow::Result<(PathBuf, File)> {
+        match self {
+            ConflictPolicy::Overwrite | ConflictPolicy::KeepNewest => {
+                let file = File::create(output).context("Could not create output")?;
+                Ok((output.to_owned(), file))
+            }
+            ConflictPolicy::Error | ConflictPolicy::Skip => {
+                let file = OpenOptions::new()
+                    .write(true)
+                    .create_new(true)
+                    .open(output)
+                    .context("Could not create output")?;
+                Ok((output.to_owned(), file))
+            }
+            ConflictPolicy::RenameWithSuffix => {
+                let stem = output.file_stem().unwrap_or_default().to_string_lossy();
+                let extension = output.extension().map(|e| e.to_string_lossy());
+                let mut candidate = output.to_owned();
+                for n in 1.. {
+                    match OpenOptions::new()
+                        .write(true)
+                        .create_new(true)
+                        .open(&candidate)
+                    {
+                        Ok(file) => return Ok((candidate, file)),
+                        Err(e) if e.kind() == ErrorKind::AlreadyExists => {}
+                        Err(e) => return Err(e).context("Could not create output"),
+                    }
+                    let name = match &extension {
+                        Some(extension) => format!("{}-{}.{}", stem, n, extension),
+                        None => format!("{}-{}", stem, n),
+                    };
+                    candidate = output.with_file_name(name);
+                }
+                unreachable!()
+            }
+        }
+    }
+}
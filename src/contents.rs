@@ -0,0 +1,45 @@
+use std::{fs::File, io, ops::Deref, path::Path};
+
+use memmap2::Mmap;
+
+/// The contents of an input file, mapped into memory when possible so that large screenshots
+/// are decoded straight from the page cache instead of being copied into a buffer first.
+pub enum Contents {
+    /// The file is mapped.
+    Mapped(Mmap),
+    /// The file was read, because it is empty or could not be mapped, as on some network
+    /// filesystems.
+    Read(Vec<u8>),
+}
+
+impl Deref for Contents {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Contents::Mapped(map) => map,
+            Contents::Read(data) => data,
+        }
+    }
+}
+
+/// Map or read a file.
+///
+/// # Errors
+///
+/// An error is returned if the file cannot be opened or read.
+pub fn read(path: &Path) -> io::Result<Contents> {
+    let file = File::open(path)?;
+    if file.metadata()?.len() > 0 {
+        // SAFETY: The map is only read, and is dropped once the screenshot is decoded, before
+        // anything is written. Screenshots are not expected to change while they are cropped;
+        // if one is truncated anyway, the decoder may see garbage or the process may be killed,
+        // which is no worse than a screenshot that is still being written.
+        if let Ok(map) = unsafe { Mmap::map(&file) } {
+            return Ok(Contents::Mapped(map));
+        }
+    }
+    let mut data = Vec::new();
+    io::Read::read_to_end(&mut &file, &mut data)?;
+    Ok(Contents::Read(data))
+}
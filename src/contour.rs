@@ -0,0 +1,178 @@
+use image::{GrayImage, ImageBuffer, Luma};
+use imageproc::region_labelling::{connected_components, Connectivity};
+
+use crate::Quad;
+
+/// How many candidate regions are checked for straight edges, largest first.
+const CANDIDATES: usize = 8;
+/// How many points are checked along each edge of an outline.
+const SAMPLES: u32 = 64;
+/// How far a sample point may be from a matching pixel, in pixels.
+const SAMPLE_RADIUS: i64 = 2;
+/// The fraction of sample points that must be near the region for it to count as a quadrilateral.
+const MIN_COVERAGE: f64 = 0.8;
+/// The smallest photo to look for, as a fraction of the screenshot area.
+const MIN_AREA: f64 = 0.05;
+
+/// The points of a region that are furthest toward each corner.
+#[derive(Clone, Copy, Debug)]
+struct Extremes {
+    label: u32,
+    /// Points and scores clockwise from the top left.
+    corners: [(i64, u32, u32); 4],
+}
+
+impl Extremes {
+    fn new(label: u32, x: u32, y: u32) -> Self {
+        let mut extremes = Extremes {
+            label,
+            corners: [(i64::MIN, x, y); 4],
+        };
+        extremes.add(x, y);
+        extremes
+    }
+
+    fn add(&mut self, x: u32, y: u32) {
+        let (x_, y_) = (x as i64, y as i64);
+        let scores = [-x_ - y_, x_ - y_, x_ + y_, y_ - x_];
+        for (corner, score) in self.corners.iter_mut().zip(scores) {
+            if score > corner.0 {
+                *corner = (score, x, y);
+            }
+        }
+    }
+
+    fn quad(&self) -> Quad {
+        self.corners.map(|(_, x, y)| (x as f32, y as f32))
+    }
+}
+
+/// Find the area of a quadrilateral with the shoelace formula.
+pub fn area(quad: &Quad) -> f64 {
+    let mut sum = 0.0;
+    for i in 0..4 {
+        let (x0, y0) = quad[i];
+        let (x1, y1) = quad[(i + 1) % 4];
+        sum += x0 as f64 * y1 as f64 - x1 as f64 * y0 as f64;
+    }
+    sum.abs() / 2.0
+}
+
+/// Measure what fraction of the points along the outline of a quadrilateral are near a pixel
+/// that `is_hit`, in an image of `size`.
+pub(crate) fn sample_outline(
+    quad: &Quad,
+    size: (u32, u32),
+    is_hit: impl Fn(u32, u32) -> bool,
+) -> f64 {
+    let (width, height) = size;
+    let mut hits = 0;
+    for i in 0..4 {
+        let (x0, y0) = quad[i];
+        let (x1, y1) = quad[(i + 1) % 4];
+        for s in 0..SAMPLES {
+            let t = s as f32 / SAMPLES as f32;
+            let (x, y) = (
+                (x0 + (x1 - x0) * t).round() as i64,
+                (y0 + (y1 - y0) * t).round() as i64,
+            );
+            let near = (y - SAMPLE_RADIUS..=y + SAMPLE_RADIUS).any(|y| {
+                (x - SAMPLE_RADIUS..=x + SAMPLE_RADIUS).any(|x| {
+                    x >= 0
+                        && y >= 0
+                        && x < width as i64
+                        && y < height as i64
+                        && is_hit(x as u32, y as u32)
+                })
+            });
+            if near {
+                hits += 1;
+            }
+        }
+    }
+    hits as f64 / (4 * SAMPLES) as f64
+}
+
+/// Measure how much of the outline of a quadrilateral is covered by a region.
+fn coverage(labels: &ImageBuffer<Luma<u32>, Vec<u32>>, label: u32, quad: &Quad) -> f64 {
+    sample_outline(quad, labels.dimensions(), |x, y| {
+        labels.get_pixel(x, y).0[0] == label
+    })
+}
+
+/// Find the groups of border pixels that outline quadrilaterals, largest first.
+fn quadrilaterals(threshold: &GrayImage) -> impl Iterator<Item = Quad> {
+    let (width, height) = threshold.dimensions();
+    let labels = connected_components(threshold, Connectivity::Eight, Luma([255u8]));
+
+    let mut regions: Vec<Option<Extremes>> = Vec::new();
+    for (x, y, label) in labels.enumerate_pixels() {
+        let label = label.0[0];
+        if label == 0 {
+            continue;
+        }
+        let index = label as usize;
+        if regions.len() <= index {
+            regions.resize(index + 1, None);
+        }
+        if let Some(extremes) = &mut regions[index] {
+            extremes.add(x, y);
+        } else {
+            regions[index] = Some(Extremes::new(label, x, y));
+        }
+    }
+
+    let min_area = width as f64 * height as f64 * MIN_AREA;
+    let mut candidates: Vec<_> = regions
+        .into_iter()
+        .flatten()
+        .map(|extremes| (area(&extremes.quad()), extremes))
+        .filter(|(area, _)| *area >= min_area)
+        .collect();
+    candidates.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+    candidates
+        .into_iter()
+        .take(CANDIDATES)
+        .map(|(_, extremes)| extremes)
+        .filter(move |extremes| coverage(&labels, extremes.label, &extremes.quad()) >= MIN_COVERAGE)
+        .map(|extremes| extremes.quad())
+}
+
+/// Check whether a point is inside a convex quadrilateral.
+fn contains(quad: &Quad, (x, y): (f32, f32)) -> bool {
+    (0..4).all(|i| {
+        let (x0, y0) = quad[i];
+        let (x1, y1) = quad[(i + 1) % 4];
+        (x1 - x0) * (y - y0) - (y1 - y0) * (x - x0) >= 0.0
+    })
+}
+
+/// Find the photo as the largest connected group of border pixels that outlines a quadrilateral.
+///
+/// Unlike the nearest-to-corner search, this finds photos that are far from the corners of the
+/// screenshot.
+///
+/// Returns `None` if no group of border pixels looks like a quadrilateral.
+pub fn detect(threshold: &GrayImage) -> Option<Quad> {
+    quadrilaterals(threshold).next()
+}
+
+/// Find every photo in a screenshot that holds several, ordered left to right.
+///
+/// Each photo is a connected group of border pixels that outlines a quadrilateral. Outlines
+/// inside a larger outline, such as the inner edge of a thick frame, are left out.
+pub fn detect_all(threshold: &GrayImage) -> Vec<Quad> {
+    let mut photos: Vec<Quad> = Vec::new();
+    for quad in quadrilaterals(threshold) {
+        let center = (
+            quad.iter().map(|p| p.0).sum::<f32>() / 4.0,
+            quad.iter().map(|p| p.1).sum::<f32>() / 4.0,
+        );
+        if !photos.iter().any(|photo| contains(photo, center)) {
+            photos.push(quad);
+        }
+    }
+    photos.sort_by(|a, b| (a[0].0 + a[3].0).total_cmp(&(b[0].0 + b[3].0)));
+    photos
+}
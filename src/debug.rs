@@ -0,0 +1,69 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use image::{GrayImage, Rgb, RgbImage};
+use imageproc::drawing::{draw_hollow_circle_mut, draw_line_segment_mut};
+use qdcrop::Quad;
+
+/// The color used to draw detected corners and edges.
+const COLOR: Rgb<u8> = Rgb([255, 0, 255]);
+
+/// Choose the path of a debug image.
+///
+/// # Arguments
+///
+/// * `dir` - The debug directory.
+/// * `input` - The path to the input file.
+/// * `suffix` - What the debug image shows.
+fn debug_path(dir: &Path, input: &Path, suffix: &str) -> anyhow::Result<PathBuf> {
+    fs::create_dir_all(dir).context("Could not create debug directory")?;
+    let stem = input.file_stem().unwrap_or_default().to_string_lossy();
+    Ok(dir.join(format!("{}.{}.png", stem, suffix)))
+}
+
+/// Save the black and white image that detection searches.
+///
+/// # Errors
+///
+/// An error is returned if the image cannot be saved.
+pub fn save_threshold(dir: &Path, input: &Path, threshold: &GrayImage) -> anyhow::Result<()> {
+    threshold
+        .save(debug_path(dir, input, "threshold")?)
+        .context("Could not save threshold image")
+}
+
+/// Save a copy of the screenshot with the detected corners and edges of each photo drawn on it.
+///
+/// # Errors
+///
+/// An error is returned if the image cannot be saved.
+pub fn save_corners(
+    dir: &Path,
+    input: &Path,
+    img: &RgbImage,
+    quads: &[Quad],
+) -> anyhow::Result<()> {
+    let mut img = img.clone();
+    // Thin lines disappear when large screenshots are viewed at a reduced size.
+    let thickness = std::cmp::max(img.width(), img.height()) as i32 / 1000 + 1;
+    let radius = thickness * 8;
+    for quad in quads {
+        for i in 0..4 {
+            let (x0, y0) = quad[i];
+            let (x1, y1) = quad[(i + 1) % 4];
+            for offset in -thickness / 2..=thickness / 2 {
+                let offset = offset as f32;
+                draw_line_segment_mut(&mut img, (x0 + offset, y0), (x1 + offset, y1), COLOR);
+                draw_line_segment_mut(&mut img, (x0, y0 + offset), (x1, y1 + offset), COLOR);
+            }
+            for r in radius..radius + thickness {
+                draw_hollow_circle_mut(&mut img, (x0.round() as i32, y0.round() as i32), r, COLOR);
+            }
+        }
+    }
+    img.save(debug_path(dir, input, "corners")?)
+        .context("Could not save corners image")
+}
@@ -0,0 +1,161 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context};
+use qdcrop::{detect_scaled, CropOptions, Detection, Quad};
+use rayon::prelude::*;
+use serde_json::{json, Value};
+
+use crate::{annotations, open};
+
+/// The photos found in one screenshot.
+struct Found {
+    /// The size of the screenshot.
+    size: (u32, u32),
+    /// The photos, most confident first.
+    detections: Vec<Detection>,
+}
+
+/// Find the photos in one screenshot without warping them.
+fn detect_file(input: &Path, options: &CropOptions) -> anyhow::Result<Found> {
+    let (img, _) = open(input, options)?;
+    Ok(Found {
+        size: img.dimensions(),
+        detections: detect_scaled(&img, options)?,
+    })
+}
+
+/// The photos found in a screenshot as JSON, each with its `corners` clockwise from the top
+/// left, its `confidence`, and the `detector` that found it.
+pub fn photos(detections: &[Detection]) -> Value {
+    detections
+        .iter()
+        .map(|detection| {
+            json!({
+                "corners": detection.corners.map(|p| [p.0, p.1]),
+                "confidence": detection.confidence,
+                "detector": detection.detector,
+            })
+        })
+        .collect()
+}
+
+/// Find the photos in screenshots and print their corners, without writing any pictures.
+///
+/// Each screenshot is printed as one line of JSON, with its path as `input`, its size as
+/// `size`, and the photos in it as `photos`, as returned by `photos`. Screenshots that fail are
+/// printed with an `error` instead, with the `kind` of error from `qdcrop::Error::kind` and its
+/// `message`.
+///
+/// Returns the number of screenshots that could not be read.
+pub fn run(inputs: &[PathBuf], options: &CropOptions) -> usize {
+    let results: Vec<_> = inputs
+        .par_iter()
+        .map(|input| (input, detect_file(input, options)))
+        .collect();
+    let mut failed = 0;
+    for (input, result) in results {
+        match result {
+            Ok(found) => {
+                let line = json!({
+                    "input": input.to_string_lossy(),
+                    "size": [found.size.0, found.size.1],
+                    "photos": photos(&found.detections),
+                });
+                println!("{}", line);
+            }
+            Err(error) => {
+                eprintln!(
+                    "Error while detecting {}: {}",
+                    input.to_string_lossy(),
+                    error
+                );
+                let kind = error
+                    .downcast_ref::<qdcrop::Error>()
+                    .map_or("other", qdcrop::Error::kind);
+                let line = json!({
+                    "input": input.to_string_lossy(),
+                    "error": {
+                        "kind": kind,
+                        "message": format!("{:#}", error),
+                    },
+                });
+                println!("{}", line);
+                failed += 1;
+            }
+        }
+    }
+    failed
+}
+
+/// Read the photos of one screenshot as printed by `run`.
+///
+/// Returns `None` for screenshots that failed.
+fn import_line(json: &Value) -> anyhow::Result<Option<(PathBuf, Vec<Quad>)>> {
+    if json.get("error").is_some() {
+        return Ok(None);
+    }
+    let input = json["input"].as_str().context("Missing input")?;
+    let mut quads = Vec::new();
+    for photo in json["photos"].as_array().context("Missing photos")? {
+        let points = photo["corners"]
+            .as_array()
+            .and_then(|corners| {
+                corners
+                    .iter()
+                    .map(|p| Some((p[0].as_f64()? as f32, p[1].as_f64()? as f32)))
+                    .collect::<Option<Vec<_>>>()
+            })
+            .with_context(|| format!("Invalid corners for {}", input))?;
+        match points.try_into() {
+            Ok(quad) => quads.push(quad),
+            Err(points) => bail!(
+                "Expected 4 corners for {}, but found {}",
+                input,
+                points.len()
+            ),
+        }
+    }
+    Ok(Some((annotations::key(Path::new(input)), quads)))
+}
+
+/// Read corners printed by `run`, which may have been edited.
+///
+/// # Arguments
+///
+/// * `path` - A file with one screenshot on each line, or with a JSON array of screenshots, or
+///   `-` to read standard input.
+///
+/// Returns the corners of the photos in each screenshot, keyed by `annotations::key`.
+///
+/// # Errors
+///
+/// An error is returned if the file cannot be read or a photo does not have four corners.
+pub fn import(path: &Path) -> anyhow::Result<HashMap<PathBuf, Vec<Quad>>> {
+    let text = if path == Path::new("-") {
+        let mut text = String::new();
+        io::stdin()
+            .read_to_string(&mut text)
+            .context("Could not read corners from standard input")?;
+        text
+    } else {
+        fs::read_to_string(path)
+            .with_context(|| format!("Could not read {}", path.to_string_lossy()))?
+    };
+    let lines: Vec<Value> = match serde_json::from_str(&text) {
+        Ok(Value::Array(lines)) => lines,
+        _ => text
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| serde_json::from_str(line).context("Invalid JSON in corners"))
+            .collect::<anyhow::Result<_>>()?,
+    };
+    lines
+        .iter()
+        .filter_map(|line| import_line(line).transpose())
+        .collect()
+}
@@ -0,0 +1,88 @@
+use std::str::FromStr;
+
+use anyhow::anyhow;
+use image::{Rgb, RgbImage};
+
+/// Radial lens distortion, in the model used by OpenCV and most camera calibration tools.
+///
+/// A point at distance `r` from the center of the picture, measured in half diagonals, appears at
+/// `r * (1 + k1 * r^2 + k2 * r^4)`. Barrel distortion has a negative `k1`, and pincushion
+/// distortion a positive one.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Distortion {
+    pub k1: f32,
+    pub k2: f32,
+}
+
+impl FromStr for Distortion {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            anyhow!(
+                "Invalid distortion {}. Expected k1 or k1,k2, like -0.05,0.01",
+                s
+            )
+        };
+        let mut values = s.split(',').map(|v| v.trim().parse::<f32>());
+        let k1 = values.next().ok_or_else(invalid)?.map_err(|_| invalid())?;
+        let k2 = values
+            .next()
+            .transpose()
+            .map_err(|_| invalid())?
+            .unwrap_or(0.0);
+        if values.next().is_some() {
+            return Err(invalid());
+        }
+        Ok(Distortion { k1, k2 })
+    }
+}
+
+/// Sample an image between pixels, blending the four nearest ones.
+///
+/// Points outside the image are black.
+fn bilinear(img: &RgbImage, x: f32, y: f32) -> Rgb<u8> {
+    let (width, height) = img.dimensions();
+    if !(-0.5..width as f32 - 0.5).contains(&x) || !(-0.5..height as f32 - 0.5).contains(&y) {
+        return Rgb([0, 0, 0]);
+    }
+    let (x, y) = (x.max(0.0), y.max(0.0));
+    let (x0, y0) = (x as u32, y as u32);
+    let (x1, y1) = ((x0 + 1).min(width - 1), (y0 + 1).min(height - 1));
+    let (fx, fy) = (x - x0 as f32, y - y0 as f32);
+    let mut pixel = [0; 3];
+    for (c, value) in pixel.iter_mut().enumerate() {
+        let top =
+            img.get_pixel(x0, y0).0[c] as f32 * (1.0 - fx) + img.get_pixel(x1, y0).0[c] as f32 * fx;
+        let bottom =
+            img.get_pixel(x0, y1).0[c] as f32 * (1.0 - fx) + img.get_pixel(x1, y1).0[c] as f32 * fx;
+        *value = (top * (1.0 - fy) + bottom * fy).round() as u8;
+    }
+    Rgb(pixel)
+}
+
+/// Straighten the lines bent by a lens, so that the edges of photos are straight again.
+///
+/// Each pixel of the result is sampled from where the lens moved it, so this runs before corners
+/// are detected and the photo is warped.
+pub fn undistort(img: &RgbImage, distortion: Distortion) -> RgbImage {
+    let (width, height) = img.dimensions();
+    let center = (width as f32 / 2.0, height as f32 / 2.0);
+    let radius = center.0.hypot(center.1);
+    let mut out_img = crate::pool::rgb(width, height);
+    crate::for_each_row(&mut out_img, |y, row| {
+        let dy = (y as f32 + 0.5 - center.1) / radius;
+        for (x, pixel) in row.chunks_exact_mut(3).enumerate() {
+            let dx = (x as f32 + 0.5 - center.0) / radius;
+            let r2 = dx * dx + dy * dy;
+            let factor = 1.0 + distortion.k1 * r2 + distortion.k2 * r2 * r2;
+            let sample = bilinear(
+                img,
+                center.0 + dx * factor * radius - 0.5,
+                center.1 + dy * factor * radius - 0.5,
+            );
+            pixel.copy_from_slice(&sample.0);
+        }
+    });
+    out_img
+}
@@ -0,0 +1,370 @@
+use image::GrayImage;
+
+use crate::Quad;
+
+/// How many points are sampled along each side.
+const SAMPLES: u32 = 64;
+/// The fraction of each side left out at both ends, where occluding objects usually are.
+const TRIM: f32 = 0.15;
+/// How far past the corners to search for the border, as a fraction of the screenshot size.
+const WINDOW: f32 = 0.03;
+/// The smallest search distance past the corners, in pixels.
+const MIN_WINDOW: f32 = 16.0;
+/// The fewest samples needed to trust a fitted line.
+const MIN_POINTS: usize = 8;
+/// Samples further than this from a fitted line are dropped before it is fitted again, in pixels.
+const OUTLIER_DISTANCE: f32 = 2.0;
+/// How many times a line is fitted again without its outliers.
+const REFITS: usize = 3;
+/// How many random pairs of points are tried when fitting a line with RANSAC.
+const RANSAC_ITERATIONS: usize = 256;
+/// Points closer than this to a line tried by RANSAC count as being on it, in pixels.
+const INLIER_DISTANCE: f32 = 1.5;
+/// How far a corner may be from the intersection of its sides before it is replaced, in pixels.
+const TOLERANCE: f32 = 3.0;
+/// The most the photo is turned to level it with its border, in degrees.
+const MAX_STRAIGHTEN: f32 = 2.0;
+
+/// A line written as `across = slope * along + offset`.
+///
+/// `along` is x and `across` is y for the top and bottom sides, and the other way around for
+/// the left and right sides, so that no side is close to vertical.
+#[derive(Clone, Copy, Debug)]
+struct Line {
+    slope: f32,
+    offset: f32,
+}
+
+impl Line {
+    /// Fit a line to points by least squares.
+    fn fit(points: &[(f32, f32)]) -> Option<Line> {
+        let n = points.len() as f32;
+        let (sum_a, sum_c) = points
+            .iter()
+            .fold((0.0, 0.0), |(sa, sc), (a, c)| (sa + a, sc + c));
+        let (mean_a, mean_c) = (sum_a / n, sum_c / n);
+        let (covariance, variance) = points.iter().fold((0.0, 0.0), |(cv, v), (a, c)| {
+            (
+                cv + (a - mean_a) * (c - mean_c),
+                v + (a - mean_a) * (a - mean_a),
+            )
+        });
+        if variance <= f32::EPSILON {
+            return None;
+        }
+        let slope = covariance / variance;
+        Some(Line {
+            slope,
+            offset: mean_c - slope * mean_a,
+        })
+    }
+
+    /// Fit a line, then fit it again without points that are far from it.
+    fn fit_robust(points: &[(f32, f32)]) -> Option<Line> {
+        let mut points = points.to_vec();
+        let mut line = Line::fit(&points)?;
+        for _ in 0..REFITS {
+            let inliers: Vec<_> = points
+                .iter()
+                .copied()
+                .filter(|p| line.distance(*p) <= OUTLIER_DISTANCE)
+                .collect();
+            if inliers.len() < MIN_POINTS || inliers.len() == points.len() {
+                break;
+            }
+            points = inliers;
+            line = Line::fit(&points)?;
+        }
+        Some(line)
+    }
+
+    /// The line through two points, if they are not on top of each other.
+    fn through(a: (f32, f32), b: (f32, f32)) -> Option<Line> {
+        if (b.0 - a.0).abs() <= f32::EPSILON {
+            return None;
+        }
+        let slope = (b.1 - a.1) / (b.0 - a.0);
+        Some(Line {
+            slope,
+            offset: a.1 - slope * a.0,
+        })
+    }
+
+    /// Fit a line with RANSAC.
+    ///
+    /// Lines through random pairs of points are tried, and the one with the most points near it
+    /// is fitted again to only those points by least squares. Unlike `fit_robust`, this is not
+    /// pulled off course when most of the points are noise.
+    fn fit_ransac(points: &[(f32, f32)]) -> Option<Line> {
+        if points.len() < MIN_POINTS {
+            return None;
+        }
+        let mut random = XorShift(0x9e37_79b9_7f4a_7c15);
+        let mut best: Option<(usize, Line)> = None;
+        for _ in 0..RANSAC_ITERATIONS {
+            let a = points[random.below(points.len())];
+            let b = points[random.below(points.len())];
+            let Some(line) = Line::through(a, b) else {
+                continue;
+            };
+            let inliers = points
+                .iter()
+                .filter(|p| line.distance(**p) <= INLIER_DISTANCE)
+                .count();
+            if inliers > best.map_or(0, |(count, _)| count) {
+                best = Some((inliers, line));
+            }
+        }
+        let (_, line) = best?;
+        let inliers: Vec<_> = points
+            .iter()
+            .copied()
+            .filter(|p| line.distance(*p) <= INLIER_DISTANCE)
+            .collect();
+        if inliers.len() < MIN_POINTS {
+            return None;
+        }
+        Line::fit(&inliers)
+    }
+
+    /// The distance from a point to the line.
+    fn distance(self, (along, across): (f32, f32)) -> f32 {
+        (self.slope * along + self.offset - across).abs() / self.slope.hypot(1.0)
+    }
+}
+
+/// A small pseudorandom number generator, so that RANSAC finds the same lines every run.
+struct XorShift(u64);
+
+impl XorShift {
+    /// A number from 0 up to, but not including, `n`.
+    fn below(&mut self, n: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % n as u64) as usize
+    }
+}
+
+/// Find border pixels along one side of a quad.
+///
+/// Each sample searches across the side from the outside in and keeps the first border pixel.
+/// Returns points as `(along, across)`.
+fn sample_side(threshold: &GrayImage, quad: &Quad, side: usize) -> Vec<(f32, f32)> {
+    let (width, height) = threshold.dimensions();
+    let horizontal = side % 2 == 0;
+    let swap = |(x, y): (f32, f32)| if horizontal { (x, y) } else { (y, x) };
+    let (start, end) = (swap(quad[side]), swap(quad[(side + 1) % 4]));
+    let (along_limit, across_limit) = if horizontal {
+        (width, height)
+    } else {
+        (height, width)
+    };
+    let window = f32::max(across_limit as f32 * WINDOW, MIN_WINDOW);
+    let low = (start.1.min(end.1) - window).max(0.0) as u32;
+    let high = ((start.1.max(end.1) + window).max(0.0) as u32).min(across_limit - 1);
+    // The top and left sides are searched from low coordinates, the others from high ones.
+    let from_low = side == 0 || side == 3;
+    let is_border = |along: u32, across: u32| {
+        let (x, y) = if horizontal {
+            (along, across)
+        } else {
+            (across, along)
+        };
+        threshold.get_pixel(x, y).0[0] == 0
+    };
+
+    let mut points = Vec::new();
+    for s in 0..SAMPLES {
+        let t = TRIM + (1.0 - 2.0 * TRIM) * s as f32 / (SAMPLES - 1) as f32;
+        let along = (start.0 + (end.0 - start.0) * t).round();
+        if along < 0.0 || along >= along_limit as f32 {
+            continue;
+        }
+        let along = along as u32;
+        let hit = if from_low {
+            (low..=high).find(|across| is_border(along, *across))
+        } else {
+            (low..=high).rev().find(|across| is_border(along, *across))
+        };
+        if let Some(across) = hit {
+            points.push((along as f32, across as f32));
+        }
+    }
+    points
+}
+
+/// Find the outermost border pixel in every row or column, seen from one side of the screenshot.
+///
+/// `side` is 0 for the top, then clockwise. Returns points as `(along, across)`, like
+/// `sample_side`.
+fn outline(threshold: &GrayImage, side: usize) -> Vec<(f32, f32)> {
+    let (width, height) = threshold.dimensions();
+    let horizontal = side % 2 == 0;
+    let (along_limit, across_limit) = if horizontal {
+        (width, height)
+    } else {
+        (height, width)
+    };
+    let from_low = side == 0 || side == 3;
+    let is_border = |along: u32, across: u32| {
+        let (x, y) = if horizontal {
+            (along, across)
+        } else {
+            (across, along)
+        };
+        threshold.get_pixel(x, y).0[0] == 0
+    };
+
+    (0..along_limit)
+        .filter_map(|along| {
+            let hit = if from_low {
+                (0..across_limit).find(|across| is_border(along, *across))
+            } else {
+                (0..across_limit)
+                    .rev()
+                    .find(|across| is_border(along, *across))
+            };
+            hit.map(|across| (along as f32, across as f32))
+        })
+        .collect()
+}
+
+/// Find where a horizontal side and a vertical side meet.
+fn intersect(horizontal: Line, vertical: Line) -> Option<(f32, f32)> {
+    // y = h.slope * x + h.offset and x = v.slope * y + v.offset.
+    let det = 1.0 - horizontal.slope * vertical.slope;
+    if det.abs() <= f32::EPSILON {
+        return None;
+    }
+    let x = (vertical.slope * horizontal.offset + vertical.offset) / det;
+    Some((x, horizontal.slope * x + horizontal.offset))
+}
+
+/// Correct corners that are hidden behind something in front of the photo or rounded off.
+///
+/// A straight line is fitted to the border pixels along the middle of each side, and any
+/// corner that is not where its two sides meet is moved there. This fixes corners where an
+/// avatar or UI element covers the photo and the nearest border pixel is on that object instead,
+/// and corners of rounded frames, where the nearest border pixel is on the arc inside the photo.
+///
+/// If a side cannot be fitted, `quad` is returned unchanged.
+pub fn infer_corners(threshold: &GrayImage, quad: &Quad) -> Quad {
+    let mut lines = [None; 4];
+    for (side, line) in lines.iter_mut().enumerate() {
+        let points = sample_side(threshold, quad, side);
+        if points.len() < MIN_POINTS {
+            return *quad;
+        }
+        *line = Line::fit_robust(&points);
+    }
+    let [Some(top), Some(right), Some(bottom), Some(left)] = lines else {
+        return *quad;
+    };
+
+    let corners = [
+        intersect(top, left),
+        intersect(top, right),
+        intersect(bottom, right),
+        intersect(bottom, left),
+    ];
+    let mut refined = *quad;
+    for (corner, intersection) in refined.iter_mut().zip(corners) {
+        if let Some((x, y)) = intersection {
+            if (corner.0 - x).hypot(corner.1 - y) > TOLERANCE {
+                *corner = (x, y);
+            }
+        }
+    }
+    refined
+}
+
+/// The angle of each side of a quad, as how far it is turned clockwise from level, in radians.
+///
+/// The left and right sides are level when they are vertical.
+fn side_angles(quad: &Quad) -> [f32; 4] {
+    let [a, b, c, d] = *quad;
+    [
+        (b.1 - a.1).atan2(b.0 - a.0),
+        -(c.0 - b.0).atan2(c.1 - b.1),
+        (c.1 - d.1).atan2(c.0 - d.0),
+        -(d.0 - a.0).atan2(d.1 - a.1),
+    ]
+}
+
+/// Turn corners so that the photo is as level as the border around it.
+///
+/// A straight line is fitted to the border pixels along each side, and the photo is turned by
+/// the median difference between the angles of those lines and the angles of the sides of
+/// `quad`. Corners found on single pixels are often off by a pixel or two, which tilts the
+/// output a little even when the photo in the screenshot is level.
+///
+/// If fewer than two sides can be fitted, or the lines disagree with the corners by more than
+/// `MAX_STRAIGHTEN`, `quad` is returned unchanged.
+pub fn straighten(threshold: &GrayImage, quad: &Quad) -> Quad {
+    let angles = side_angles(quad);
+    let mut differences: Vec<f32> = (0..4)
+        .filter_map(|side| {
+            let points = sample_side(threshold, quad, side);
+            if points.len() < MIN_POINTS {
+                return None;
+            }
+            let line = Line::fit_robust(&points)?;
+            let angle = match side {
+                0 | 2 => line.slope.atan(),
+                _ => -line.slope.atan(),
+            };
+            Some(angle - angles[side])
+        })
+        .collect();
+    if differences.len() < 2 {
+        return *quad;
+    }
+    differences.sort_by(f32::total_cmp);
+    let middle = differences.len() / 2;
+    let turn = if differences.len() % 2 == 0 {
+        (differences[middle - 1] + differences[middle]) / 2.0
+    } else {
+        differences[middle]
+    };
+    if turn.abs() > MAX_STRAIGHTEN.to_radians() {
+        return *quad;
+    }
+
+    let center = (
+        quad.iter().map(|p| p.0).sum::<f32>() / 4.0,
+        quad.iter().map(|p| p.1).sum::<f32>() / 4.0,
+    );
+    let (sin, cos) = turn.sin_cos();
+    quad.map(|(x, y)| {
+        let (dx, dy) = (x - center.0, y - center.1);
+        (
+            center.0 + dx * cos - dy * sin,
+            center.1 + dx * sin + dy * cos,
+        )
+    })
+}
+
+/// Find the photo by fitting a line to each side of the border with RANSAC.
+///
+/// The outermost border pixel in every row and column is found from each side of the
+/// screenshot, and a line is fitted to each side's pixels. Isolated dark specks, such as
+/// compression noise or avatar shadows, are far from the line most pixels agree on and are
+/// ignored, where the nearest detector would put a corner on them.
+///
+/// Returns `None` if a side does not have enough pixels on one line.
+pub fn ransac(threshold: &GrayImage) -> Option<Quad> {
+    let mut lines = [None; 4];
+    for (side, line) in lines.iter_mut().enumerate() {
+        *line = Line::fit_ransac(&outline(threshold, side));
+    }
+    let [Some(top), Some(right), Some(bottom), Some(left)] = lines else {
+        return None;
+    };
+    Some([
+        intersect(top, left)?,
+        intersect(top, right)?,
+        intersect(bottom, right)?,
+        intersect(bottom, left)?,
+    ])
+}
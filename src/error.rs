@@ -0,0 +1,57 @@
+use std::io;
+
+/// Why cropping a screenshot failed.
+///
+/// Errors from the steps inside each category, such as which detector failed, are kept as the
+/// message of the category.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// No photo could be found in the screenshot.
+    #[error("{0}")]
+    DetectionFailed(anyhow::Error),
+    /// A photo was found, but with less confidence than `min_confidence`.
+    #[error("Detection confidence {confidence:.2} is below {min_confidence:.2}")]
+    LowConfidence {
+        confidence: f64,
+        min_confidence: f64,
+    },
+    /// The corners of a photo do not form a shape that can be warped, such as when three of them
+    /// are on a line.
+    #[error("{0}")]
+    DegenerateQuad(anyhow::Error),
+    /// Warping failed, such as when the graphics card is lost.
+    #[error("{0}")]
+    WarpFailed(anyhow::Error),
+    /// The text in a photo could not be read to turn it upright.
+    #[error("{0}")]
+    AutoRotateFailed(anyhow::Error),
+    /// A screenshot is not an image that can be read.
+    #[error("Could not decode input")]
+    DecodeError(#[source] image::ImageError),
+    /// The WebP encoder failed.
+    #[error("Could not encode output: {0}")]
+    EncodeError(String),
+    /// A setting is out of range.
+    #[error("{0}")]
+    InvalidOptions(String),
+    /// A file could not be read or written.
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+impl Error {
+    /// A short name for the category, such as `detection_failed`, for reports.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::DetectionFailed(_) => "detection_failed",
+            Error::LowConfidence { .. } => "low_confidence",
+            Error::DegenerateQuad(_) => "degenerate_quad",
+            Error::WarpFailed(_) => "warp_failed",
+            Error::AutoRotateFailed(_) => "auto_rotate_failed",
+            Error::DecodeError(_) => "decode_error",
+            Error::EncodeError(_) => "encode_error",
+            Error::InvalidOptions(_) => "invalid_options",
+            Error::Io(_) => "io",
+        }
+    }
+}
@@ -0,0 +1,340 @@
+/// A value of an EXIF field.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    /// Text. The terminating NUL is added when written.
+    Ascii(String),
+    /// Bytes with a meaning specific to the tag.
+    Undefined(Vec<u8>),
+    Long(u32),
+}
+
+impl Value {
+    /// The TIFF field type.
+    fn kind(&self) -> u16 {
+        match self {
+            Value::Ascii(_) => 2,
+            Value::Undefined(_) => 7,
+            Value::Long(_) => 4,
+        }
+    }
+
+    /// The number of items of `kind` in the value.
+    fn count(&self) -> u32 {
+        match self {
+            Value::Ascii(s) => s.len() as u32 + 1,
+            Value::Undefined(b) => b.len() as u32,
+            Value::Long(_) => 1,
+        }
+    }
+
+    /// The value as little endian bytes.
+    fn bytes(&self) -> Vec<u8> {
+        match self {
+            Value::Ascii(s) => {
+                let mut bytes = s.as_bytes().to_vec();
+                bytes.push(0);
+                bytes
+            }
+            Value::Undefined(b) => b.clone(),
+            Value::Long(v) => v.to_le_bytes().to_vec(),
+        }
+    }
+}
+
+/// Tags used by qdcrop.
+pub mod tag {
+    /// IFD0: Which way the image is turned or mirrored.
+    pub const ORIENTATION: u16 = 0x0112;
+    /// IFD0: A title or description of the image.
+    pub const IMAGE_DESCRIPTION: u16 = 0x010e;
+    /// IFD0: The person who made the image.
+    pub const ARTIST: u16 = 0x013b;
+    /// IFD0: The copyright notice of the image.
+    pub const COPYRIGHT: u16 = 0x8298;
+    /// IFD0: The software that produced the image.
+    pub const SOFTWARE: u16 = 0x0131;
+    /// IFD0: The offset of the EXIF IFD. This is managed by `Exif::to_tiff`.
+    pub const EXIF_IFD: u16 = 0x8769;
+    /// EXIF IFD: When the photo was taken, as `YYYY:MM:DD HH:MM:SS`.
+    pub const DATE_TIME_ORIGINAL: u16 = 0x9003;
+    /// EXIF IFD: Data specific to the software that produced the image.
+    pub const MAKER_NOTE: u16 = 0x927c;
+
+    /// Text fields of the primary image IFD that are copied from screenshots to outputs.
+    pub const COPIED_IFD0: &[u16] = &[
+        0x010e, // ImageDescription
+        0x010f, // Make
+        0x0110, // Model
+        0x0132, // DateTime
+        0x013b, // Artist
+        0x8298, // Copyright
+    ];
+    /// Text fields of the EXIF IFD that are copied from screenshots to outputs.
+    pub const COPIED_EXIF: &[u16] = &[
+        0x9003, // DateTimeOriginal
+        0x9004, // DateTimeDigitized
+        0x9010, // OffsetTime
+        0x9011, // OffsetTimeOriginal
+        0x9012, // OffsetTimeDigitized
+    ];
+}
+
+/// A minimal EXIF block with the primary image IFD and the EXIF IFD.
+#[derive(Clone, Debug, Default)]
+pub struct Exif {
+    pub ifd0: Vec<(u16, Value)>,
+    pub exif: Vec<(u16, Value)>,
+}
+
+/// Set a field in an IFD, replacing any existing value.
+fn set(ifd: &mut Vec<(u16, Value)>, tag: u16, value: Value) {
+    match ifd.iter_mut().find(|(t, _)| *t == tag) {
+        Some(entry) => entry.1 = value,
+        None => ifd.push((tag, value)),
+    }
+}
+
+/// Append an IFD to `out`.
+///
+/// Offsets in TIFF are relative to the start of the TIFF header, which is the start of `out`.
+/// Returns the position of the value of `pointer_tag` so it can be patched, if it is present.
+fn write_ifd(out: &mut Vec<u8>, entries: &[(u16, Value)], pointer_tag: u16) -> Option<usize> {
+    let mut entries: Vec<_> = entries.iter().collect();
+    entries.sort_by_key(|(tag, _)| *tag);
+
+    let data_start = out.len() + 2 + 12 * entries.len() + 4;
+    let mut data = Vec::new();
+    let mut pointer = None;
+    out.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+    for (tag, value) in entries {
+        out.extend_from_slice(&tag.to_le_bytes());
+        out.extend_from_slice(&value.kind().to_le_bytes());
+        out.extend_from_slice(&value.count().to_le_bytes());
+        if *tag == pointer_tag {
+            pointer = Some(out.len());
+        }
+        let mut bytes = value.bytes();
+        if bytes.len() <= 4 {
+            bytes.resize(4, 0);
+            out.extend_from_slice(&bytes);
+        } else {
+            let offset = (data_start + data.len()) as u32;
+            out.extend_from_slice(&offset.to_le_bytes());
+            data.extend_from_slice(&bytes);
+            if data.len() % 2 == 1 {
+                data.push(0);
+            }
+        }
+    }
+    // There is no next IFD.
+    out.extend_from_slice(&0u32.to_le_bytes());
+    out.extend_from_slice(&data);
+    pointer
+}
+
+impl Exif {
+    /// Read the fields of an EXIF block that still describe a cropped photo: when it was
+    /// taken, by what, and by whom.
+    ///
+    /// Fields that describe the pixels, like the orientation, are left out. An invalid block
+    /// gives an empty `Exif`.
+    pub fn read(tiff: &[u8]) -> Self {
+        let mut exif = Exif::default();
+        let Some(reader) = Reader::new(tiff) else {
+            return exif;
+        };
+        let copy = |ifd: &mut Vec<(u16, Value)>, offset: usize, tags: &[u16]| {
+            for (tag, kind, count, position) in reader.entries(offset) {
+                if kind == 2 && tags.contains(&tag) {
+                    if let Some(text) = reader.ascii(count, position) {
+                        set(ifd, tag, Value::Ascii(text));
+                    }
+                }
+            }
+        };
+        let Some(ifd0) = reader.u32_at(4) else {
+            return exif;
+        };
+        copy(&mut exif.ifd0, ifd0 as usize, tag::COPIED_IFD0);
+        let exif_ifd = reader
+            .entries(ifd0 as usize)
+            .into_iter()
+            .find(|(tag, ..)| *tag == tag::EXIF_IFD)
+            .and_then(|(.., position)| reader.u32_at(position));
+        if let Some(offset) = exif_ifd {
+            copy(&mut exif.exif, offset as usize, tag::COPIED_EXIF);
+        }
+        exif
+    }
+
+    /// Check whether the primary image IFD has a field.
+    pub fn has_ifd0(&self, tag: u16) -> bool {
+        self.ifd0.iter().any(|(t, _)| *t == tag)
+    }
+
+    /// Check whether the EXIF IFD has a field.
+    pub fn has_exif(&self, tag: u16) -> bool {
+        self.exif.iter().any(|(t, _)| *t == tag)
+    }
+
+    /// Whether there are no fields.
+    pub fn is_empty(&self) -> bool {
+        self.ifd0.is_empty() && self.exif.is_empty()
+    }
+
+    /// Set a field in the primary image IFD.
+    pub fn set_ifd0(&mut self, tag: u16, value: Value) {
+        set(&mut self.ifd0, tag, value);
+    }
+
+    /// Set a field in the EXIF IFD.
+    pub fn set_exif(&mut self, tag: u16, value: Value) {
+        set(&mut self.exif, tag, value);
+    }
+
+    /// Encode the fields as little endian TIFF, as used by the WebP EXIF chunk.
+    pub fn to_tiff(&self) -> Vec<u8> {
+        let mut ifd0 = self.ifd0.clone();
+        ifd0.retain(|(tag, _)| *tag != tag::EXIF_IFD);
+        if !self.exif.is_empty() {
+            ifd0.push((tag::EXIF_IFD, Value::Long(0)));
+        }
+
+        let mut out = b"II*\0".to_vec();
+        out.extend_from_slice(&8u32.to_le_bytes());
+        let pointer = write_ifd(&mut out, &ifd0, tag::EXIF_IFD);
+        if let Some(pointer) = pointer {
+            let offset = (out.len() as u32).to_le_bytes();
+            out[pointer..pointer + 4].copy_from_slice(&offset);
+            write_ifd(&mut out, &self.exif, 0);
+        }
+        out
+    }
+}
+
+/// Find the EXIF block in a JPEG, PNG, or WebP file.
+///
+/// Returns the block as TIFF, or `None` if the file has no EXIF or is another format.
+pub fn find(file: &[u8]) -> Option<&[u8]> {
+    const EXIF_HEADER: &[u8] = b"Exif\0\0";
+    if let Some(mut rest) = file.strip_prefix(b"\xff\xd8") {
+        // JPEG segments up to the start of the image data.
+        while rest.len() >= 4 && rest[0] == 0xff && rest[1] != 0xda {
+            let length = u16::from_be_bytes([rest[2], rest[3]]) as usize;
+            let segment = rest.get(4..2 + length)?;
+            if rest[1] == 0xe1 {
+                if let Some(tiff) = segment.strip_prefix(EXIF_HEADER) {
+                    return Some(tiff);
+                }
+            }
+            rest = &rest[2 + length..];
+        }
+        None
+    } else if let Some(mut rest) = file.strip_prefix(b"\x89PNG\r\n\x1a\n") {
+        while rest.len() >= 12 {
+            let length = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+            let data = rest.get(8..8 + length)?;
+            if &rest[4..8] == b"eXIf" {
+                return Some(data);
+            }
+            rest = rest.get(12 + length..)?;
+        }
+        None
+    } else if file.len() >= 12 && &file[..4] == b"RIFF" && &file[8..12] == b"WEBP" {
+        let mut rest = &file[12..];
+        while rest.len() >= 8 {
+            let length = u32::from_le_bytes(rest[4..8].try_into().unwrap()) as usize;
+            let data = rest.get(8..8 + length)?;
+            if &rest[..4] == b"EXIF" {
+                // Some writers keep the JPEG header.
+                return Some(data.strip_prefix(EXIF_HEADER).unwrap_or(data));
+            }
+            rest = rest.get(8 + length + length % 2..).unwrap_or_default();
+        }
+        None
+    } else {
+        None
+    }
+}
+
+/// A TIFF block being read.
+struct Reader<'a> {
+    data: &'a [u8],
+    little_endian: bool,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Option<Self> {
+        let little_endian = match data.get(..4)? {
+            b"II*\0" => true,
+            b"MM\0*" => false,
+            _ => return None,
+        };
+        Some(Reader {
+            data,
+            little_endian,
+        })
+    }
+
+    fn u16_at(&self, at: usize) -> Option<u16> {
+        let bytes = self.data.get(at..at + 2)?.try_into().ok()?;
+        Some(if self.little_endian {
+            u16::from_le_bytes(bytes)
+        } else {
+            u16::from_be_bytes(bytes)
+        })
+    }
+
+    fn u32_at(&self, at: usize) -> Option<u32> {
+        let bytes = self.data.get(at..at + 4)?.try_into().ok()?;
+        Some(if self.little_endian {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        })
+    }
+
+    /// The entries of the IFD at `offset`, as their tags, types, counts, and the positions of
+    /// their values or value offsets.
+    fn entries(&self, offset: usize) -> Vec<(u16, u16, u32, usize)> {
+        let count = self.u16_at(offset).unwrap_or(0) as usize;
+        (0..count)
+            .map_while(|i| {
+                let entry = offset + 2 + 12 * i;
+                Some((
+                    self.u16_at(entry)?,
+                    self.u16_at(entry + 2)?,
+                    self.u32_at(entry + 4)?,
+                    entry + 8,
+                ))
+            })
+            .collect()
+    }
+
+    /// Read a text value, without its terminating NUL.
+    fn ascii(&self, count: u32, position: usize) -> Option<String> {
+        let count = count as usize;
+        let start = if count <= 4 {
+            position
+        } else {
+            self.u32_at(position)? as usize
+        };
+        let bytes = self.data.get(start..start + count)?;
+        let bytes = bytes.split(|b| *b == 0).next().unwrap_or_default();
+        Some(String::from_utf8_lossy(bytes).into_owned())
+    }
+}
+
+/// Read the orientation of an image from its EXIF block.
+///
+/// Returns the value of the orientation tag, from 1 to 8, or `None` if it is missing or the
+/// block is not valid TIFF.
+pub fn orientation(tiff: &[u8]) -> Option<u16> {
+    let reader = Reader::new(tiff)?;
+    reader
+        .entries(reader.u32_at(4)? as usize)
+        .into_iter()
+        .find(|(tag, ..)| *tag == tag::ORIENTATION)
+        .and_then(|(.., position)| reader.u16_at(position))
+        .filter(|orientation| (1..=8).contains(orientation))
+}
@@ -0,0 +1,120 @@
+use anyhow::bail;
+
+/// An optional part of qdcrop that is chosen when it is built.
+#[derive(Debug)]
+pub struct Feature {
+    /// The Cargo feature that enables it.
+    pub name: &'static str,
+    /// What the feature does, for error messages.
+    pub description: &'static str,
+    /// Whether this build includes it.
+    pub enabled: bool,
+}
+
+/// Warping on the graphics card.
+pub static GPU: Feature = Feature {
+    name: "gpu",
+    description: "GPU warping",
+    enabled: cfg!(feature = "gpu"),
+};
+
+/// Corner detection with a machine learning model.
+pub static ML: Feature = Feature {
+    name: "ml",
+    description: "the ONNX model detector",
+    enabled: cfg!(feature = "ml"),
+};
+
+/// Finding the orientation of text in photos.
+pub static OCR: Feature = Feature {
+    name: "ocr",
+    description: "text orientation detection",
+    enabled: cfg!(feature = "ocr"),
+};
+
+/// Option values that need a feature, as `(option, value, feature)`.
+pub static REQUIREMENTS: &[(&str, &str, &Feature)] =
+    &[("backend", "gpu", &GPU), ("detector", "onnx", &ML)];
+
+/// The feature that a value of an option needs and that is not in this build, if any.
+fn missing(option: &str, value: &str) -> Option<&'static Feature> {
+    REQUIREMENTS
+        .iter()
+        .find(|(o, v, feature)| *o == option && *v == value && !feature.enabled)
+        .map(|(_, _, feature)| *feature)
+}
+
+/// Check that the features needed for the values of an option are in this build.
+///
+/// Values that need a missing feature are an error listing the `alternatives` that are in this
+/// build, or with `allow_fallback`, are left out with a warning. Other values are returned
+/// unchanged, even if they are not valid, so that they are reported by the usual parsing.
+///
+/// # Arguments
+///
+/// * `option` - The long name of the option.
+/// * `values` - The values given for the option.
+/// * `alternatives` - The values of the option to suggest instead.
+/// * `allow_fallback` - Whether to leave out unavailable values instead of failing.
+///
+/// # Errors
+///
+/// An error is returned if a value needs a missing feature and `allow_fallback` is not set.
+pub fn available<'a>(
+    option: &str,
+    values: impl IntoIterator<Item = &'a str>,
+    alternatives: &[&str],
+    allow_fallback: bool,
+) -> anyhow::Result<Vec<&'a str>> {
+    let alternatives: Vec<_> = alternatives
+        .iter()
+        .copied()
+        .filter(|alternative| missing(option, alternative).is_none())
+        .collect();
+    let mut available = Vec::new();
+    for value in values {
+        match missing(option, value) {
+            None => available.push(value),
+            Some(feature) if allow_fallback => eprintln!(
+                "Warning: --{} {} needs {}, which is not in this build of qdcrop. Continuing without it.",
+                option, value, feature.description
+            ),
+            Some(feature) => bail!(
+                "--{} {} needs {}, which is not in this build of qdcrop. Rebuild with `--features {}`, use one of: {}, or pass --allow-fallback to continue without it.",
+                option,
+                value,
+                feature.description,
+                feature.name,
+                alternatives.join(", ")
+            ),
+        }
+    }
+    Ok(available)
+}
+
+/// Check that the feature needed by a flag is in this build.
+///
+/// Returns whether the flag can be used. Without the feature, this is an error, or with
+/// `allow_fallback`, `false` with a warning.
+///
+/// # Errors
+///
+/// An error is returned if the feature is missing and `allow_fallback` is not set.
+pub fn flag(option: &str, feature: &Feature, allow_fallback: bool) -> anyhow::Result<bool> {
+    if feature.enabled {
+        return Ok(true);
+    }
+    if !allow_fallback {
+        bail!(
+            "--{} needs {}, which is not in this build of qdcrop. Rebuild with `--features {}`, or pass --allow-fallback to continue without it.",
+            option,
+            feature.description,
+            feature.name
+        );
+    }
+    eprintln!(
+        "Warning: --{} needs {}, which is not in this build of qdcrop. Continuing without it.",
+        option, feature.description
+    );
+    Ok(false)
+}
@@ -0,0 +1,246 @@
+use std::{
+    cell::RefCell,
+    ffi::{c_char, CString},
+    panic::{self, AssertUnwindSafe},
+    ptr, slice,
+};
+
+use image::RgbImage;
+
+use crate::{
+    crop_photo, detect_scaled, encode, Aspect, AspectMode, CropOptions, Detection, Error, MaxSize,
+};
+
+/// Returned when a function succeeds.
+const OK: i32 = 0;
+/// Returned when qdcrop panics, which is a bug.
+const PANICKED: i32 = -1;
+
+thread_local! {
+    /// The message of the last error on this thread, for `qdcrop_last_error`.
+    static LAST_ERROR: RefCell<CString> = RefCell::new(CString::default());
+}
+
+/// Settings for the C interface. These are the settings of `CropOptions` that a companion app
+/// is likely to change.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct QdcropOptions {
+    /// The WebP quality of outputs, from 0 to 100.
+    pub quality: f32,
+    /// The proportions of the photos as width divided by height, or 0 to estimate them from the
+    /// perspective of each photo.
+    pub aspect: f64,
+    /// The widest output, or 0 for no limit.
+    pub max_width: f64,
+    /// The tallest output, or 0 for no limit.
+    pub max_height: f64,
+}
+
+impl QdcropOptions {
+    /// Convert the settings to `CropOptions`, checking them.
+    fn to_crop_options(self) -> Result<CropOptions, Error> {
+        let limit = |max: f64| (max > 0.0).then_some(max);
+        CropOptions::builder()
+            .quality(self.quality)
+            .aspect(if self.aspect > 0.0 {
+                AspectMode::Fixed(Aspect(self.aspect))
+            } else {
+                AspectMode::Auto
+            })
+            .max_size(MaxSize {
+                width: limit(self.max_width),
+                height: limit(self.max_height),
+            })
+            .build()
+    }
+}
+
+/// The number returned for each kind of error, which matches the order of `Error`.
+fn code(error: &Error) -> i32 {
+    match error {
+        Error::DetectionFailed(_) => 1,
+        Error::LowConfidence { .. } => 2,
+        Error::DegenerateQuad(_) => 3,
+        Error::WarpFailed(_) => 4,
+        Error::AutoRotateFailed(_) => 5,
+        Error::DecodeError(_) => 6,
+        Error::EncodeError(_) => 7,
+        Error::InvalidOptions(_) => 8,
+        Error::Io(_) => 9,
+    }
+}
+
+/// Run the body of a C function, turning errors and panics into a return code and keeping the
+/// message for `qdcrop_last_error`.
+fn run(f: impl FnOnce() -> Result<(), Error>) -> i32 {
+    let (code, message) = match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => (OK, String::new()),
+        Ok(Err(error)) => (code(&error), error.to_string()),
+        Err(_) => (PANICKED, "qdcrop panicked".to_owned()),
+    };
+    let message = CString::new(message.replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = message);
+    code
+}
+
+/// Decode a screenshot from the bytes of its file.
+///
+/// # Safety
+///
+/// `input` must point to `input_len` readable bytes.
+unsafe fn decode(input: *const u8, input_len: usize) -> Result<RgbImage, Error> {
+    if input.is_null() {
+        return Err(Error::InvalidOptions("The input is null".to_owned()));
+    }
+    let data = slice::from_raw_parts(input, input_len);
+    Ok(image::load_from_memory(data)
+        .map_err(Error::DecodeError)?
+        .into_rgb8())
+}
+
+/// Read the settings given to a C function, using the defaults for null.
+///
+/// # Safety
+///
+/// `options` must be null or point to a `QdcropOptions`.
+unsafe fn read_options(options: *const QdcropOptions) -> Result<CropOptions, Error> {
+    match options.as_ref() {
+        Some(options) => options.to_crop_options(),
+        None => CropOptions::builder().build(),
+    }
+}
+
+/// Write corners to eight floats, the x and y of each corner clockwise from the top left.
+///
+/// # Safety
+///
+/// `out` must point to eight writable floats.
+unsafe fn write_corners(detection: &Detection, out: *mut f32) {
+    let out = slice::from_raw_parts_mut(out, 8);
+    for (i, (x, y)) in detection.corners.iter().enumerate() {
+        out[i * 2] = *x;
+        out[i * 2 + 1] = *y;
+    }
+}
+
+/// The default settings, the same as the defaults of the `qdcrop` command.
+#[no_mangle]
+pub extern "C" fn qdcrop_default_options() -> QdcropOptions {
+    let defaults = CropOptions::default();
+    QdcropOptions {
+        quality: defaults.quality,
+        aspect: match defaults.aspect {
+            AspectMode::Fixed(aspect) => aspect.0,
+            AspectMode::Auto => 0.0,
+        },
+        max_width: defaults.max_size.width.unwrap_or(0.0),
+        max_height: defaults.max_size.height.unwrap_or(0.0),
+    }
+}
+
+/// Find the corners of the most confident photo in a screenshot.
+///
+/// Returns 0 on success, and otherwise an error code with the message in `qdcrop_last_error`.
+///
+/// # Safety
+///
+/// `input` must point to `input_len` bytes of a PNG or JPEG file, `options` must be null or
+/// point to a `QdcropOptions`, `corners` must point to eight writable floats, and `confidence`
+/// must be null or point to a writable double.
+#[no_mangle]
+pub unsafe extern "C" fn qdcrop_detect(
+    input: *const u8,
+    input_len: usize,
+    options: *const QdcropOptions,
+    corners: *mut f32,
+    confidence: *mut f64,
+) -> i32 {
+    run(|| {
+        if corners.is_null() {
+            return Err(Error::InvalidOptions("corners is null".to_owned()));
+        }
+        let img = decode(input, input_len)?;
+        let detection = detect_scaled(&img, &read_options(options)?)?[0];
+        write_corners(&detection, corners);
+        if let Some(confidence) = confidence.as_mut() {
+            *confidence = detection.confidence;
+        }
+        Ok(())
+    })
+}
+
+/// Crop the photo out of a screenshot and encode it as WebP.
+///
+/// The photo is cropped at `corners` if it is not null, and otherwise at the corners of the most
+/// confident photo, which are written to `found_corners` if it is not null. The output must be
+/// freed with `qdcrop_free`.
+///
+/// Returns 0 on success, and otherwise an error code with the message in `qdcrop_last_error`.
+///
+/// # Safety
+///
+/// `input` must point to `input_len` bytes of a PNG or JPEG file, `options` must be null or
+/// point to a `QdcropOptions`, `corners` and `found_corners` must be null or point to eight
+/// floats, and `output` and `output_len` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn qdcrop_crop(
+    input: *const u8,
+    input_len: usize,
+    options: *const QdcropOptions,
+    corners: *const f32,
+    found_corners: *mut f32,
+    output: *mut *mut u8,
+    output_len: *mut usize,
+) -> i32 {
+    run(|| {
+        if output.is_null() || output_len.is_null() {
+            return Err(Error::InvalidOptions("output is null".to_owned()));
+        }
+        let img = decode(input, input_len)?;
+        let mut options = read_options(options)?;
+        let detection = if corners.is_null() {
+            detect_scaled(&img, &options)?[0]
+        } else {
+            let c = slice::from_raw_parts(corners, 8);
+            let corners = [(c[0], c[1]), (c[2], c[3]), (c[4], c[5]), (c[6], c[7])];
+            // Given corners are always warped, even at the edges of the screenshot.
+            options.corners = vec![corners];
+            Detection {
+                corners,
+                confidence: 1.0,
+                detector: None,
+            }
+        };
+        if !found_corners.is_null() {
+            write_corners(&detection, found_corners);
+        }
+        let photo = crop_photo(&img, &detection, &options)?;
+        let encoded = encode(&photo.image, &options)?.into_boxed_slice();
+        *output_len = encoded.len();
+        *output = Box::into_raw(encoded).cast();
+        Ok(())
+    })
+}
+
+/// Free an output returned by `qdcrop_crop`.
+///
+/// # Safety
+///
+/// `output` and `output_len` must be an output and its length from `qdcrop_crop` that was not
+/// already freed, or `output` must be null.
+#[no_mangle]
+pub unsafe extern "C" fn qdcrop_free(output: *mut u8, output_len: usize) {
+    if !output.is_null() {
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(
+            output, output_len,
+        )));
+    }
+}
+
+/// The message of the last error on the calling thread, or an empty string if the last call
+/// succeeded. The message stays valid until the next call on the same thread.
+#[no_mangle]
+pub extern "C" fn qdcrop_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ptr())
+}
@@ -0,0 +1,60 @@
+use std::{
+    fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+
+/// Read a list of inputs with one path on each line, from standard input if `path` is `-`.
+///
+/// Blank lines are skipped, and so are Windows line endings.
+///
+/// # Errors
+///
+/// An error is returned if the list cannot be read.
+pub fn read(path: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let text = if path == Path::new("-") {
+        let mut text = String::new();
+        io::stdin()
+            .read_to_string(&mut text)
+            .context("Could not read the list of inputs")?;
+        text
+    } else {
+        fs::read_to_string(path)
+            .with_context(|| format!("Could not read {}", path.to_string_lossy()))?
+    };
+    Ok(text
+        .lines()
+        .map(|line| line.trim_end_matches('\r'))
+        .filter(|line| !line.trim().is_empty())
+        .map(PathBuf::from)
+        .collect())
+}
+
+/// Write a list of inputs that `read` can read back.
+///
+/// # Errors
+///
+/// An error is returned if the list cannot be written.
+pub fn write(path: &Path, inputs: &[&Path]) -> anyhow::Result<()> {
+    let text: String = inputs
+        .iter()
+        .map(|input| format!("{}\n", input.to_string_lossy()))
+        .collect();
+    fs::write(path, text).with_context(|| format!("Could not write {}", path.to_string_lossy()))
+}
+
+/// Remove a list written by an earlier run, if there is one.
+///
+/// # Errors
+///
+/// An error is returned if the list exists and cannot be removed.
+pub fn remove(path: &Path) -> anyhow::Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => {
+            Err(e).with_context(|| format!("Could not remove {}", path.to_string_lossy()))
+        }
+        _ => Ok(()),
+    }
+}
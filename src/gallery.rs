@@ -0,0 +1,258 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    fs,
+    path::{Component, Path, PathBuf},
+};
+
+use anyhow::{bail, Context};
+use image::imageops::FilterType;
+use rayon::prelude::*;
+use webp::Encoder;
+
+use crate::capture::CaptureTime;
+
+/// The folder in the gallery where thumbnails are saved.
+const THUMBNAILS: &str = "thumbnails";
+/// The longest side of a thumbnail.
+const THUMBNAIL_SIZE: u32 = 480;
+/// The WebP quality used for thumbnails.
+const THUMBNAIL_QUALITY: f32 = 80.0;
+
+/// Find every photo in a gallery folder and its subfolders, as paths relative to it.
+///
+/// Thumbnails and the tiles of `--tile-pyramid` are left out.
+fn find_photos(root: &Path, dir: &Path, found: &mut Vec<PathBuf>) -> anyhow::Result<()> {
+    let entries = dir
+        .read_dir()
+        .with_context(|| format!("Could not read {}", dir.to_string_lossy()))?;
+    for entry in entries {
+        let path = entry
+            .with_context(|| format!("Could not read {}", dir.to_string_lossy()))?
+            .path();
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        if path.is_dir() {
+            let is_thumbnails = dir == root && name == THUMBNAILS;
+            if !is_thumbnails && !name.ends_with("_files") {
+                find_photos(root, &path, found)?;
+            }
+        } else if path
+            .extension()
+            .is_some_and(|e| e.eq_ignore_ascii_case("webp"))
+        {
+            found.push(path.strip_prefix(root).unwrap().to_owned());
+        }
+    }
+    Ok(())
+}
+
+/// Write the thumbnail of a photo, unless it is already newer than the photo.
+fn write_thumbnail(photo: &Path, thumbnail: &Path) -> anyhow::Result<()> {
+    let modified = |path: &Path| fs::metadata(path).and_then(|m| m.modified()).ok();
+    if let (Some(photo_time), Some(thumbnail_time)) = (modified(photo), modified(thumbnail)) {
+        if thumbnail_time >= photo_time {
+            return Ok(());
+        }
+    }
+    let data = fs::read(photo).context("Could not read photo")?;
+    let img = webp::Decoder::new(&data)
+        .decode()
+        .context("Could not decode photo")?
+        .to_image()
+        .into_rgb8();
+    let scale = THUMBNAIL_SIZE as f64 / std::cmp::max(img.width(), img.height()) as f64;
+    let small = if scale < 1.0 {
+        image::imageops::resize(
+            &img,
+            std::cmp::max((img.width() as f64 * scale).round() as u32, 1),
+            std::cmp::max((img.height() as f64 * scale).round() as u32, 1),
+            FilterType::Lanczos3,
+        )
+    } else {
+        img
+    };
+    let encoded =
+        Encoder::from_rgb(small.as_raw(), small.width(), small.height()).encode(THUMBNAIL_QUALITY);
+    if let Some(parent) = thumbnail.parent() {
+        fs::create_dir_all(parent).context("Could not create thumbnail folder")?;
+    }
+    fs::write(thumbnail, &*encoded).context("Could not write thumbnail")
+}
+
+/// Escape text for HTML.
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Turn a relative path into a relative URL, percent-encoding each part of it.
+fn url(path: &Path) -> String {
+    let mut url = String::new();
+    for component in path.components() {
+        if let Component::Normal(part) = component {
+            if !url.is_empty() {
+                url.push('/');
+            }
+            for byte in part.to_string_lossy().bytes() {
+                match byte {
+                    b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                        url.push(byte as char)
+                    }
+                    _ => write!(url, "%{:02X}", byte).unwrap(),
+                }
+            }
+        }
+    }
+    url
+}
+
+/// Build the gallery page.
+///
+/// `days` maps each day, as `YYYY-MM-DD`, to its photos in the order they were taken.
+fn page(title: &str, days: &BTreeMap<String, Vec<PathBuf>>) -> String {
+    let mut html = format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>{title}</title>
+<style>
+  body {{ margin: 0 auto; max-width: 1600px; padding: 0 16px 32px; font-family: sans-serif; background: #111; color: #eee; }}
+  h2 {{ margin: 32px 0 12px; font-weight: normal; }}
+  .day {{ display: flex; flex-wrap: wrap; gap: 8px; }}
+  .day a {{ display: block; }}
+  .day img {{ height: 200px; border-radius: 4px; display: block; }}
+  #lightbox {{ position: fixed; inset: 0; background: rgba(0, 0, 0, 0.92); display: none;
+               align-items: center; justify-content: center; cursor: zoom-out; }}
+  #lightbox.open {{ display: flex; }}
+  #lightbox img {{ max-width: 96vw; max-height: 96vh; }}
+</style>
+</head>
+<body>
+<h1>{title}</h1>
+"#,
+        title = escape_html(title)
+    );
+    // Newest first, but each day in the order it happened.
+    for (day, photos) in days.iter().rev() {
+        writeln!(html, "<h2>{}</h2>\n<div class=\"day\">", escape_html(day)).unwrap();
+        for photo in photos {
+            let thumbnail = Path::new(THUMBNAILS).join(photo);
+            writeln!(
+                html,
+                "<a href=\"{}\"><img src=\"{}\" alt=\"{}\" loading=\"lazy\"></a>",
+                url(photo),
+                url(&thumbnail),
+                escape_html(&photo.file_name().unwrap_or_default().to_string_lossy())
+            )
+            .unwrap();
+        }
+        html.push_str("</div>\n");
+    }
+    html.push_str(
+        r#"<div id="lightbox"><img alt=""></div>
+<script>
+"use strict";
+const lightbox = document.getElementById("lightbox");
+const image = lightbox.querySelector("img");
+const links = [...document.querySelectorAll(".day a")];
+let current = -1;
+function show(index) {
+  current = (index + links.length) % links.length;
+  image.src = links[current].href;
+  lightbox.classList.add("open");
+}
+links.forEach((link, index) => link.addEventListener("click", (event) => {
+  event.preventDefault();
+  show(index);
+}));
+lightbox.addEventListener("click", () => lightbox.classList.remove("open"));
+document.addEventListener("keydown", (event) => {
+  if (!lightbox.classList.contains("open")) return;
+  if (event.key === "Escape") lightbox.classList.remove("open");
+  if (event.key === "ArrowRight") show(current + 1);
+  if (event.key === "ArrowLeft") show(current - 1);
+});
+</script>
+</body>
+</html>
+"#,
+    );
+    html
+}
+
+/// Run the `gallery` subcommand.
+///
+/// Writes `index.html` in `dir`, with every photo in it grouped by the day it was taken, and
+/// thumbnails in a `thumbnails` folder. Running it again after cropping more photos only makes
+/// the missing thumbnails.
+///
+/// # Arguments
+///
+/// * `dir` - The folder of cropped photos.
+/// * `title` - The title of the page, or the name of the folder if `None`.
+///
+/// # Errors
+///
+/// An error is returned if the folder cannot be read or the page cannot be written.
+pub fn run(dir: &Path, title: Option<&str>) -> anyhow::Result<()> {
+    let mut photos = Vec::new();
+    find_photos(dir, dir, &mut photos)?;
+    if photos.is_empty() {
+        bail!("No photos were found in {}", dir.to_string_lossy());
+    }
+
+    let failed: Vec<_> = photos
+        .par_iter()
+        .filter_map(|photo| {
+            let thumbnail = dir.join(THUMBNAILS).join(photo);
+            write_thumbnail(&dir.join(photo), &thumbnail)
+                .err()
+                .map(|error| (photo.clone(), error))
+        })
+        .collect();
+    for (photo, error) in &failed {
+        eprintln!(
+            "Left out {} because it could not be read: {:#}",
+            photo.to_string_lossy(),
+            error
+        );
+    }
+
+    let mut days: BTreeMap<String, Vec<(Option<CaptureTime>, PathBuf)>> = BTreeMap::new();
+    for photo in photos {
+        if failed.iter().any(|(f, _)| *f == photo) {
+            continue;
+        }
+        let time = CaptureTime::of(&dir.join(&photo));
+        let day = time.map_or_else(|| "Unknown date".to_owned(), |t| t.date());
+        days.entry(day).or_default().push((time, photo));
+    }
+    let days: BTreeMap<_, _> = days
+        .into_iter()
+        .map(|(day, mut photos)| {
+            photos.sort_by_key(|(time, photo)| {
+                (time.map(|t| (t.hour, t.minute, t.second)), photo.clone())
+            });
+            (day, photos.into_iter().map(|(_, photo)| photo).collect())
+        })
+        .collect();
+
+    let title = title.map_or_else(
+        || {
+            dir.canonicalize()
+                .ok()
+                .and_then(|d| d.file_name().map(|n| n.to_string_lossy().into_owned()))
+                .unwrap_or_else(|| "Photos".to_owned())
+        },
+        str::to_owned,
+    );
+    let index = dir.join("index.html");
+    fs::write(&index, page(&title, &days))
+        .with_context(|| format!("Could not write {}", index.to_string_lossy()))?;
+    eprintln!("Wrote {}", index.to_string_lossy());
+    Ok(())
+}
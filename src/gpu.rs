@@ -0,0 +1,294 @@
+use std::sync::{mpsc, OnceLock};
+
+use anyhow::{anyhow, bail, Context};
+use image::RgbImage;
+use imageproc::geometric_transformations::Projection;
+use wgpu::util::DeviceExt;
+
+use crate::backend::{square_to_quad, Interpolation};
+
+/// The width and height of a compute workgroup.
+const WORKGROUP: u32 = 8;
+
+/// The warp shader. Each invocation maps one output pixel back into the screenshot and samples
+/// it, averaging a grid of samples when the photo is shrunk.
+const SHADER: &str = r#"
+struct Params {
+    // a, b, c, g and d, e, f, h of the map from the unit square to the screenshot.
+    m0: vec4<f32>,
+    m1: vec4<f32>,
+    src_size: vec2<u32>,
+    dst_size: vec2<u32>,
+    // 0 for nearest, 1 for bilinear, 2 for bicubic.
+    mode: u32,
+    samples: u32,
+    pad0: u32,
+    pad1: u32,
+}
+
+@group(0) @binding(0) var<uniform> params: Params;
+@group(0) @binding(1) var<storage, read> src: array<u32>;
+@group(0) @binding(2) var<storage, read_write> dst: array<u32>;
+
+fn source_at(u: f32, v: f32) -> vec2<f32> {
+    let w = params.m0.w * u + params.m1.w * v + 1.0;
+    return vec2<f32>(
+        (params.m0.x * u + params.m0.y * v + params.m0.z) / w,
+        (params.m1.x * u + params.m1.y * v + params.m1.z) / w,
+    );
+}
+
+fn texel(x: i32, y: i32) -> vec3<f32> {
+    let cx = clamp(x, 0, i32(params.src_size.x) - 1);
+    let cy = clamp(y, 0, i32(params.src_size.y) - 1);
+    let p = src[u32(cy) * params.src_size.x + u32(cx)];
+    return vec3<f32>(f32(p & 0xffu), f32((p >> 8u) & 0xffu), f32((p >> 16u) & 0xffu));
+}
+
+// Weights of the four pixels around a point for the Keys cubic with a = -0.5.
+fn cubic(t: f32) -> vec4<f32> {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    return vec4<f32>(
+        -0.5 * t3 + t2 - 0.5 * t,
+        1.5 * t3 - 2.5 * t2 + 1.0,
+        -1.5 * t3 + 2.0 * t2 + 0.5 * t,
+        0.5 * t3 - 0.5 * t2,
+    );
+}
+
+fn sample(p: vec2<f32>) -> vec3<f32> {
+    if (params.mode == 0u) {
+        return texel(i32(round(p.x)), i32(round(p.y)));
+    }
+    let base = floor(p);
+    let f = p - base;
+    let x = i32(base.x);
+    let y = i32(base.y);
+    if (params.mode == 1u) {
+        let top = mix(texel(x, y), texel(x + 1, y), f.x);
+        let bottom = mix(texel(x, y + 1), texel(x + 1, y + 1), f.x);
+        return mix(top, bottom, f.y);
+    }
+    let wx = cubic(f.x);
+    let wy = cubic(f.y);
+    var sum = vec3<f32>(0.0);
+    for (var j = 0; j < 4; j++) {
+        let row = y - 1 + j;
+        sum += (texel(x - 1, row) * wx.x + texel(x, row) * wx.y
+            + texel(x + 1, row) * wx.z + texel(x + 2, row) * wx.w) * wy[j];
+    }
+    return sum;
+}
+
+@compute @workgroup_size(8, 8)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    if (id.x >= params.dst_size.x || id.y >= params.dst_size.y) {
+        return;
+    }
+    let size = vec2<f32>(params.dst_size);
+    let src_size = vec2<f32>(params.src_size);
+    let index = id.y * params.dst_size.x + id.x;
+    let center = source_at(f32(id.x) / size.x, f32(id.y) / size.y);
+    if (any(center < vec2<f32>(-0.5)) || any(center >= src_size - 0.5)) {
+        dst[index] = 0xff000000u;
+        return;
+    }
+    let n = params.samples;
+    var sum = vec3<f32>(0.0);
+    for (var j = 0u; j < n; j++) {
+        for (var i = 0u; i < n; i++) {
+            let offset = (vec2<f32>(f32(i), f32(j)) + 0.5) / f32(n) - 0.5;
+            let p = (vec2<f32>(id.xy) + offset) / size;
+            sum += sample(source_at(p.x, p.y));
+        }
+    }
+    let color = vec3<u32>(clamp(round(sum / f32(n * n)), vec3<f32>(0.0), vec3<f32>(255.0)));
+    dst[index] = color.x | (color.y << 8u) | (color.z << 16u) | 0xff000000u;
+}
+"#;
+
+/// A graphics card ready to run the warp shader.
+struct Gpu {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    pipeline: wgpu::ComputePipeline,
+}
+
+impl Gpu {
+    fn new() -> anyhow::Result<Self> {
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
+        let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            ..Default::default()
+        }))
+        .context("No graphics card was found")?;
+        let (device, queue) = pollster::block_on(adapter.request_device(
+            &wgpu::DeviceDescriptor {
+                label: Some("qdcrop"),
+                required_features: wgpu::Features::empty(),
+                // 8K screenshots need more than the default storage buffer size.
+                required_limits: adapter.limits(),
+            },
+            None,
+        ))
+        .context("Could not open the graphics card")?;
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("warp"),
+            source: wgpu::ShaderSource::Wgsl(SHADER.into()),
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("warp"),
+            layout: None,
+            module: &module,
+            entry_point: "main",
+        });
+        Ok(Gpu {
+            device,
+            queue,
+            pipeline,
+        })
+    }
+}
+
+/// The graphics card, opened the first time it is needed.
+fn gpu() -> anyhow::Result<&'static Gpu> {
+    static GPU: OnceLock<Result<Gpu, String>> = OnceLock::new();
+    GPU.get_or_init(|| Gpu::new().map_err(|e| format!("{:#}", e)))
+        .as_ref()
+        .map_err(|e| anyhow!("{}", e))
+}
+
+/// Reverse a perspective projection on the graphics card.
+///
+/// `Lanczos` interpolation is not available on the graphics card, so it samples with `Bicubic`.
+///
+/// # Arguments
+///
+/// * `img` - The source image.
+/// * `projection` - The projection from source coordinates to output coordinates.
+/// * `size` - The width and height of the output image.
+/// * `interpolation` - How to sample the source image.
+/// * `samples` - How many samples to average along each axis of every output pixel. More than 1
+///   shrinks without aliasing, like warping to a larger size and resizing afterward.
+///
+/// # Errors
+///
+/// An error is returned if there is no usable graphics card or the images are too large for it.
+pub fn warp(
+    img: &RgbImage,
+    projection: &Projection,
+    size: (u32, u32),
+    interpolation: Interpolation,
+    samples: u32,
+) -> anyhow::Result<RgbImage> {
+    let gpu = gpu()?;
+    let inverse = projection.invert();
+    let (w, h) = (size.0 as f32, size.1 as f32);
+    let quad = [(0.0, 0.0), (w, 0.0), (w, h), (0.0, h)].map(|p| inverse * p);
+    let m = square_to_quad(&quad);
+    let mode: u32 = match interpolation {
+        Interpolation::Nearest => 0,
+        Interpolation::Bilinear => 1,
+        Interpolation::Bicubic | Interpolation::Lanczos => 2,
+    };
+
+    let mut params = Vec::with_capacity(64);
+    for v in [m[0], m[1], m[2], m[6], m[3], m[4], m[5], m[7]] {
+        params.extend(v.to_le_bytes());
+    }
+    for v in [
+        img.width(),
+        img.height(),
+        size.0,
+        size.1,
+        mode,
+        samples,
+        0,
+        0,
+    ] {
+        params.extend(v.to_le_bytes());
+    }
+    let source: Vec<u8> = img
+        .pixels()
+        .flat_map(|p| [p.0[0], p.0[1], p.0[2], 255])
+        .collect();
+    let output_bytes = size.0 as u64 * size.1 as u64 * 4;
+    let limit = gpu.device.limits().max_storage_buffer_binding_size as u64;
+    if source.len() as u64 > limit || output_bytes > limit {
+        bail!("The picture is too large for this graphics card");
+    }
+
+    let device = &gpu.device;
+    let params = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("params"),
+        contents: &params,
+        usage: wgpu::BufferUsages::UNIFORM,
+    });
+    let source = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("source"),
+        contents: &source,
+        usage: wgpu::BufferUsages::STORAGE,
+    });
+    let output = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("output"),
+        size: output_bytes,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+        mapped_at_creation: false,
+    });
+    let readback = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("readback"),
+        size: output_bytes,
+        usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("warp"),
+        layout: &gpu.pipeline.get_bind_group_layout(0),
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: params.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: source.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: output.as_entire_binding(),
+            },
+        ],
+    });
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+    {
+        let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("warp"),
+            timestamp_writes: None,
+        });
+        pass.set_pipeline(&gpu.pipeline);
+        pass.set_bind_group(0, &bind_group, &[]);
+        pass.dispatch_workgroups(size.0.div_ceil(WORKGROUP), size.1.div_ceil(WORKGROUP), 1);
+    }
+    encoder.copy_buffer_to_buffer(&output, 0, &readback, 0, output_bytes);
+    gpu.queue.submit(Some(encoder.finish()));
+
+    let slice = readback.slice(..);
+    let (sender, receiver) = mpsc::channel();
+    slice.map_async(wgpu::MapMode::Read, move |result| {
+        let _ = sender.send(result);
+    });
+    device.poll(wgpu::Maintain::Wait);
+    receiver
+        .recv()
+        .context("The graphics card stopped responding")?
+        .context("Could not read the warped image from the graphics card")?;
+    let raw = slice
+        .get_mapped_range()
+        .chunks_exact(4)
+        .flat_map(|p| [p[0], p[1], p[2]])
+        .collect();
+    readback.unmap();
+    Ok(RgbImage::from_raw(size.0, size.1, raw).unwrap())
+}
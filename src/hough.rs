@@ -0,0 +1,118 @@
+use image::GrayImage;
+
+use crate::{Quad, Region};
+
+/// How far from horizontal or vertical a border line may be, in tenths of a degree.
+const MAX_TILT: i32 = 200;
+/// The angular resolution of the accumulator, in tenths of a degree.
+const STEP: i32 = 5;
+
+/// A line in Hough space: the points where `x * cos(theta) + y * sin(theta) == r`.
+#[derive(Clone, Copy, Debug)]
+struct Line {
+    theta: f64,
+    r: f64,
+    votes: u32,
+}
+
+impl Line {
+    /// Find the point where two lines cross.
+    fn intersect(&self, other: &Line) -> Option<(f32, f32)> {
+        let (a1, b1) = (self.theta.cos(), self.theta.sin());
+        let (a2, b2) = (other.theta.cos(), other.theta.sin());
+        let det = a1 * b2 - a2 * b1;
+        if det.abs() < 1e-9 {
+            return None;
+        }
+        let x = (self.r * b2 - other.r * b1) / det;
+        let y = (a1 * other.r - a2 * self.r) / det;
+        Some((x as f32, y as f32))
+    }
+}
+
+/// Vote for lines through edge pixels.
+///
+/// # Arguments
+///
+/// * `edges` - An image where edge pixels are non-zero.
+/// * `center` - The angle of the lines to look for, in tenths of a degree. 0 finds vertical lines.
+/// * `min_votes` - Lines with fewer votes than this are discarded.
+fn accumulate(edges: &GrayImage, center: i32, min_votes: u32) -> Vec<Line> {
+    let (width, height) = edges.dimensions();
+    let max_r = ((width as f64).hypot(height as f64)).ceil() as i32;
+    let angles: Vec<(f64, f64, f64)> = (-MAX_TILT / STEP..=MAX_TILT / STEP)
+        .map(|i| {
+            let theta = ((center + i * STEP) as f64 / 10.0).to_radians();
+            (theta, theta.cos(), theta.sin())
+        })
+        .collect();
+    let rows = (2 * max_r + 1) as usize;
+    let mut accumulator = vec![0u32; angles.len() * rows];
+
+    for (x, y, p) in edges.enumerate_pixels() {
+        if p.0[0] == 0 {
+            continue;
+        }
+        for (a, (_, cos, sin)) in angles.iter().enumerate() {
+            let r = (x as f64 * cos + y as f64 * sin).round() as i32;
+            accumulator[a * rows + (r + max_r) as usize] += 1;
+        }
+    }
+
+    accumulator
+        .iter()
+        .enumerate()
+        .filter(|(_, votes)| **votes >= min_votes)
+        .map(|(i, votes)| Line {
+            theta: angles[i / rows].0,
+            r: (i % rows) as f64 - max_r as f64,
+            votes: *votes,
+        })
+        .collect()
+}
+
+/// Find the line with the most votes out of the lines accepted by `filter`.
+fn strongest(lines: &[Line], filter: impl Fn(&Line) -> bool) -> Option<Line> {
+    lines
+        .iter()
+        .filter(|l| filter(l))
+        .max_by_key(|l| l.votes)
+        .copied()
+}
+
+/// Find the photo by intersecting the strongest straight lines on each side of the screenshot.
+///
+/// This is not fooled by dark objects near the corners of the screenshot because a single
+/// dark object does not form a long straight line.
+///
+/// Edges inside the `exclude` regions are ignored.
+///
+/// Returns `None` if a border line could not be found on every side.
+pub fn detect(luma: &GrayImage, exclude: &[Region]) -> Option<Quad> {
+    let (width, height) = luma.dimensions();
+    let (w, h) = (width as f64, height as f64);
+    let mut edges = imageproc::edges::canny(luma, 30.0, 90.0);
+    for region in exclude {
+        region.fill(&mut edges, 0);
+    }
+
+    // A border line should cover at least a quarter of the screenshot.
+    let horizontal = accumulate(&edges, 900, width / 4);
+    let vertical = accumulate(&edges, 0, height / 4);
+
+    // Where a line crosses the middle of the screenshot.
+    let y_at_center = |l: &Line| (l.r - w / 2.0 * l.theta.cos()) / l.theta.sin();
+    let x_at_center = |l: &Line| (l.r - h / 2.0 * l.theta.sin()) / l.theta.cos();
+
+    let top = strongest(&horizontal, |l| (0.0..h / 2.0).contains(&y_at_center(l)))?;
+    let bottom = strongest(&horizontal, |l| (h / 2.0..h).contains(&y_at_center(l)))?;
+    let left = strongest(&vertical, |l| (0.0..w / 2.0).contains(&x_at_center(l)))?;
+    let right = strongest(&vertical, |l| (w / 2.0..w).contains(&x_at_center(l)))?;
+
+    Some([
+        top.intersect(&left)?,
+        top.intersect(&right)?,
+        bottom.intersect(&right)?,
+        bottom.intersect(&left)?,
+    ])
+}
@@ -0,0 +1,136 @@
+/// The signature of the ICC profile segments of JPEG files.
+const JPEG_SIGNATURE: &[u8] = b"ICC_PROFILE\0";
+
+/// Find the ICC profile of a JPEG or WebP file.
+///
+/// Returns `None` if the file has no profile or is another format. Profiles in PNG files are
+/// compressed and are not read.
+pub fn find(file: &[u8]) -> Option<Vec<u8>> {
+    if let Some(mut rest) = file.strip_prefix(b"\xff\xd8") {
+        // Large profiles are split into numbered segments.
+        let mut parts = Vec::new();
+        while rest.len() >= 4 && rest[0] == 0xff && rest[1] != 0xda {
+            let length = u16::from_be_bytes([rest[2], rest[3]]) as usize;
+            let segment = rest.get(4..2 + length)?;
+            if rest[1] == 0xe2 {
+                if let Some(part) = segment.strip_prefix(JPEG_SIGNATURE) {
+                    if part.len() >= 2 {
+                        parts.push((part[0], &part[2..]));
+                    }
+                }
+            }
+            rest = &rest[2 + length..];
+        }
+        parts.sort_by_key(|(index, _)| *index);
+        let profile = parts
+            .into_iter()
+            .flat_map(|(_, data)| data)
+            .copied()
+            .collect::<Vec<_>>();
+        (!profile.is_empty()).then_some(profile)
+    } else if file.len() >= 12 && &file[..4] == b"RIFF" && &file[8..12] == b"WEBP" {
+        let mut rest = &file[12..];
+        while rest.len() >= 8 {
+            let length = u32::from_le_bytes(rest[4..8].try_into().unwrap()) as usize;
+            let data = rest.get(8..8 + length)?;
+            if &rest[..4] == b"ICCP" {
+                return Some(data.to_vec());
+            }
+            rest = rest.get(8 + length + length % 2..).unwrap_or_default();
+        }
+        None
+    } else {
+        None
+    }
+}
+
+/// Encode a number as an ICC s15Fixed16Number.
+fn fixed(value: f64) -> [u8; 4] {
+    ((value * 65536.0).round() as i32).to_be_bytes()
+}
+
+/// Build an `XYZ ` tag.
+fn xyz(x: f64, y: f64, z: f64) -> Vec<u8> {
+    let mut tag = b"XYZ \0\0\0\0".to_vec();
+    for value in [x, y, z] {
+        tag.extend_from_slice(&fixed(value));
+    }
+    tag
+}
+
+/// Build an `mluc` tag holding one English text.
+fn text(text: &str) -> Vec<u8> {
+    let utf16: Vec<u8> = text.encode_utf16().flat_map(u16::to_be_bytes).collect();
+    let mut tag = b"mluc\0\0\0\0".to_vec();
+    tag.extend_from_slice(&1u32.to_be_bytes());
+    tag.extend_from_slice(&12u32.to_be_bytes());
+    tag.extend_from_slice(b"enUS");
+    tag.extend_from_slice(&(utf16.len() as u32).to_be_bytes());
+    tag.extend_from_slice(&28u32.to_be_bytes());
+    tag.extend_from_slice(&utf16);
+    tag
+}
+
+/// Build an ICC v4 profile for sRGB, the color space of screenshots without a profile.
+///
+/// The primaries are adapted to the D50 illuminant of the profile connection space with the
+/// Bradford transform, as in the sRGB profiles shipped with operating systems.
+pub fn srgb() -> Vec<u8> {
+    let mut curve = b"para\0\0\0\0".to_vec();
+    curve.extend_from_slice(&3u16.to_be_bytes());
+    curve.extend_from_slice(&[0, 0]);
+    for value in [2.4, 1.0 / 1.055, 0.055 / 1.055, 1.0 / 12.92, 0.04045] {
+        curve.extend_from_slice(&fixed(value));
+    }
+    let mut adaptation = b"sf32\0\0\0\0".to_vec();
+    for value in [
+        1.0478, 0.0229, -0.0501, 0.0295, 0.9905, -0.0171, -0.0092, 0.0151, 0.7521,
+    ] {
+        adaptation.extend_from_slice(&fixed(value));
+    }
+    let tags: [(&[u8; 4], Vec<u8>); 10] = [
+        (b"desc", text("sRGB")),
+        (b"cprt", text("No copyright, use freely")),
+        (b"wtpt", xyz(0.9642, 1.0, 0.8249)),
+        (b"chad", adaptation),
+        (b"rXYZ", xyz(0.4361, 0.2225, 0.0139)),
+        (b"gXYZ", xyz(0.3851, 0.7169, 0.0971)),
+        (b"bXYZ", xyz(0.1431, 0.0606, 0.7141)),
+        (b"rTRC", curve.clone()),
+        (b"gTRC", curve.clone()),
+        (b"bTRC", curve),
+    ];
+
+    let mut table = (tags.len() as u32).to_be_bytes().to_vec();
+    let mut data = Vec::new();
+    let data_start = 128 + 4 + 12 * tags.len();
+    for (signature, tag) in &tags {
+        table.extend_from_slice(*signature);
+        table.extend_from_slice(&((data_start + data.len()) as u32).to_be_bytes());
+        table.extend_from_slice(&(tag.len() as u32).to_be_bytes());
+        data.extend_from_slice(tag);
+        data.resize(data.len().next_multiple_of(4), 0);
+    }
+
+    let size = (data_start + data.len()) as u32;
+    let mut profile = Vec::with_capacity(size as usize);
+    profile.extend_from_slice(&size.to_be_bytes());
+    profile.extend_from_slice(&[0; 4]);
+    // Version 4.3.
+    profile.extend_from_slice(&[4, 0x30, 0, 0]);
+    profile.extend_from_slice(b"mntrRGB XYZ ");
+    // The creation date, 2022-01-01.
+    for value in [2022u16, 1, 1, 0, 0, 0] {
+        profile.extend_from_slice(&value.to_be_bytes());
+    }
+    profile.extend_from_slice(b"acsp");
+    profile.extend_from_slice(&[0; 24]);
+    // Perceptual rendering intent.
+    profile.extend_from_slice(&[0; 4]);
+    profile.extend_from_slice(&xyz(0.9642, 1.0, 0.8249)[8..]);
+    profile.extend_from_slice(&[0; 4 + 16 + 28]);
+    debug_assert_eq!(profile.len(), 128);
+    profile.extend_from_slice(&table);
+    profile.extend_from_slice(&data);
+    profile
+}
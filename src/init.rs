@@ -0,0 +1,139 @@
+use std::{
+    fs,
+    io::{self, BufRead, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context};
+
+use crate::{config, source};
+
+/// Ask a question and read the answer from the terminal.
+///
+/// Returns `default` if the answer is empty.
+fn ask(question: &str, default: &str) -> anyhow::Result<String> {
+    if default.is_empty() {
+        print!("{}: ", question);
+    } else {
+        print!("{} [{}]: ", question, default);
+    }
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    if io::stdin().lock().read_line(&mut answer)? == 0 {
+        bail!("Setup was cancelled");
+    }
+    let answer = answer.trim();
+    Ok(if answer.is_empty() {
+        default.to_owned()
+    } else {
+        answer.to_owned()
+    })
+}
+
+/// Ask a yes or no question.
+fn confirm(question: &str, default: bool) -> anyhow::Result<bool> {
+    loop {
+        print!("{} [{}]: ", question, if default { "Y/n" } else { "y/N" });
+        io::stdout().flush()?;
+        let mut answer = String::new();
+        if io::stdin().lock().read_line(&mut answer)? == 0 {
+            bail!("Setup was cancelled");
+        }
+        match answer.trim().to_lowercase().as_str() {
+            "" => return Ok(default),
+            "y" | "yes" => return Ok(true),
+            "n" | "no" => return Ok(false),
+            _ => println!("Please answer yes or no."),
+        }
+    }
+}
+
+/// Quote a string for the configuration file.
+///
+/// Single quotes are used so Windows paths do not need their backslashes escaped.
+fn quote(value: &str) -> anyhow::Result<String> {
+    if value.contains('\'') {
+        bail!("{} cannot contain '", value);
+    }
+    Ok(format!("'{}'", value))
+}
+
+/// Run the `init` subcommand, which asks a few questions and writes the configuration file.
+pub fn run() -> anyhow::Result<()> {
+    let path = config::path()?;
+    println!("This will set up qdcrop and save your answers to");
+    println!("  {}", path.to_string_lossy());
+    println!("Press Enter to accept the suggestion in brackets.");
+    println!();
+    if path.exists() && !confirm("A configuration already exists. Replace it?", false)? {
+        return Ok(());
+    }
+
+    let vrchat = source::find("vrchat")
+        .unwrap()
+        .directories()
+        .into_iter()
+        .find(|d| d.is_dir());
+    let suggestion = vrchat
+        .as_deref()
+        .map(Path::to_string_lossy)
+        .unwrap_or_default();
+    let screenshots = loop {
+        let answer = ask("Where does VRChat save your pictures?", &suggestion)?;
+        if Path::new(&answer).is_dir() {
+            break PathBuf::from(answer);
+        }
+        println!("{} is not a folder.", answer);
+    };
+    let auto_source = if vrchat.as_ref() == Some(&screenshots) {
+        quote("vrchat")?
+    } else {
+        quote(&format!("folder:{}", screenshots.to_string_lossy()))?
+    };
+
+    let output = ask(
+        "Where should the cropped pictures be saved?",
+        &screenshots.join("qdcrop").to_string_lossy(),
+    )?;
+
+    let quality = loop {
+        let answer = ask(
+            "WebP quality, from 0 (smallest files) to 100 (best pictures)",
+            "95",
+        )?;
+        match answer.parse::<f32>() {
+            Ok(quality) if (0.0..=100.0).contains(&quality) => break answer,
+            _ => println!("Please enter a number from 0 to 100."),
+        }
+    };
+
+    let watch = confirm(
+        "After cropping, keep running and crop new pictures as soon as they are saved?",
+        false,
+    )?;
+
+    let text = format!(
+        "# Written by qdcrop init. Run qdcrop config edit to change these.\n\
+         auto-source = {}\n\
+         output-dir = {}\n\
+         quality = {}\n\
+         # Only crop pictures that have not been cropped before.\n\
+         on-conflict = 'skip'\n\
+         # Keep cropping new pictures until stopped with Ctrl+C.\n\
+         watch = {}\n",
+        auto_source,
+        quote(&output)?,
+        quality,
+        watch
+    );
+    fs::create_dir_all(config::dir()?).context("Could not create configuration directory")?;
+    fs::create_dir_all(&output).context("Could not create output folder")?;
+    fs::write(&path, text).context("Could not write configuration")?;
+    println!();
+    if watch {
+        println!("All set. Run qdcrop with no arguments to crop your new pictures, and leave it running to crop the pictures you take after that.");
+    } else {
+        println!("All set. Run qdcrop with no arguments to crop your new pictures.");
+    }
+    Ok(())
+}
@@ -0,0 +1,80 @@
+use image::{GenericImageView, RgbImage};
+use imageproc::geometric_transformations::Projection;
+
+/// A rectangle of an image, in pixels.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct Rect {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+}
+
+impl Rect {
+    fn area(self) -> u64 {
+        self.width as u64 * self.height as u64
+    }
+}
+
+/// Find the largest rectangle under a histogram of column heights whose bottom is on `y`.
+fn largest_in_histogram(heights: &[u32], y: u32) -> Rect {
+    let mut best = Rect::default();
+    // Columns whose heights increase, each with the first column its height reaches back to.
+    let mut stack: Vec<(usize, u32)> = Vec::new();
+    for (x, &height) in heights.iter().chain([&0]).enumerate() {
+        let mut start = x;
+        while let Some(&(left, top)) = stack.last() {
+            if top < height {
+                break;
+            }
+            stack.pop();
+            let rect = Rect {
+                x: left as u32,
+                y: y + 1 - top,
+                width: (x - left) as u32,
+                height: top,
+            };
+            if rect.area() > best.area() {
+                best = rect;
+            }
+            start = left;
+        }
+        stack.push((start, height));
+    }
+    best
+}
+
+/// Crop a warped photo to the largest rectangle that was sampled from inside the screenshot.
+///
+/// Where the corners of the photo are outside the screenshot, the warp fills the output with
+/// black. Cropping to this rectangle leaves none of that fill.
+///
+/// # Arguments
+///
+/// * `img` - The warped photo.
+/// * `projection` - The projection from screenshot coordinates to `img`'s coordinates.
+/// * `source` - The size of the screenshot.
+pub fn crop(img: &RgbImage, projection: &Projection, source: (u32, u32)) -> RgbImage {
+    let inverse = projection.invert();
+    let (width, height) = img.dimensions();
+    let (source_width, source_height) = (source.0 as f32, source.1 as f32);
+
+    let mut heights = vec![0; width as usize];
+    let mut best = Rect::default();
+    for y in 0..height {
+        for (x, column) in heights.iter_mut().enumerate() {
+            let (sx, sy) = inverse * (x as f32, y as f32);
+            let inside = (-0.5..source_width - 0.5).contains(&sx)
+                && (-0.5..source_height - 0.5).contains(&sy);
+            *column = if inside { *column + 1 } else { 0 };
+        }
+        let rect = largest_in_histogram(&heights, y);
+        if rect.area() > best.area() {
+            best = rect;
+        }
+    }
+    if best.area() == 0 || best.area() == width as u64 * height as u64 {
+        return img.clone();
+    }
+    img.view(best.x, best.y, best.width, best.height).to_image()
+}
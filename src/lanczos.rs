@@ -0,0 +1,117 @@
+use std::f32::consts::PI;
+
+use image::RgbImage;
+use imageproc::geometric_transformations::Projection;
+
+/// The number of lobes of the Lanczos kernel on each side of its center.
+const LOBES: f32 = 3.0;
+/// The most the kernel is widened when the warp shrinks the photo. Shrinking more than this
+/// still aliases a little, but wider kernels get very slow.
+const MAX_SCALE: f32 = 4.0;
+/// The most source pixels the kernel covers along one axis.
+const MAX_TAPS: usize = (2.0 * LOBES * MAX_SCALE) as usize + 2;
+
+/// The Lanczos kernel.
+fn kernel(x: f32) -> f32 {
+    if x.abs() < f32::EPSILON {
+        1.0
+    } else if x.abs() >= LOBES {
+        0.0
+    } else {
+        let px = PI * x;
+        LOBES * px.sin() * (px / LOBES).sin() / (px * px)
+    }
+}
+
+/// Find the source pixels the kernel covers around `center` along one axis, and their weights.
+///
+/// Pixels past the edge of the source are clamped to the edge. Returns how many taps were
+/// written.
+fn taps(
+    center: f32,
+    scale: f32,
+    limit: u32,
+    indices: &mut [u32; MAX_TAPS],
+    weights: &mut [f32; MAX_TAPS],
+) -> usize {
+    let radius = LOBES * scale;
+    let first = (center - radius).ceil() as i64;
+    let last = (center + radius).floor() as i64;
+    let mut count = 0;
+    for i in first..=last {
+        if count == MAX_TAPS {
+            break;
+        }
+        indices[count] = i.clamp(0, limit as i64 - 1) as u32;
+        weights[count] = kernel((i as f32 - center) / scale);
+        count += 1;
+    }
+    count
+}
+
+/// Reverse a perspective projection, sampling the source with a Lanczos kernel.
+///
+/// Where the warp shrinks the photo, the kernel is widened to match, so that fine patterns are
+/// averaged instead of aliasing. Output pixels that come from outside the source are black.
+///
+/// # Arguments
+///
+/// * `img` - The source image.
+/// * `projection` - The projection from source coordinates to output coordinates.
+/// * `size` - The width and height of the output image.
+pub fn warp(img: &RgbImage, projection: &Projection, size: (u32, u32)) -> RgbImage {
+    let inverse = projection.invert();
+    let (width, height) = img.dimensions();
+    let mut out_img = crate::pool::rgb(size.0, size.1);
+    let margin = LOBES * MAX_SCALE;
+    crate::for_each_tile(&mut out_img, |mut tile| {
+        if tile.is_outside(|p| inverse * p, (width, height), margin) {
+            return;
+        }
+        let mut x_indices = [0; MAX_TAPS];
+        let mut x_weights = [0.0; MAX_TAPS];
+        let mut y_indices = [0; MAX_TAPS];
+        let mut y_weights = [0.0; MAX_TAPS];
+        let (left, top) = (tile.x, tile.y);
+        for (y, row) in tile.rows.iter_mut().enumerate() {
+            let y = (top as usize + y) as f32;
+            for (x, pixel) in row.chunks_exact_mut(3).enumerate() {
+                let x = (left as usize + x) as f32;
+                let (sx, sy) = inverse * (x, y);
+                if !(-0.5..width as f32 - 0.5).contains(&sx)
+                    || !(-0.5..height as f32 - 0.5).contains(&sy)
+                {
+                    continue;
+                }
+                // How far one output pixel reaches in the source, along each source axis.
+                let (right, down) = (inverse * (x + 1.0, y), inverse * (x, y + 1.0));
+                let scale_x = f32::max((right.0 - sx).abs(), (down.0 - sx).abs());
+                let scale_y = f32::max((right.1 - sy).abs(), (down.1 - sy).abs());
+                let scale_x = scale_x.clamp(1.0, MAX_SCALE);
+                let scale_y = scale_y.clamp(1.0, MAX_SCALE);
+
+                let nx = taps(sx, scale_x, width, &mut x_indices, &mut x_weights);
+                let ny = taps(sy, scale_y, height, &mut y_indices, &mut y_weights);
+                let mut sum = [0.0f32; 3];
+                let mut total = 0.0;
+                for (&row_index, &wy) in y_indices[..ny].iter().zip(&y_weights[..ny]) {
+                    for (&column, &wx) in x_indices[..nx].iter().zip(&x_weights[..nx]) {
+                        let weight = wx * wy;
+                        let source = img.get_pixel(column, row_index).0;
+                        for (s, v) in sum.iter_mut().zip(source) {
+                            *s += v as f32 * weight;
+                        }
+                        total += weight;
+                    }
+                }
+                if total.abs() <= f32::EPSILON {
+                    continue;
+                }
+                for (p, s) in pixel.iter_mut().zip(sum) {
+                    *p = (s / total).round().clamp(0.0, 255.0) as u8;
+                }
+            }
+        }
+    });
+    out_img
+}
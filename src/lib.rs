@@ -0,0 +1,1895 @@
+//! Find photos of photos in screenshots and correct their perspective.
+//!
+//! This is the pipeline behind the `qdcrop` command, for programs that want to crop screenshots
+//! themselves. `process` finds the photos in a decoded screenshot and warps them, and `encode`
+//! turns a photo into WebP. The steps are also available separately: `detect_scaled` finds the
+//! corners of photos, and `crop_photo` warps one photo at corners found earlier.
+//!
+//! ```no_run
+//! let img = image::open("screenshot.png")?.into_rgb8();
+//! let processed = qdcrop::process(&img, &qdcrop::CropOptions::default())?;
+//! for (i, photo) in processed.photos.iter().enumerate() {
+//!     let webp = qdcrop::encode(&photo.image, &qdcrop::CropOptions::default())?;
+//!     std::fs::write(format!("photo_{}.webp", i + 1), webp)?;
+//! }
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+
+use std::{borrow::Cow, fmt, path::PathBuf, str::FromStr, sync::Arc};
+
+use anyhow::{anyhow, bail, Context};
+use image::{imageops::FilterType, GenericImageView, GrayImage, RgbImage};
+use imageproc::{definitions::HasBlack, geometric_transformations::Projection};
+use nalgebra::{ArrayStorage, Matrix};
+#[cfg(not(target_arch = "wasm32"))]
+use rayon::prelude::*;
+#[cfg(not(target_arch = "wasm32"))]
+use webp::{Encoder, WebPConfig};
+
+use crate::{
+    backend::{Backend, Interpolation},
+    border::BorderColor,
+    confidence::LowConfidence,
+    conflict::ConflictPolicy,
+    resize::{ResizeFilter, ScaleMode},
+};
+
+pub use error::Error;
+
+mod aspect;
+pub mod backend;
+pub mod border;
+pub mod confidence;
+pub mod conflict;
+pub mod contour;
+pub mod distortion;
+mod edges;
+mod error;
+pub mod features;
+// The C interface declared in include/qdcrop.h.
+#[cfg(feature = "ffi")]
+mod ffi;
+#[cfg(feature = "gpu")]
+mod gpu;
+mod hough;
+mod inscribed;
+mod lanczos;
+mod ocr;
+pub mod onnx;
+// Screenshots of a batch usually have the same size, so the buffers of one fit the next.
+pub mod pool;
+// Python bindings, built with maturin.
+#[cfg(feature = "python")]
+mod python;
+pub mod resize;
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+mod simd;
+// How long each stage takes, for `--timings`.
+pub mod timings;
+mod trim;
+pub mod validate;
+// Cropping in the browser. Screenshots are given as the bytes of their files and photos are
+// returned as PNG files, so nothing there reads or writes files.
+#[cfg(feature = "wasm")]
+mod wasm;
+mod window;
+
+/// Find an inverse projection matrix for a rectangle.
+///
+/// # Arguments
+///
+/// * `from` - the points of the forward perspective transformed rectangle, provided clockwise from (0, 0) in the top left.
+/// * `to` - the width and height of the image after reverse projection.
+///
+/// # Errors
+///
+/// `Error::DegenerateQuad` is returned if three of the points in `from` form a line.
+pub fn from_control_points(from: [(f32, f32); 4], to: (u32, u32)) -> Result<Projection, Error> {
+    Ok(Projection::from_matrix(control_point_matrix(from, to)?).unwrap())
+}
+
+/// Find the matrix of the projection found by `from_control_points`, row by row.
+///
+/// # Errors
+///
+/// `Error::DegenerateQuad` is returned if three of the points in `from` form a line.
+pub fn control_point_matrix(from: [(f32, f32); 4], to: (u32, u32)) -> Result<[f32; 9], Error> {
+    // imageproc::geometric_transformations::Projection has a from_control_points,
+    // but it seems to randomly fail on trivial cases.
+    // This is an implementation of the algorithm used by OpenCV with the solver from nalgebra.
+    // It is much more reliable.
+    let ((x0, y0), (x1, y1), (x2, y2), (x3, y3)) = (
+        (from[0].0 as f64, from[0].1 as f64),
+        (from[1].0 as f64, from[1].1 as f64),
+        (from[2].0 as f64, from[2].1 as f64),
+        (from[3].0 as f64, from[3].1 as f64),
+    );
+    let ((u0, v0), (u1, v1), (u2, v2), (u3, v3)) = (
+        (0.0f64, 0.0f64),
+        (to.0 as f64, 0.0f64),
+        (to.0 as f64, to.1 as f64),
+        (0.0f64, to.1 as f64),
+    );
+
+    let a = Matrix::from_data(ArrayStorage([
+        [x0, x1, x2, x3, 0.0, 0.0, 0.0, 0.0],
+        [y0, y1, y2, y3, 0.0, 0.0, 0.0, 0.0],
+        [1.0, 1.0, 1.0, 1.0, 0.0, 0.0, 0.0, 0.0],
+        [0.0, 0.0, 0.0, 0.0, x0, x1, x2, x3],
+        [0.0, 0.0, 0.0, 0.0, y0, y1, y2, y3],
+        [0.0, 0.0, 0.0, 0.0, 1.0, 1.0, 1.0, 1.0],
+        [
+            -x0 * u0,
+            -x1 * u1,
+            -x2 * u2,
+            -x3 * u3,
+            -x0 * v0,
+            -x1 * v1,
+            -x2 * v2,
+            -x3 * v3,
+        ],
+        [
+            -y0 * u0,
+            -y1 * u1,
+            -y2 * u2,
+            -y3 * u3,
+            -y0 * v0,
+            -y1 * v1,
+            -y2 * v2,
+            -y3 * v3,
+        ],
+    ]));
+    let b = Matrix::from_data(ArrayStorage([[u0, u1, u2, u3, v0, v1, v2, v3]]));
+
+    let svd = a
+        .try_svd(true, true, f64::EPSILON, 1048576)
+        .ok_or_else(|| Error::DegenerateQuad(anyhow!("SVD failed")))?;
+    let x = svd
+        .solve(&b, 0.125)
+        .map_err(|e| Error::DegenerateQuad(anyhow!("Unable to solve for projection: {:?}", e)))?;
+    let x = x.column(0);
+
+    Ok([
+        x[0] as f32,
+        x[1] as f32,
+        x[2] as f32,
+        x[3] as f32,
+        x[4] as f32,
+        x[5] as f32,
+        x[6] as f32,
+        x[7] as f32,
+        1.0,
+    ])
+}
+
+/// Find the position of the black pixel closest to a corner of the image.
+///
+/// # Arguments
+///
+/// * `threshold` - The image to search.
+/// * `flip_x` - `true` if the search should start from the right.
+/// * `flip_y` - `true` if the search should start from the bottom.
+fn find_nearest_to_corner<Image: GenericImageView<Pixel = P>, P: HasBlack + PartialEq>(
+    threshold: &Image,
+    flip_x: bool,
+    flip_y: bool,
+) -> Option<(u32, u32)> {
+    #[derive(Debug)]
+    struct Nearest {
+        square_distance: usize,
+        x: u32,
+        y: u32,
+    }
+    let mut nearest = None;
+    for i in 0..std::cmp::max(threshold.width(), threshold.height()) {
+        let i_squared = i as usize * i as usize;
+        match &nearest {
+            Some(Nearest {
+                square_distance, ..
+            }) if *square_distance < i_squared => break,
+            _ => {}
+        }
+
+        if i < threshold.height() {
+            let real_y = if flip_y {
+                threshold.height() - 1 - i
+            } else {
+                i
+            };
+            for x in 0..std::cmp::min(i + 1, threshold.width()) {
+                let real_x = if flip_x { threshold.width() - 1 - x } else { x };
+                if threshold.get_pixel(real_x, real_y) == P::black() {
+                    let square_distance = x as usize * x as usize + i_squared;
+                    nearest = Some(match nearest {
+                        Some(
+                            v
+                            @
+                            Nearest {
+                                square_distance: c, ..
+                            },
+                        ) if c < square_distance => v,
+                        _ => Nearest {
+                            square_distance,
+                            x: real_x,
+                            y: real_y,
+                        },
+                    });
+                }
+            }
+        }
+        if i < threshold.width() {
+            let real_x = if flip_x { threshold.width() - 1 - i } else { i };
+            for y in 0..std::cmp::min(i, threshold.height()) {
+                let real_y = if flip_y {
+                    threshold.height() - 1 - y
+                } else {
+                    y
+                };
+                if threshold.get_pixel(real_x, real_y) == P::black() {
+                    let square_distance = i_squared + y as usize * y as usize;
+                    nearest = Some(match nearest {
+                        Some(
+                            v
+                            @
+                            Nearest {
+                                square_distance: c, ..
+                            },
+                        ) if c < square_distance => v,
+                        _ => Nearest {
+                            square_distance,
+                            x: real_x,
+                            y: real_y,
+                        },
+                    });
+                }
+            }
+        }
+    }
+
+    nearest.map(|n| (n.x, n.y))
+}
+
+/// Find the black pixels closest to each corner of the image, clockwise from the top left.
+///
+/// The four corners are searched at the same time, except in WebAssembly, where there are no
+/// threads.
+fn find_nearest_to_corners<Image, P>(threshold: &Image) -> [Option<(u32, u32)>; 4]
+where
+    Image: GenericImageView<Pixel = P> + Sync,
+    P: HasBlack + PartialEq,
+{
+    const CORNERS: [(bool, bool); 4] = [(false, false), (true, false), (true, true), (false, true)];
+    #[cfg(not(target_arch = "wasm32"))]
+    let corners = CORNERS.par_iter();
+    #[cfg(target_arch = "wasm32")]
+    let corners = CORNERS.iter();
+    let nearest: Vec<_> = corners
+        .map(|&(flip_x, flip_y)| find_nearest_to_corner(threshold, flip_x, flip_y))
+        .collect();
+    nearest.try_into().unwrap()
+}
+
+/// How to separate border pixels from the rest of the screenshot.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ThresholdMode {
+    /// Compare each pixel to the mean of its neighbors.
+    #[default]
+    Adaptive,
+    /// Compare each pixel to a single level chosen with Otsu's method.
+    ///
+    /// Evenly lit screenshots produce less speckle with this than with `Adaptive`.
+    Otsu,
+}
+
+impl FromStr for ThresholdMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "adaptive" => Ok(ThresholdMode::Adaptive),
+            "otsu" => Ok(ThresholdMode::Otsu),
+            _ => Err(anyhow!("Unknown threshold mode {}", s)),
+        }
+    }
+}
+
+impl ThresholdMode {
+    /// The names accepted by `from_str`.
+    pub const NAMES: &'static [&'static str] = &["adaptive", "otsu"];
+
+    /// Make a black and white image where border pixels are black.
+    ///
+    /// `radius` is how far `Adaptive` looks for neighbors, in pixels.
+    pub(crate) fn apply(self, luma: &GrayImage, radius: u32) -> GrayImage {
+        match self {
+            ThresholdMode::Adaptive => imageproc::contrast::adaptive_threshold(luma, radius),
+            ThresholdMode::Otsu => {
+                let level = imageproc::contrast::otsu_level(luma);
+                imageproc::contrast::threshold(luma, level)
+            }
+        }
+    }
+}
+
+/// A way to find the corners of the photo in a screenshot.
+///
+/// The detectors built into qdcrop are the variants of `Detector`. Other detectors can be added
+/// to `CropOptions::detectors`, where they are tried in order with the built-in ones.
+pub trait CornerDetector: fmt::Debug + Send + Sync {
+    /// The name of the detector as shown to users, such as in sidecars.
+    fn name(&self) -> &'static str;
+
+    /// Find the four corners of the photo in a screenshot, clockwise from the top left.
+    ///
+    /// # Arguments
+    ///
+    /// * `img` - The screenshot.
+    /// * `luma` - The screenshot in grayscale.
+    /// * `threshold` - The screenshot with border pixels in black.
+    /// * `options` - Settings that control detection.
+    ///
+    /// # Errors
+    ///
+    /// An error is returned if the detector cannot find the photo.
+    fn detect(
+        &self,
+        img: &RgbImage,
+        luma: &GrayImage,
+        threshold: &GrayImage,
+        options: &CropOptions,
+    ) -> anyhow::Result<Quad>;
+
+    /// How likely it is that corners found by `detect` are correct, from 0 to 1.
+    ///
+    /// By default this measures how well the corners outline the border in `threshold` and how
+    /// much they look like a photo at the proportions in `options`.
+    fn confidence(&self, threshold: &GrayImage, corners: &Quad, options: &CropOptions) -> f64 {
+        confidence::score(
+            threshold,
+            corners,
+            options.aspect_of(corners, threshold.dimensions()).0,
+        )
+    }
+
+    /// Whether the corners this detector finds can be searched for in windows at the corners
+    /// of the screenshot first, with `CropOptions::corner_window`.
+    ///
+    /// The windowed search finds the border pixels nearest to each corner, so this is only true
+    /// of `Detector::Nearest`.
+    fn supports_windows(&self) -> bool {
+        false
+    }
+}
+
+/// The detectors built into qdcrop.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Detector {
+    /// Use the border pixels nearest to each corner of the screenshot.
+    Nearest,
+    /// Intersect the strongest straight line on each side of the screenshot.
+    Hough,
+    /// Use the largest group of border pixels that outlines a quadrilateral.
+    Contour,
+    /// Like `Nearest`, but corners that are not where the straight sides of the border meet,
+    /// such as corners hidden behind an avatar, are moved there.
+    Edges,
+    /// Fit a line to the outline of the border on each side with RANSAC, ignoring specks.
+    Ransac,
+    /// Ask a machine learning model where the corners are. This needs the `ml` feature, and
+    /// falls back to `Nearest` if no model was given.
+    Onnx,
+}
+
+impl FromStr for Detector {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "nearest" => Ok(Detector::Nearest),
+            "hough" => Ok(Detector::Hough),
+            "contour" => Ok(Detector::Contour),
+            "edges" => Ok(Detector::Edges),
+            "ransac" => Ok(Detector::Ransac),
+            "onnx" => Ok(Detector::Onnx),
+            _ => Err(anyhow!(
+                "Unknown detector {}. Expected one of: {}",
+                s,
+                Detector::NAMES.join(", ")
+            )),
+        }
+    }
+}
+
+impl Detector {
+    /// The names accepted by `from_str`.
+    pub const NAMES: &'static [&'static str] =
+        &["nearest", "hough", "contour", "edges", "ransac", "onnx"];
+
+    /// The name of the detector as shown to users.
+    pub fn name(self) -> &'static str {
+        match self {
+            Detector::Nearest => "nearest",
+            Detector::Hough => "hough",
+            Detector::Contour => "contour",
+            Detector::Edges => "edges",
+            Detector::Ransac => "ransac",
+            Detector::Onnx => "onnx",
+        }
+    }
+}
+
+/// Settings that control how an image is cropped.
+///
+/// The defaults are the defaults of the command line. Build settings with `CropOptions::builder`
+/// to have them checked, or call `validate` after setting fields directly.
+#[derive(Clone, Debug)]
+pub struct CropOptions {
+    /// What to do if the output file already exists.
+    pub conflict: ConflictPolicy,
+    /// How to find border pixels.
+    pub threshold_mode: ThresholdMode,
+    /// How far the adaptive threshold looks for neighbors, in pixels.
+    pub threshold_radius: u32,
+    /// Pixels with this luminance or darker are always border pixels.
+    pub black_threshold: Option<u8>,
+    /// The color of the frame around the photo.
+    pub border_color: BorderColor,
+    /// The largest difference in any channel from `border_color` that is still part of the frame.
+    pub border_tolerance: u8,
+    /// Detectors to try in order until one is confident. `Detector::Nearest` is used if this is
+    /// empty.
+    pub detectors: Vec<Arc<dyn CornerDetector>>,
+    /// The proportions of the photos.
+    pub aspect: AspectMode,
+    /// Whether photos are wider or taller, which turns fixed proportions to match.
+    pub orientation: Orientation,
+    /// The largest size of outputs.
+    pub max_size: MaxSize,
+    /// How the warp reaches the output size.
+    pub scale_mode: ScaleMode,
+    /// The filter used when the warped image is resized separately.
+    pub resize_filter: ResizeFilter,
+    /// How the warp samples the screenshot.
+    pub interpolation: Interpolation,
+    /// Where the warp runs.
+    pub backend: Backend,
+    /// The model used by the `onnx` detector.
+    pub model: Option<Arc<onnx::Model>>,
+    /// Lens distortion to remove from screenshots before detecting corners.
+    pub distortion: Option<distortion::Distortion>,
+    /// Parts of the screenshot to ignore when detecting corners.
+    pub exclude: Vec<Region>,
+    /// Corners of photos to use instead of detecting them.
+    pub corners: Vec<Quad>,
+    /// A directory to save images showing how the photo was detected.
+    pub debug_dir: Option<PathBuf>,
+    /// The smallest part of the screenshot a photo may cover, in percent.
+    pub min_area: f64,
+    /// The lowest detection confidence that is warped.
+    pub min_confidence: f64,
+    /// What to do with files below `min_confidence`.
+    pub low_confidence: LowConfidence,
+    /// Whether to decode each output after writing it to check that it was written correctly.
+    pub verify_outputs: bool,
+    /// Whether photo frames have rounded corners.
+    pub rounded_corners: bool,
+    /// Whether to turn corners to be level with the border around the photo.
+    pub straighten: bool,
+    /// How far to move the edges of photos outward before warping.
+    pub margin: Margin,
+    /// Whether to only turn photos upright instead of correcting their perspective.
+    pub deskew_only: bool,
+    /// Whether to cut out the rectangle around photos instead of correcting their perspective.
+    pub no_warp: bool,
+    /// Whether to crop outputs to the part of the photo that is inside the screenshot.
+    pub inscribed: bool,
+    /// Whether to cut off dark lines left by the frame along the edges of outputs.
+    pub trim_edges: bool,
+    /// How far to turn outputs clockwise, in degrees.
+    pub rotate: u32,
+    /// Whether to mirror outputs left to right.
+    pub flip_horizontal: bool,
+    /// Whether to mirror outputs top to bottom.
+    pub flip_vertical: bool,
+    /// Whether to turn outputs whose text is sideways or upside down.
+    pub auto_rotate: bool,
+    /// Whether to fail instead of detecting corners that were not given.
+    pub warp_only: bool,
+    /// Whether to crop every photo in each screenshot instead of only one.
+    pub multiple: bool,
+    /// The WebP quality of outputs, from 0 to 100.
+    pub quality: f32,
+    /// Whether to encode with the fastest settings instead of the smallest output.
+    pub fast_encode: bool,
+    /// Whether to leave all EXIF out of outputs, and copy nothing else from inputs.
+    pub strip_metadata: bool,
+    /// Whether to write a JSON sidecar recording where each photo was found.
+    pub json_sidecar: bool,
+    /// Whether to warp the corners in existing JSON sidecars instead of detecting them.
+    pub use_sidecar: bool,
+    /// Whether to write an XMP sidecar recording how each output was made.
+    pub xmp_sidecar: bool,
+    /// Whether to give outputs the modification times of their inputs.
+    pub preserve_times: bool,
+    /// Whether to also write a Deep Zoom tile pyramid of each output.
+    pub tile_pyramid: bool,
+    /// The longest side of the copy of the screenshot used for detection.
+    pub detect_size: Option<u32>,
+    /// The size of the windows at the corners of the screenshot that the nearest detector
+    /// searches first, as a percentage of its width and height.
+    pub corner_window: Option<f32>,
+    /// Whether photos that fill the whole screenshot are kept as they are instead of warped.
+    pub skip_cropped: bool,
+}
+
+impl Default for CropOptions {
+    fn default() -> Self {
+        CropOptions {
+            conflict: ConflictPolicy::default(),
+            threshold_mode: ThresholdMode::default(),
+            threshold_radius: 2,
+            black_threshold: None,
+            border_color: BorderColor::default(),
+            border_tolerance: 40,
+            detectors: Vec::new(),
+            aspect: AspectMode::default(),
+            orientation: Orientation::default(),
+            max_size: MaxSize::default(),
+            scale_mode: ScaleMode::default(),
+            resize_filter: ResizeFilter::default(),
+            interpolation: Interpolation::default(),
+            backend: Backend::detect(),
+            model: None,
+            distortion: None,
+            exclude: Vec::new(),
+            corners: Vec::new(),
+            debug_dir: None,
+            min_area: 5.0,
+            min_confidence: 0.0,
+            low_confidence: LowConfidence::default(),
+            verify_outputs: false,
+            rounded_corners: false,
+            straighten: false,
+            margin: Margin::default(),
+            deskew_only: false,
+            no_warp: false,
+            inscribed: false,
+            trim_edges: false,
+            rotate: 0,
+            flip_horizontal: false,
+            flip_vertical: false,
+            auto_rotate: false,
+            warp_only: false,
+            multiple: false,
+            quality: 95.0,
+            fast_encode: false,
+            strip_metadata: false,
+            json_sidecar: false,
+            use_sidecar: false,
+            xmp_sidecar: false,
+            preserve_times: false,
+            tile_pyramid: false,
+            detect_size: None,
+            corner_window: None,
+            skip_cropped: false,
+        }
+    }
+}
+
+impl CropOptions {
+    /// Start building settings from the defaults.
+    pub fn builder() -> CropOptionsBuilder {
+        CropOptionsBuilder::default()
+    }
+
+    /// Check that the settings are in range.
+    ///
+    /// # Errors
+    ///
+    /// `Error::InvalidOptions` is returned describing the first setting that is out of range.
+    pub fn validate(&self) -> Result<(), Error> {
+        let invalid = |message: &str| Err(Error::InvalidOptions(message.to_owned()));
+        if !(0.0..=100.0).contains(&self.quality) {
+            return invalid("Quality must be a number from 0 to 100");
+        }
+        if self.threshold_radius == 0 {
+            return invalid("The threshold radius must be at least 1");
+        }
+        if !(0.0..=100.0).contains(&self.min_area) {
+            return invalid("The minimum area must be a percentage from 0 to 100");
+        }
+        if !(0.0..=1.0).contains(&self.min_confidence) {
+            return invalid("The minimum confidence must be a number from 0 to 1");
+        }
+        if let AspectMode::Fixed(Aspect(aspect)) = self.aspect {
+            if !(aspect > 0.0 && aspect.is_finite()) {
+                return invalid("The aspect ratio must be positive");
+            }
+        }
+        if [self.max_size.width, self.max_size.height]
+            .iter()
+            .flatten()
+            .any(|max| max.is_nan() || *max < 1.0)
+        {
+            return invalid("The maximum size must be at least 1 pixel");
+        }
+        if self.detect_size == Some(0) {
+            return invalid("The detection size must be at least 1 pixel");
+        }
+        if let Some(percent) = self.corner_window {
+            if percent.is_nan() || percent <= 0.0 || percent > 50.0 {
+                return invalid("The corner window must be a percentage from 0 to 50");
+            }
+        }
+        if self.rotate % 90 != 0 || self.rotate >= 360 {
+            return invalid("Rotation must be 0, 90, 180, or 270 degrees");
+        }
+        Ok(())
+    }
+
+    /// Find the corners to warp for a detected photo, after `margin` and `deskew_only`.
+    ///
+    /// # Arguments
+    ///
+    /// * `quad` - The detected corners of the photo.
+    /// * `size` - The size of the screenshot the corners are in.
+    ///
+    /// # Errors
+    ///
+    /// An error is returned if the margin leaves nothing of the photo.
+    pub fn warp_corners(&self, quad: &Quad, size: (u32, u32)) -> anyhow::Result<Quad> {
+        let corners = self.margin.apply(quad)?;
+        Ok(if self.deskew_only {
+            deskew(&corners, self.aspect_of(&corners, size))
+        } else {
+            corners
+        })
+    }
+
+    /// Choose the proportions of one photo, turned to match the orientation of its corners.
+    ///
+    /// # Arguments
+    ///
+    /// * `quad` - The corners of the photo.
+    /// * `size` - The size of the screenshot the corners are in.
+    pub fn aspect_of(&self, quad: &Quad, size: (u32, u32)) -> Aspect {
+        let aspect = self.aspect.resolve(quad, size);
+        // Estimated proportions already have the right orientation.
+        if self.aspect == AspectMode::Auto {
+            return aspect;
+        }
+        let portrait = match self.orientation {
+            Orientation::Landscape => false,
+            Orientation::Portrait => true,
+            Orientation::Auto => {
+                let height = f32::max(quad[3].1 - quad[0].1, quad[2].1 - quad[1].1);
+                let width = f32::max(quad[1].0 - quad[0].0, quad[2].0 - quad[3].0);
+                height > width
+            }
+        };
+        if portrait == (aspect.0 > 1.0) {
+            Aspect(1.0 / aspect.0)
+        } else {
+            aspect
+        }
+    }
+
+    /// Move corners found on the arcs of rounded photo frames to where the straight sides meet,
+    /// then turn them to be level with the border.
+    ///
+    /// Each step does nothing unless `rounded_corners` or `straighten` is set.
+    fn square_corners(&self, threshold: &GrayImage, corners: Quad) -> Quad {
+        let corners = if self.rounded_corners {
+            edges::infer_corners(threshold, &corners)
+        } else {
+            corners
+        };
+        if self.straighten {
+            edges::straighten(threshold, &corners)
+        } else {
+            corners
+        }
+    }
+
+    /// The detectors to try in order. This is `nearest` alone unless others were chosen.
+    fn detectors(&self) -> Vec<&dyn CornerDetector> {
+        if self.detectors.is_empty() {
+            vec![&Detector::Nearest]
+        } else {
+            self.detectors.iter().map(|detector| &**detector).collect()
+        }
+    }
+}
+
+/// Builds `CropOptions`, starting from the defaults and checking them in `build`.
+///
+/// ```
+/// let options = qdcrop::CropOptions::builder()
+///     .aspect("4:3".parse()?)
+///     .quality(90.0)
+///     .build()?;
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct CropOptionsBuilder {
+    options: CropOptions,
+}
+
+impl CropOptionsBuilder {
+    /// Set how border pixels are found.
+    pub fn threshold_mode(mut self, threshold_mode: ThresholdMode) -> Self {
+        self.options.threshold_mode = threshold_mode;
+        self
+    }
+
+    /// Set how far the adaptive threshold looks for neighbors, in pixels.
+    pub fn threshold_radius(mut self, threshold_radius: u32) -> Self {
+        self.options.threshold_radius = threshold_radius;
+        self
+    }
+
+    /// Set the color of the frame around the photo.
+    pub fn border_color(mut self, border_color: BorderColor) -> Self {
+        self.options.border_color = border_color;
+        self
+    }
+
+    /// Set the built-in detectors to try in order until one is confident.
+    pub fn detectors(mut self, detectors: impl IntoIterator<Item = Detector>) -> Self {
+        self.options.detectors = detectors
+            .into_iter()
+            .map(|detector| Arc::new(detector) as Arc<dyn CornerDetector>)
+            .collect();
+        self
+    }
+
+    /// Add a detector to try after the ones already set.
+    pub fn detector(mut self, detector: impl CornerDetector + 'static) -> Self {
+        self.options.detectors.push(Arc::new(detector));
+        self
+    }
+
+    /// Set the corners of photos to use instead of detecting them.
+    pub fn corners(mut self, corners: impl IntoIterator<Item = Quad>) -> Self {
+        self.options.corners = corners.into_iter().collect();
+        self
+    }
+
+    /// Set whether to crop every photo in each screenshot instead of only one.
+    pub fn multiple(mut self, multiple: bool) -> Self {
+        self.options.multiple = multiple;
+        self
+    }
+
+    /// Set the lowest detection confidence that is warped.
+    pub fn min_confidence(mut self, min_confidence: f64) -> Self {
+        self.options.min_confidence = min_confidence;
+        self
+    }
+
+    /// Set the proportions of the photos.
+    pub fn aspect(mut self, aspect: AspectMode) -> Self {
+        self.options.aspect = aspect;
+        self
+    }
+
+    /// Set whether photos are wider or taller.
+    pub fn orientation(mut self, orientation: Orientation) -> Self {
+        self.options.orientation = orientation;
+        self
+    }
+
+    /// Set how far to move the edges of photos outward before warping.
+    pub fn margin(mut self, margin: Margin) -> Self {
+        self.options.margin = margin;
+        self
+    }
+
+    /// Set the largest size of outputs.
+    pub fn max_size(mut self, max_size: MaxSize) -> Self {
+        self.options.max_size = max_size;
+        self
+    }
+
+    /// Set how the warp samples the screenshot.
+    pub fn interpolation(mut self, interpolation: Interpolation) -> Self {
+        self.options.interpolation = interpolation;
+        self
+    }
+
+    /// Set where the warp runs.
+    pub fn backend(mut self, backend: Backend) -> Self {
+        self.options.backend = backend;
+        self
+    }
+
+    /// Set how the warp reaches the output size.
+    pub fn scale_mode(mut self, scale_mode: ScaleMode) -> Self {
+        self.options.scale_mode = scale_mode;
+        self
+    }
+
+    /// Set the filter used when the warped image is resized separately.
+    pub fn resize_filter(mut self, resize_filter: ResizeFilter) -> Self {
+        self.options.resize_filter = resize_filter;
+        self
+    }
+
+    /// Set the WebP quality of outputs, from 0 to 100.
+    pub fn quality(mut self, quality: f32) -> Self {
+        self.options.quality = quality;
+        self
+    }
+
+    /// Set whether to encode with the fastest settings instead of the smallest output.
+    pub fn fast_encode(mut self, fast_encode: bool) -> Self {
+        self.options.fast_encode = fast_encode;
+        self
+    }
+
+    /// Check the settings and finish building them.
+    ///
+    /// # Errors
+    ///
+    /// `Error::InvalidOptions` is returned if a setting is out of range.
+    pub fn build(self) -> Result<CropOptions, Error> {
+        self.options.validate()?;
+        Ok(self.options)
+    }
+}
+
+/// The corners of a photo, clockwise from the top left.
+pub type Quad = [(f32, f32); 4];
+
+/// The photo found in a screenshot.
+#[derive(Clone, Copy, Debug)]
+pub struct Detection {
+    /// The corners of the photo.
+    pub corners: Quad,
+    /// How likely it is that the corners are correct, from 0 to 1.
+    pub confidence: f64,
+    /// The name of the detector that found the corners, or `None` if they were given by the
+    /// user.
+    pub detector: Option<&'static str>,
+}
+
+/// Parse corners written as `x0,y0,x1,y1,x2,y2,x3,y3`.
+///
+/// # Errors
+///
+/// An error is returned if there are not exactly eight numbers.
+pub fn parse_quad(s: &str) -> anyhow::Result<Quad> {
+    let values = s
+        .split(',')
+        .map(|v| v.trim().parse::<f32>())
+        .collect::<Result<Vec<_>, _>>()
+        .context("Corners must be numbers")?;
+    if values.len() != 8 {
+        bail!(
+            "Expected 8 numbers for 4 corners, but found {}",
+            values.len()
+        );
+    }
+    Ok([
+        (values[0], values[1]),
+        (values[2], values[3]),
+        (values[4], values[5]),
+        (values[6], values[7]),
+    ])
+}
+
+/// Find the corners of a photo from a projection written as `a,b,c,d,e,f,g,h`.
+///
+/// The numbers are the first eight entries of a 3x3 matrix, row by row, that maps screenshot
+/// coordinates to output coordinates. The last entry is 1.
+///
+/// # Arguments
+///
+/// * `s` - The matrix.
+/// * `size` - The width and height of the output the matrix maps to.
+///
+/// # Errors
+///
+/// An error is returned if there are not exactly eight numbers, or if the matrix cannot be
+/// inverted.
+pub fn parse_matrix(s: &str, size: (f64, f64)) -> anyhow::Result<Quad> {
+    let values = s
+        .split(',')
+        .map(|v| v.trim().parse::<f32>())
+        .collect::<Result<Vec<_>, _>>()
+        .context("The matrix must be numbers")?;
+    let values: [f32; 8] = values.as_slice().try_into().map_err(|_| {
+        anyhow!(
+            "Expected 8 numbers for the matrix, but found {}",
+            values.len()
+        )
+    })?;
+    let [a, b, c, d, e, f, g, h] = values;
+    let projection = Projection::from_matrix([a, b, c, d, e, f, g, h, 1.0])
+        .context("The matrix cannot be inverted")?
+        .invert();
+    let (width, height) = (size.0 as f32, size.1 as f32);
+    Ok([(0.0, 0.0), (width, 0.0), (width, height), (0.0, height)].map(|point| projection * point))
+}
+
+/// A rectangle of the screenshot, in pixels.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Region {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Region {
+    /// Set every pixel of the region that is inside an image to `value`.
+    pub(crate) fn fill(self, img: &mut GrayImage, value: u8) {
+        let right = std::cmp::min(self.x.saturating_add(self.width), img.width());
+        let bottom = std::cmp::min(self.y.saturating_add(self.height), img.height());
+        for y in self.y..bottom {
+            for x in self.x..right {
+                img.put_pixel(x, y, image::Luma([value]));
+            }
+        }
+    }
+
+    /// Scale the region to a resized copy of the screenshot, covering every partial pixel.
+    fn scale(self, sx: f64, sy: f64) -> Region {
+        let x = (self.x as f64 * sx).floor() as u32;
+        let y = (self.y as f64 * sy).floor() as u32;
+        Region {
+            x,
+            y,
+            width: (self.x.saturating_add(self.width) as f64 * sx).ceil() as u32 - x,
+            height: (self.y.saturating_add(self.height) as f64 * sy).ceil() as u32 - y,
+        }
+    }
+}
+
+/// Parse a region written as `x,y,width,height`.
+///
+/// # Errors
+///
+/// An error is returned if there are not exactly four whole numbers.
+pub fn parse_region(s: &str) -> anyhow::Result<Region> {
+    let values = s
+        .split(',')
+        .map(|v| v.trim().parse::<u32>())
+        .collect::<Result<Vec<_>, _>>()
+        .context("Regions must be whole numbers")?;
+    match values[..] {
+        [x, y, width, height] => Ok(Region {
+            x,
+            y,
+            width,
+            height,
+        }),
+        _ => bail!(
+            "Expected 4 numbers for x,y,width,height, but found {}",
+            values.len()
+        ),
+    }
+}
+
+impl CornerDetector for Detector {
+    fn name(&self) -> &'static str {
+        Detector::name(*self)
+    }
+
+    fn supports_windows(&self) -> bool {
+        *self == Detector::Nearest
+    }
+
+    fn detect(
+        &self,
+        img: &RgbImage,
+        luma: &GrayImage,
+        threshold: &GrayImage,
+        options: &CropOptions,
+    ) -> anyhow::Result<Quad> {
+        match self {
+            Detector::Nearest => {
+                let [top_left, top_right, bottom_right, bottom_left] =
+                    find_nearest_to_corners(threshold);
+                // Every search finds a black pixel if there is one.
+                let closest = [
+                    top_left.context("No interesting points")?,
+                    top_right.unwrap(),
+                    bottom_right.unwrap(),
+                    bottom_left.unwrap(),
+                ];
+                Ok(closest.map(|p| (p.0 as f32, p.1 as f32)))
+            }
+            Detector::Hough => {
+                hough::detect(luma, &options.exclude).context("Could not find four border lines")
+            }
+            Detector::Contour => {
+                contour::detect(threshold).context("Could not find a quadrilateral border")
+            }
+            Detector::Edges => {
+                let nearest = Detector::Nearest.detect(img, luma, threshold, options)?;
+                Ok(edges::infer_corners(threshold, &nearest))
+            }
+            Detector::Ransac => edges::ransac(threshold).context("Could not fit four border lines"),
+            Detector::Onnx => match &options.model {
+                Some(model) => model.detect(luma),
+                None => Detector::Nearest.detect(img, luma, threshold, options),
+            },
+        }
+    }
+}
+
+/// Find the four corners of the photo in a screenshot.
+///
+/// Each detector in `options` is tried in order until one finds corners with at least the
+/// minimum confidence. If none do, the most confident result is returned. When the border color
+/// is chosen automatically, this is repeated for each candidate color.
+///
+/// With `options.corner_window`, the nearest detector first thresholds only windows at the
+/// corners of the screenshot, and the whole screenshot is only thresholded if that fails.
+///
+/// # Arguments
+///
+/// * `img` - The screenshot.
+/// * `luma` - The screenshot in grayscale.
+/// * `options` - Settings that control detection.
+///
+/// # Errors
+///
+/// `Error::DetectionFailed` is returned if no detector can find the photo.
+pub fn detect_corners(
+    img: &RgbImage,
+    luma: &GrayImage,
+    options: &CropOptions,
+) -> Result<Detection, Error> {
+    // Only the nearest detector looks no further than the corners.
+    let windowed = options.border_color == BorderColor::Black
+        && !options.rounded_corners
+        && !options.straighten
+        && options.detectors()[0].supports_windows();
+    if let (Some(percent), true) = (options.corner_window, windowed) {
+        if let Some(detection) = window::detect_nearest(luma, options, percent) {
+            return Ok(detection);
+        }
+    }
+    let mut best: Option<Detection> = None;
+    let mut error = None;
+    for mask in border::masks(img, luma, options) {
+        let threshold = &mask;
+        for detector in options.detectors() {
+            let corners = detector
+                .detect(img, luma, threshold, options)
+                .and_then(|corners| {
+                    let corners = options.square_corners(threshold, corners);
+                    validate::validate(&corners, luma.dimensions(), options.min_area)?;
+                    Ok(corners)
+                });
+            match corners {
+                Ok(corners) => {
+                    let detection = Detection {
+                        corners,
+                        confidence: detector.confidence(threshold, &corners, options),
+                        detector: Some(detector.name()),
+                    };
+                    if detection.confidence >= options.min_confidence {
+                        pool::recycle(mask);
+                        return Ok(detection);
+                    }
+                    if best.is_none_or(|b| detection.confidence > b.confidence) {
+                        best = Some(detection);
+                    }
+                }
+                Err(e) => error = Some(e),
+            }
+        }
+        pool::recycle(mask);
+    }
+    match (best, error) {
+        (Some(best), _) => Ok(best),
+        (None, Some(error)) => Err(Error::DetectionFailed(error)),
+        (None, None) => Err(Error::InvalidOptions(
+            "No detectors were selected".to_owned(),
+        )),
+    }
+}
+
+/// Find the photos in a screenshot.
+///
+/// Unless `options.multiple` is set, this is the one photo found by `detect_corners`. Otherwise
+/// every outline found by the contour detector is returned, leaving out those below the minimum
+/// confidence. If none are confident enough, the most confident one is returned.
+///
+/// # Errors
+///
+/// An error is returned if no photos can be found.
+fn detect_photos(
+    img: &RgbImage,
+    luma: &GrayImage,
+    options: &CropOptions,
+) -> Result<Vec<Detection>, Error> {
+    if !options.multiple {
+        return Ok(vec![detect_corners(img, luma, options)?]);
+    }
+    let mut best: Option<Detection> = None;
+    for threshold in border::masks(img, luma, options) {
+        let detections: Vec<_> = contour::detect_all(&threshold)
+            .into_iter()
+            .map(|corners| options.square_corners(&threshold, corners))
+            .map(|corners| Detection {
+                corners,
+                confidence: confidence::score(
+                    &threshold,
+                    &corners,
+                    options.aspect_of(&corners, threshold.dimensions()).0,
+                ),
+                detector: Some(Detector::Contour.name()),
+            })
+            .collect();
+        let confident: Vec<_> = detections
+            .iter()
+            .copied()
+            .filter(|d| d.confidence >= options.min_confidence)
+            .collect();
+        pool::recycle(threshold);
+        if !confident.is_empty() {
+            return Ok(confident);
+        }
+        for detection in detections {
+            if best.is_none_or(|b| detection.confidence > b.confidence) {
+                best = Some(detection);
+            }
+        }
+    }
+    match best {
+        Some(best) => Ok(vec![best]),
+        None => Err(Error::DetectionFailed(anyhow!("Could not find any photos"))),
+    }
+}
+
+/// Find the photos in a screenshot, using a smaller copy of the screenshot if it is large.
+///
+/// Thresholding and searching dominate the time taken to process 4K and 8K screenshots, and
+/// the corners found at a lower resolution are usually close enough.
+///
+/// # Errors
+///
+/// `Error::DetectionFailed` is returned if the detector cannot find the photo.
+pub fn detect_scaled(img: &RgbImage, options: &CropOptions) -> Result<Vec<Detection>, Error> {
+    let (width, height) = img.dimensions();
+    let longest = std::cmp::max(width, height);
+    let max = match options.detect_size {
+        Some(max) if max < longest => max,
+        _ => {
+            let luma = pool::grayscale(img);
+            let detections = detect_photos(img, &luma, options);
+            pool::recycle(luma);
+            return detections;
+        }
+    };
+
+    let scale = max as f64 / longest as f64;
+    let small = image::imageops::resize(
+        img,
+        std::cmp::max((width as f64 * scale).round() as u32, 1),
+        std::cmp::max((height as f64 * scale).round() as u32, 1),
+        FilterType::Triangle,
+    );
+    let (sx, sy) = (
+        width as f32 / small.width() as f32,
+        height as f32 / small.height() as f32,
+    );
+    let mut options = options.clone();
+    for region in &mut options.exclude {
+        *region = region.scale(1.0 / sx as f64, 1.0 / sy as f64);
+    }
+    let luma = pool::grayscale(&small);
+    let detections = detect_photos(&small, &luma, &options);
+    pool::recycle(luma);
+    let mut detections = detections?;
+    for detection in &mut detections {
+        // Map pixel centers rather than pixel corners.
+        detection.corners = detection
+            .corners
+            .map(|(x, y)| ((x + 0.5) * sx - 0.5, (y + 0.5) * sy - 0.5));
+    }
+    Ok(detections)
+}
+
+/// The proportions of the photos, as width divided by height.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aspect(pub f64);
+
+impl Default for Aspect {
+    fn default() -> Self {
+        Aspect(16.0 / 9.0)
+    }
+}
+
+impl FromStr for Aspect {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || anyhow!("Invalid aspect ratio {}. Expected W:H, like 4:3", s);
+        let (width, height) = s.split_once(':').ok_or_else(invalid)?;
+        let width: f64 = width.trim().parse().map_err(|_| invalid())?;
+        let height: f64 = height.trim().parse().map_err(|_| invalid())?;
+        if !(width > 0.0 && height > 0.0 && width.is_finite() && height.is_finite()) {
+            return Err(invalid());
+        }
+        Ok(Aspect(width / height))
+    }
+}
+
+/// How the proportions of the photos are chosen.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AspectMode {
+    /// Every photo has the same proportions.
+    Fixed(Aspect),
+    /// The proportions of each photo are estimated from the perspective of its corners.
+    Auto,
+}
+
+impl Default for AspectMode {
+    fn default() -> Self {
+        AspectMode::Fixed(Aspect::default())
+    }
+}
+
+impl FromStr for AspectMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(AspectMode::Auto),
+            _ => Ok(AspectMode::Fixed(s.parse()?)),
+        }
+    }
+}
+
+impl AspectMode {
+    /// The most extreme proportions that an estimate is trusted with.
+    const PLAUSIBLE: std::ops::RangeInclusive<f64> = 0.2..=5.0;
+
+    /// Choose the proportions of one photo.
+    ///
+    /// `Auto` falls back to the default proportions when the estimate fails or is implausible.
+    ///
+    /// # Arguments
+    ///
+    /// * `quad` - The corners of the photo.
+    /// * `size` - The size of the screenshot the corners are in.
+    fn resolve(self, quad: &Quad, size: (u32, u32)) -> Aspect {
+        match self {
+            AspectMode::Fixed(aspect) => aspect,
+            AspectMode::Auto => aspect::estimate(quad, size)
+                .filter(|r| Self::PLAUSIBLE.contains(r))
+                .map_or_else(Aspect::default, Aspect),
+        }
+    }
+}
+
+/// Whether photos are wider or taller.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Orientation {
+    /// Photos whose corners are taller than they are wide are portrait, and others landscape.
+    #[default]
+    Auto,
+    /// Photos are wider than they are tall.
+    Landscape,
+    /// Photos are taller than they are wide.
+    Portrait,
+}
+
+impl FromStr for Orientation {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(Orientation::Auto),
+            "landscape" => Ok(Orientation::Landscape),
+            "portrait" => Ok(Orientation::Portrait),
+            _ => Err(anyhow!("Unknown orientation {}", s)),
+        }
+    }
+}
+
+impl Orientation {
+    /// The names accepted by `from_str`.
+    pub const NAMES: &'static [&'static str] = &["auto", "landscape", "portrait"];
+}
+
+/// How far to move the edges of photos outward before warping. Negative margins move them inward.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Margin {
+    /// A distance in pixels of the screenshot.
+    Pixels(f32),
+    /// A fraction of the width and height of the photo, in percent.
+    Percent(f32),
+}
+
+impl Default for Margin {
+    fn default() -> Self {
+        Margin::Pixels(0.0)
+    }
+}
+
+impl FromStr for Margin {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            anyhow!(
+                "Invalid margin {}. Expected pixels or a percentage, like -2 or 1%",
+                s
+            )
+        };
+        match s.strip_suffix('%') {
+            Some(percent) => Ok(Margin::Percent(
+                percent.trim().parse().map_err(|_| invalid())?,
+            )),
+            None => Ok(Margin::Pixels(
+                s.trim_end_matches("px")
+                    .trim()
+                    .parse()
+                    .map_err(|_| invalid())?,
+            )),
+        }
+    }
+}
+
+impl Margin {
+    /// Move the corners of a photo so that each edge moves by the margin.
+    ///
+    /// The corners move along the perspective of the photo, so the edges stay parallel to the
+    /// photo's edges. Pixel margins are measured at the average size of the photo.
+    ///
+    /// # Errors
+    ///
+    /// An error is returned if a negative margin would leave nothing of the photo.
+    fn apply(self, quad: &Quad) -> anyhow::Result<Quad> {
+        let length = |a: (f32, f32), b: (f32, f32)| (a.0 - b.0).hypot(a.1 - b.1);
+        let (x, y) = match self {
+            Margin::Pixels(pixels) if pixels == 0.0 => return Ok(*quad),
+            Margin::Pixels(pixels) => (
+                pixels * 2.0 / (length(quad[0], quad[1]) + length(quad[3], quad[2])),
+                pixels * 2.0 / (length(quad[0], quad[3]) + length(quad[1], quad[2])),
+            ),
+            Margin::Percent(percent) => (percent / 100.0, percent / 100.0),
+        };
+        if x <= -0.5 || y <= -0.5 {
+            bail!("The margin is larger than half of the photo");
+        }
+        let projection = from_control_points(*quad, (1, 1))?.invert();
+        Ok([(-x, -y), (1.0 + x, -y), (1.0 + x, 1.0 + y), (-x, 1.0 + y)]
+            .map(|point| projection * point))
+    }
+}
+
+/// Fit a rectangle with the proportions of `aspect` inside a photo, turned to the photo's angle.
+///
+/// Warping the rectangle only turns and scales the screenshot, without correcting perspective.
+pub fn deskew(quad: &Quad, aspect: Aspect) -> Quad {
+    let length = |a: (f32, f32), b: (f32, f32)| (a.0 - b.0).hypot(a.1 - b.1);
+    // Average the direction of every edge, turning the left and right edges to horizontal.
+    let (mut x, mut y) = (0.0, 0.0);
+    for (a, b, vertical) in [
+        (quad[0], quad[1], false),
+        (quad[3], quad[2], false),
+        (quad[0], quad[3], true),
+        (quad[1], quad[2], true),
+    ] {
+        let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+        let (dx, dy) = if vertical { (dy, -dx) } else { (dx, dy) };
+        let length = dx.hypot(dy);
+        x += dx / length;
+        y += dy / length;
+    }
+    let (sin, cos) = y.atan2(x).sin_cos();
+
+    let mut width = (length(quad[0], quad[1]) + length(quad[3], quad[2])) / 2.0;
+    let mut height = (length(quad[0], quad[3]) + length(quad[1], quad[2])) / 2.0;
+    let aspect = aspect.0 as f32;
+    if width / height > aspect {
+        width = height * aspect;
+    } else {
+        height = width / aspect;
+    }
+    let center = (
+        quad.iter().map(|p| p.0).sum::<f32>() / 4.0,
+        quad.iter().map(|p| p.1).sum::<f32>() / 4.0,
+    );
+    [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)].map(|(u, v): (f32, f32)| {
+        let (u, v) = (u * width / 2.0, v * height / 2.0);
+        (center.0 + u * cos - v * sin, center.1 + u * sin + v * cos)
+    })
+}
+
+/// Find the size of the photo at the resolution of the screenshot, stretched to `aspect`.
+fn natural_size(closest: &Quad, aspect: Aspect) -> (f64, f64) {
+    let height = f32::max(closest[3].1 - closest[0].1, closest[2].1 - closest[1].1) as f64;
+    let width = f32::max(closest[1].0 - closest[0].0, closest[2].0 - closest[3].0) as f64;
+    let height_aspect = width / aspect.0;
+    let width_aspect = height * aspect.0;
+    if height_aspect < height {
+        (width_aspect, height)
+    } else {
+        (width, height_aspect)
+    }
+}
+
+/// The largest size of outputs. Larger photos are shrunk to fit, keeping their proportions.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MaxSize {
+    /// The widest output, or `None` for no limit.
+    pub width: Option<f64>,
+    /// The tallest output, or `None` for no limit.
+    pub height: Option<f64>,
+}
+
+impl Default for MaxSize {
+    fn default() -> Self {
+        MaxSize {
+            width: Some(1024.0 * 16.0 / 9.0),
+            height: Some(1024.0),
+        }
+    }
+}
+
+impl MaxSize {
+    /// No limit, so outputs keep the resolution of the photo in the screenshot.
+    pub const NONE: MaxSize = MaxSize {
+        width: None,
+        height: None,
+    };
+
+    /// Build a limit from the largest width and height given by the user.
+    ///
+    /// When only one is given, the other is derived from it at `aspect`. When neither is given,
+    /// this is the default.
+    pub fn from_limits(width: Option<u32>, height: Option<u32>, aspect: Aspect) -> Self {
+        let (width, height) = (width.map(f64::from), height.map(f64::from));
+        match (width, height) {
+            (None, None) => MaxSize::default(),
+            (Some(width), None) => MaxSize {
+                width: Some(width),
+                height: Some(width / aspect.0),
+            },
+            (None, Some(height)) => MaxSize {
+                width: Some(height * aspect.0),
+                height: Some(height),
+            },
+            (width, height) => MaxSize { width, height },
+        }
+    }
+}
+
+/// Choose the size of the output image for a set of corners.
+///
+/// The output has the proportions of `aspect` and is no larger than `max_size`.
+pub fn output_size(closest: &Quad, aspect: Aspect, max_size: MaxSize) -> (u32, u32) {
+    let (width, height) = natural_size(closest, aspect);
+
+    let height_ratio = max_size.height.map_or(f64::INFINITY, |max| max / height);
+    let width_ratio = max_size.width.map_or(f64::INFINITY, |max| max / width);
+    let ratio = height_ratio.min(width_ratio).min(1.0);
+
+    (
+        (width * ratio).round() as u32,
+        (height * ratio).round() as u32,
+    )
+}
+
+/// Fill each row of an image with `f`, which is given the row number and the row's pixels.
+///
+/// Rows are filled in parallel, except in WebAssembly, where there are no threads.
+pub(crate) fn for_each_row(img: &mut RgbImage, f: impl Fn(usize, &mut [u8]) + Send + Sync) {
+    let row_size = img.width() as usize * 3;
+    #[cfg(not(target_arch = "wasm32"))]
+    img.par_chunks_mut(row_size)
+        .enumerate()
+        .for_each(|(y, row)| f(y, row));
+    #[cfg(target_arch = "wasm32")]
+    img.chunks_mut(row_size)
+        .enumerate()
+        .for_each(|(y, row)| f(y, row));
+}
+
+/// The size of the square tiles that `for_each_tile` fills, in pixels. A tile of an output and
+/// the part of the screenshot it samples fit in the caches of one core.
+const TILE: usize = 128;
+
+/// A square of an image being filled by `for_each_tile`. Tiles at the right and bottom edges
+/// may be smaller.
+pub(crate) struct Tile<'a> {
+    /// The column of the left edge of the tile in the image.
+    pub x: u32,
+    /// The row of the top edge of the tile in the image.
+    pub y: u32,
+    /// The pixels of each row of the tile, from the top.
+    pub rows: Vec<&'a mut [u8]>,
+}
+
+impl Tile<'_> {
+    /// The width of the tile in pixels.
+    pub fn width(&self) -> u32 {
+        (self.rows[0].len() / 3) as u32
+    }
+
+    /// Whether the whole tile maps to points more than `margin` pixels outside a source of
+    /// `size`, so that none of its pixels sample the source.
+    ///
+    /// `map` takes output coordinates to source coordinates. The corners of the tile are
+    /// mapped, which bounds the whole tile since projections keep lines straight.
+    pub fn is_outside(
+        &self,
+        map: impl Fn((f32, f32)) -> (f32, f32),
+        size: (u32, u32),
+        margin: f32,
+    ) -> bool {
+        let (x0, y0) = (self.x as f32, self.y as f32);
+        let (x1, y1) = (x0 + self.width() as f32, y0 + self.rows.len() as f32);
+        let corners = [(x0, y0), (x1, y0), (x1, y1), (x0, y1)].map(map);
+        let (mut left, mut top, mut right, mut bottom) = (
+            f32::INFINITY,
+            f32::INFINITY,
+            f32::NEG_INFINITY,
+            f32::NEG_INFINITY,
+        );
+        for (x, y) in corners {
+            (left, top) = (left.min(x), top.min(y));
+            (right, bottom) = (right.max(x), bottom.max(y));
+        }
+        right < -margin
+            || bottom < -margin
+            || left > size.0 as f32 + margin
+            || top > size.1 as f32 + margin
+    }
+}
+
+/// Fill an image in square tiles with `f`.
+///
+/// Tiles are filled in parallel, except in WebAssembly, where there are no threads. Compared
+/// to `for_each_row`, the pixels sampled for neighboring output pixels stay in the cache, and
+/// large outputs are split into more pieces of work.
+pub(crate) fn for_each_tile(img: &mut RgbImage, f: impl Fn(Tile) + Send + Sync) {
+    let row_size = img.width() as usize * 3;
+    if row_size == 0 {
+        return;
+    }
+    let band = |(i, band): (usize, &mut [u8])| {
+        let mut tiles: Vec<Tile> = Vec::new();
+        for (r, row) in band.chunks_mut(row_size).enumerate() {
+            for (c, part) in row.chunks_mut(TILE * 3).enumerate() {
+                if r == 0 {
+                    tiles.push(Tile {
+                        x: (c * TILE) as u32,
+                        y: (i * TILE) as u32,
+                        rows: Vec::with_capacity(TILE),
+                    });
+                }
+                tiles[c].rows.push(part);
+            }
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        tiles.into_par_iter().for_each(&f);
+        #[cfg(target_arch = "wasm32")]
+        tiles.into_iter().for_each(&f);
+    };
+    #[cfg(not(target_arch = "wasm32"))]
+    img.par_chunks_mut(row_size * TILE)
+        .enumerate()
+        .for_each(band);
+    #[cfg(target_arch = "wasm32")]
+    img.chunks_mut(row_size * TILE).enumerate().for_each(band);
+}
+
+/// Reverse the perspective of the photo in a screenshot.
+///
+/// # Arguments
+///
+/// * `img` - The screenshot.
+/// * `closest` - The corners of the photo.
+/// * `size` - The size of the output image.
+/// * `options` - Settings that control how the output is resampled.
+///
+/// # Errors
+///
+/// `Error::DegenerateQuad` is returned if the corners do not form a usable projection, and
+/// `Error::WarpFailed` if the backend fails.
+pub fn warp(
+    img: &RgbImage,
+    closest: Quad,
+    size: (u32, u32),
+    options: &CropOptions,
+) -> Result<RgbImage, Error> {
+    validate::validate(&closest, img.dimensions(), options.min_area)
+        .map_err(Error::DegenerateQuad)?;
+    let aspect = options.aspect_of(&closest, img.dimensions());
+    let natural = natural_size(&closest, aspect);
+    let natural = (natural.0.round() as u32, natural.1.round() as u32);
+    // The Lanczos warp widens its kernel to shrink without aliasing, so it does not need the
+    // second pass unless it is asked for.
+    let lanczos_auto =
+        options.interpolation == Interpolation::Lanczos && options.scale_mode == ScaleMode::Auto;
+    let intermediate = options.scale_mode.intermediate_size(natural, size);
+    if let Some(intermediate) = intermediate.filter(|_| !lanczos_auto) {
+        #[cfg(feature = "gpu")]
+        if options.backend == Backend::Gpu {
+            // The graphics card averages several samples for each output pixel instead of
+            // resizing afterward, so the large image never leaves it.
+            let samples = (intermediate.0 as f32 / size.0 as f32).ceil() as u32;
+            let projection = from_control_points(closest, size)?;
+            return gpu::warp(
+                img,
+                &projection,
+                size,
+                options.interpolation,
+                samples.clamp(1, 4),
+            )
+            .map_err(Error::WarpFailed);
+        }
+        let projection = from_control_points(closest, intermediate)?;
+        let full = options
+            .backend
+            .warp(img, &projection, intermediate, options.interpolation)
+            .map_err(Error::WarpFailed)?;
+        let resized = options.resize_filter.resize(&full, size);
+        pool::recycle(full);
+        return Ok(resized);
+    }
+
+    let projection = from_control_points(closest, size)?;
+    options
+        .backend
+        .warp(img, &projection, size, options.interpolation)
+        .map_err(Error::WarpFailed)
+}
+
+/// Encode an output as WebP with the quality and speed in `options`.
+///
+/// # Errors
+///
+/// `Error::EncodeError` is returned if the encoder fails.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn encode(img: &RgbImage, options: &CropOptions) -> Result<Vec<u8>, Error> {
+    timings::time(timings::Stage::Encode, || {
+        let encoder = Encoder::from_rgb(img.as_raw(), img.width(), img.height());
+        if !options.fast_encode {
+            return Ok(encoder.encode(options.quality).to_vec());
+        }
+        let mut config = WebPConfig::new()
+            .map_err(|_| Error::EncodeError("Could not configure the encoder".to_owned()))?;
+        config.quality = options.quality;
+        // The fastest method, which makes slightly larger files.
+        config.method = 0;
+        let encoded = encoder
+            .encode_advanced(&config)
+            .map_err(|e| Error::EncodeError(format!("{:?}", e)))?;
+        Ok(encoded.to_vec())
+    })
+}
+
+/// Cut the smallest rectangle around the corners of a photo out of a screenshot, without
+/// correcting its perspective.
+///
+/// The rectangle is shrunk with `resize_filter` if it is larger than `max_size`.
+pub fn bounding_box(img: &RgbImage, closest: &Quad, options: &CropOptions) -> RgbImage {
+    let (width, height) = img.dimensions();
+    let clamp = |value: f32, limit: u32| (value.round().max(0.0) as u32).min(limit - 1);
+    let left = clamp(
+        closest.iter().map(|p| p.0).fold(f32::INFINITY, f32::min),
+        width,
+    );
+    let top = clamp(
+        closest.iter().map(|p| p.1).fold(f32::INFINITY, f32::min),
+        height,
+    );
+    let right = clamp(closest.iter().map(|p| p.0).fold(0.0, f32::max), width) + 1;
+    let bottom = clamp(closest.iter().map(|p| p.1).fold(0.0, f32::max), height) + 1;
+    let (width, height) = (
+        right.saturating_sub(left).max(1),
+        bottom.saturating_sub(top).max(1),
+    );
+    let cropped = img.view(left, top, width, height).to_image();
+
+    let (width, height) = (width as f64, height as f64);
+    let height_ratio = options
+        .max_size
+        .height
+        .map_or(f64::INFINITY, |max| max / height);
+    let width_ratio = options
+        .max_size
+        .width
+        .map_or(f64::INFINITY, |max| max / width);
+    let ratio = height_ratio.min(width_ratio);
+    if ratio >= 1.0 {
+        return cropped;
+    }
+    options.resize_filter.resize(
+        &cropped,
+        (
+            ((width * ratio).round() as u32).max(1),
+            ((height * ratio).round() as u32).max(1),
+        ),
+    )
+}
+
+/// One photo cropped out of a screenshot.
+#[derive(Clone, Debug)]
+pub struct Photo {
+    /// Where the photo was found.
+    pub detection: Detection,
+    /// The corners that were warped, after `margin` and `deskew_only`.
+    pub corners: Quad,
+    /// The size the corners were warped to, before the photo was trimmed or turned.
+    pub warped_size: (u32, u32),
+    /// How far the photo was turned clockwise to make its text upright, in degrees, if
+    /// `auto_rotate` turned it.
+    pub auto_rotation: Option<u32>,
+    /// The photo.
+    pub image: RgbImage,
+}
+
+/// The photos cropped out of one screenshot by `process`.
+#[derive(Clone, Debug)]
+pub struct ProcessedImage {
+    /// The photos, most confident first.
+    pub photos: Vec<Photo>,
+    /// The size of the screenshot, after lens distortion was removed.
+    pub size: (u32, u32),
+}
+
+/// Find the photos to crop in a screenshot.
+///
+/// These are the corners in `options.corners` if any were given, and otherwise the photos found
+/// by `detect_scaled`.
+///
+/// # Errors
+///
+/// `Error::DetectionFailed` is returned if no corners were given and no photos can be found.
+pub fn find_photos(img: &RgbImage, options: &CropOptions) -> Result<Vec<Detection>, Error> {
+    timings::time(timings::Stage::Detection, || {
+        if options.corners.is_empty() {
+            return detect_scaled(img, options);
+        }
+        Ok(options
+            .corners
+            .iter()
+            .map(|&corners| Detection {
+                corners,
+                confidence: 1.0,
+                detector: None,
+            })
+            .collect())
+    })
+}
+
+/// Whether a photo fills the whole screenshot with no border along its edges, so that the
+/// screenshot was already cropped and is not warped again. This is only checked with
+/// `skip_cropped`, and never for corners that were given.
+pub fn is_already_cropped(img: &RgbImage, detection: &Detection, options: &CropOptions) -> bool {
+    options.skip_cropped
+        && options.corners.is_empty()
+        && validate::is_whole_frame(&detection.corners, img.dimensions())
+        && border::is_borderless(img, options)
+}
+
+/// Unperspective one photo in a screenshot and apply the rest of the settings in `options` to
+/// it, without encoding it.
+///
+/// A screenshot that `is_already_cropped` is not warped, since that would only soften it, but
+/// the rest of the settings still apply.
+///
+/// # Arguments
+///
+/// * `img` - The screenshot.
+/// * `detection` - The corners of the photo.
+/// * `options` - Settings that control the output.
+///
+/// # Errors
+///
+/// `Error::DegenerateQuad` is returned if the corners do not form a usable projection.
+pub fn crop_photo(
+    img: &RgbImage,
+    detection: &Detection,
+    options: &CropOptions,
+) -> Result<Photo, Error> {
+    timings::time(timings::Stage::Warp, || {
+        let (img_width, img_height) = img.dimensions();
+        let is_cropped = is_already_cropped(img, detection, options);
+        let (closest, (width, height)) = if is_cropped {
+            // The photo keeps its proportions and is only shrunk to fit `max_size`.
+            let (w, h) = (img_width as f32, img_height as f32);
+            let frame = [(0.0, 0.0), (w, 0.0), (w, h), (0.0, h)];
+            let aspect = Aspect(img_width as f64 / img_height as f64);
+            (frame, output_size(&frame, aspect, options.max_size))
+        } else {
+            let closest = options
+                .warp_corners(&detection.corners, img.dimensions())
+                .map_err(Error::DegenerateQuad)?;
+            let size = output_size(
+                &closest,
+                options.aspect_of(&closest, img.dimensions()),
+                options.max_size,
+            );
+            (closest, size)
+        };
+
+        let mut out_img = if is_cropped && (width, height) == img.dimensions() {
+            img.clone()
+        } else if is_cropped {
+            options.resize_filter.resize(img, (width, height))
+        } else if options.no_warp {
+            bounding_box(img, &closest, options)
+        } else {
+            warp(img, closest, (width, height), options)?
+        };
+        if options.inscribed && !options.no_warp && !is_cropped {
+            let projection = from_control_points(closest, (width, height))?;
+            out_img = inscribed::crop(&out_img, &projection, img.dimensions());
+        }
+        if options.trim_edges {
+            out_img = trim::trim(&out_img);
+        }
+        if options.flip_horizontal {
+            image::imageops::flip_horizontal_in_place(&mut out_img);
+        }
+        if options.flip_vertical {
+            image::imageops::flip_vertical_in_place(&mut out_img);
+        }
+        if options.rotate != 0 {
+            out_img = ocr::rotate(&out_img, options.rotate);
+        }
+        let auto_rotation = if options.auto_rotate {
+            ocr::correction(&out_img).map_err(Error::AutoRotateFailed)?
+        } else {
+            None
+        };
+        if let Some(degrees) = auto_rotation {
+            out_img = ocr::rotate(&out_img, degrees);
+        }
+
+        Ok(Photo {
+            detection: *detection,
+            corners: closest,
+            warped_size: (width, height),
+            auto_rotation,
+            image: out_img,
+        })
+    })
+}
+
+/// Find the photos in a screenshot and crop them.
+///
+/// Lens distortion in `options` is removed from the screenshot first. The screenshot should
+/// already be the right way up.
+///
+/// # Errors
+///
+/// `Error::DetectionFailed` is returned if no photos can be found, `Error::LowConfidence` if a
+/// photo is found with less than `options.min_confidence`, and other errors if a photo cannot be
+/// warped.
+pub fn process(img: &RgbImage, options: &CropOptions) -> Result<ProcessedImage, Error> {
+    let img = match options.distortion {
+        Some(distortion) => Cow::Owned(distortion::undistort(img, distortion)),
+        None => Cow::Borrowed(img),
+    };
+    let detections = find_photos(&img, options)?;
+    if let Some(detection) = detections
+        .iter()
+        .find(|d| d.confidence < options.min_confidence)
+    {
+        return Err(Error::LowConfidence {
+            confidence: detection.confidence,
+            min_confidence: options.min_confidence,
+        });
+    }
+    let photos = detections
+        .iter()
+        .map(|detection| crop_photo(&img, detection, options))
+        .collect::<Result<_, Error>>()?;
+    Ok(ProcessedImage {
+        photos,
+        size: img.dimensions(),
+    })
+}
@@ -0,0 +1,161 @@
+use std::{
+    env, fs,
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+    process,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{bail, Context};
+
+/// The name of the lock file created in each output directory.
+const LOCK_FILE: &str = ".qdcrop.lock";
+/// How old a lock must be before it is considered stale when its owner cannot be checked.
+const STALE_AFTER: Duration = Duration::from_secs(6 * 60 * 60);
+
+/// An advisory lock on an output directory, held for the duration of a run.
+///
+/// The lock is a file containing the process ID and host name of its owner. It is removed when
+/// the lock is dropped.
+pub struct SessionLock {
+    path: PathBuf,
+}
+
+/// The name of this computer, used to tell whether a lock's process ID can be checked here.
+fn host_name() -> String {
+    env::var("COMPUTERNAME")
+        .or_else(|_| env::var("HOSTNAME"))
+        .ok()
+        .or_else(|| {
+            fs::read_to_string("/etc/hostname")
+                .ok()
+                .map(|h| h.trim().to_owned())
+        })
+        .unwrap_or_default()
+}
+
+/// Check whether a process is running.
+///
+/// Returns `None` if this cannot be determined on this platform.
+#[cfg(windows)]
+fn is_running(pid: u32) -> Option<bool> {
+    use std::ffi::c_void;
+
+    const PROCESS_QUERY_LIMITED_INFORMATION: u32 = 0x1000;
+    const STILL_ACTIVE: u32 = 259;
+    extern "system" {
+        fn OpenProcess(access: u32, inherit: i32, pid: u32) -> *mut c_void;
+        fn GetExitCodeProcess(process: *mut c_void, code: *mut u32) -> i32;
+        fn CloseHandle(handle: *mut c_void) -> i32;
+    }
+
+    // SAFETY: The handle is only used while it is open and is always closed.
+    unsafe {
+        let handle = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid);
+        if handle.is_null() {
+            return Some(false);
+        }
+        let mut code = 0;
+        let ok = GetExitCodeProcess(handle, &mut code);
+        CloseHandle(handle);
+        if ok == 0 {
+            None
+        } else {
+            Some(code == STILL_ACTIVE)
+        }
+    }
+}
+
+/// Check whether a process is running.
+///
+/// Returns `None` if this cannot be determined on this platform.
+#[cfg(not(windows))]
+fn is_running(pid: u32) -> Option<bool> {
+    if Path::new("/proc/self").exists() {
+        Some(Path::new(&format!("/proc/{}", pid)).exists())
+    } else {
+        None
+    }
+}
+
+/// Decide whether an existing lock file was left behind by a run that is no longer active.
+fn is_stale(path: &Path) -> bool {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        // The lock was released while we were looking at it.
+        Err(e) if e.kind() == ErrorKind::NotFound => return true,
+        Err(_) => return false,
+    };
+    let mut lines = contents.lines();
+    let pid = lines.next().and_then(|p| p.parse().ok());
+    let host = lines.next().unwrap_or_default();
+    if let Some(pid) = pid {
+        if host == host_name() {
+            if let Some(running) = is_running(pid) {
+                return !running;
+            }
+        }
+    }
+    // The owner is on another computer or cannot be checked, so fall back to the lock's age.
+    fs::metadata(path)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+        .is_some_and(|age| age > STALE_AFTER)
+}
+
+impl SessionLock {
+    /// Lock an output directory.
+    ///
+    /// A lock left behind by a process that is no longer running is replaced.
+    ///
+    /// # Errors
+    ///
+    /// An error is returned if another active run holds the lock, or if the lock file cannot be
+    /// created.
+    pub fn acquire(dir: &Path) -> anyhow::Result<SessionLock> {
+        let path = dir.join(LOCK_FILE);
+        for _ in 0..2 {
+            match fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&path)
+            {
+                Ok(mut file) => {
+                    writeln!(file, "{}\n{}", process::id(), host_name())
+                        .context("Could not write lock file")?;
+                    return Ok(SessionLock { path });
+                }
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+                    if !is_stale(&path) {
+                        let owner = fs::read_to_string(&path).unwrap_or_default();
+                        let mut owner = owner.lines();
+                        bail!(
+                            "Another qdcrop run (process {} on {}) is writing to {}. If it is not running, delete {}.",
+                            owner.next().unwrap_or("?"),
+                            owner.next().unwrap_or("?"),
+                            dir.to_string_lossy(),
+                            path.to_string_lossy()
+                        );
+                    }
+                    match fs::remove_file(&path) {
+                        Ok(()) => {}
+                        Err(e) if e.kind() == ErrorKind::NotFound => {}
+                        Err(e) => return Err(e).context("Could not remove stale lock file"),
+                    }
+                }
+                Err(e) => return Err(e).context("Could not create lock file"),
+            }
+        }
+        bail!(
+            "Could not lock {} because another run keeps taking the lock",
+            dir.to_string_lossy()
+        )
+    }
+}
+
+impl Drop for SessionLock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
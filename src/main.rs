@@ -1,186 +1,554 @@
 use std::{
     borrow::Cow,
+    collections::HashSet,
     fs::File,
-    io::Write,
+    io::{self, Write},
+    iter,
     path::{Path, PathBuf},
     process,
+    sync::Arc,
 };
 
-use anyhow::{anyhow, Context};
-use image::{DynamicImage, GenericImageView, ImageBuffer, Rgb};
-use imageproc::{
-    definitions::HasBlack,
-    geometric_transformations::{Interpolation, Projection},
+use anyhow::{bail, Context};
+use image::RgbImage;
+use qdcrop::{
+    backend::{Backend, Interpolation},
+    border,
+    confidence::LowConfidence,
+    conflict::ConflictPolicy,
+    control_point_matrix, crop_photo, distortion, encode, features, find_photos,
+    is_already_cropped, onnx, parse_matrix, parse_quad, parse_region, pool,
+    resize::{ResizeFilter, ScaleMode},
+    timings::{self, Stage, Timings},
+    Aspect, AspectMode, CornerDetector, CropOptions, Detection, Detector, MaxSize, Orientation,
+    ThresholdMode,
 };
-use nalgebra::{ArrayStorage, Matrix};
-use rayon::prelude::*;
-use webp::Encoder;
 
-/// Find an inverse projection matrix for a rectangle.
+mod annotations;
+mod cache;
+mod capture;
+mod config;
+mod contents;
+mod debug;
+mod detect;
+mod exif;
+mod filelist;
+mod gallery;
+mod icc;
+mod init;
+mod lock;
+mod makernote;
+mod memory;
+mod mux;
+mod notification;
+mod pipeline;
+mod pngtext;
+mod power;
+mod pyramid;
+mod selfcheck;
+mod selftest;
+mod serve;
+mod sheet;
+mod shell;
+mod sidecar;
+mod source;
+mod stats;
+mod verify;
+mod watch;
+mod webhook;
+mod xmp;
+
+/// The result of cropping one screenshot.
+struct Cropped {
+    /// The files that were written.
+    outputs: Vec<PathBuf>,
+    /// The photos found in the screenshot.
+    detections: Vec<Detection>,
+    /// The size of the screenshot.
+    size: (u32, u32),
+    /// Outputs that are still being written.
+    writes: Vec<pipeline::PendingWrite>,
+}
+
+/// The path of one of several photos cropped from the same screenshot, such as `photo_2.webp`.
+fn numbered(output: &Path, number: usize) -> PathBuf {
+    let mut name = output.file_stem().unwrap_or_default().to_owned();
+    name.push(format!("_{}", number));
+    if let Some(extension) = output.extension() {
+        name.push(".");
+        name.push(extension);
+    }
+    output.with_file_name(name)
+}
+
+/// Unperspective one photo in a screenshot and save it.
 ///
 /// # Arguments
 ///
-/// * `from` - the points of the forward perspective transformed rectangle, provided clockwise from (0, 0) in the top left.
-/// * `to` - the width and height of the image after reverse projection.
+/// * `img` - The screenshot.
+/// * `source` - Metadata from the screenshot to copy to the output.
+/// * `detection` - The corners of the photo.
+/// * `output` - The path to the output webp file.
+/// * `options` - Settings that control output.
+/// * `writer` - Where to hand the encoded output to be written in the background, if anywhere.
+///
+/// Returns the path that was written, which may differ from `output` because of a conflict, and
+/// the write that is still pending if it was handed to `writer`.
 ///
 /// # Errors
 ///
-/// An error will be returned if three of the points in `from` form a line.
-fn from_control_points(from: [(f32, f32); 4], to: (u32, u32)) -> anyhow::Result<Projection> {
-    // imageproc::geometric_transformations::Projection has a from_control_points,
-    // but it seems to randomly fail on trivial cases.
-    // This is an implementation of the algorithm used by OpenCV with the solver from nalgebra.
-    // It is much more reliable.
-    let ((x0, y0), (x1, y1), (x2, y2), (x3, y3)) = (
-        (from[0].0 as f64, from[0].1 as f64),
-        (from[1].0 as f64, from[1].1 as f64),
-        (from[2].0 as f64, from[2].1 as f64),
-        (from[3].0 as f64, from[3].1 as f64),
-    );
-    let ((u0, v0), (u1, v1), (u2, v2), (u3, v3)) = (
-        (0.0f64, 0.0f64),
-        (to.0 as f64, 0.0f64),
-        (to.0 as f64, to.1 as f64),
-        (0.0f64, to.1 as f64),
-    );
+/// An error message is returned if the image cannot be transformed or saved.
+fn save(
+    img: &RgbImage,
+    source: &SourceMetadata,
+    detection: &Detection,
+    output: &Path,
+    options: &CropOptions,
+    writer: Option<&pipeline::Writer>,
+) -> anyhow::Result<(PathBuf, Option<pipeline::PendingWrite>)> {
+    let photo = crop_photo(img, detection, options)?;
+    let out_img = photo.image;
+
+    let exif = if options.strip_metadata {
+        exif::Exif::default()
+    } else {
+        makernote::exif(
+            detection,
+            options,
+            &out_img,
+            &source.exif,
+            source.name.as_deref(),
+        )
+    };
+    let metadata = source.for_output(exif);
+    let fingerprint = options
+        .verify_outputs
+        .then(|| verify::Fingerprint::of(&out_img));
+    let encoded = encode(&out_img, options)?;
+    let encoded = timings::time(Stage::Encode, || {
+        mux::mux(&encoded, out_img.dimensions(), &metadata)
+    })?;
+    let (output, mut file) = options.conflict.create(output)?;
+    // Verifying and copying times read and change the output, so it must be written first.
+    let write = match writer {
+        Some(writer) if !options.verify_outputs && !options.preserve_times => {
+            Some(writer.write(output.clone(), file, encoded))
+        }
+        _ => {
+            file.write_all(&encoded).context("Could not write output")?;
+            file.flush().context("Could not write output")?;
+            None
+        }
+    };
+    if let Some(degrees) = photo.auto_rotation {
+        eprintln!(
+            "Rotated {} by {} degrees to make its text upright",
+            output.to_string_lossy(),
+            degrees
+        );
+    }
+
+    if let Some(fingerprint) = fingerprint {
+        verify::verify(&output, &fingerprint)?;
+    }
+    if options.tile_pyramid {
+        pyramid::write_dzi(&output, &out_img)?;
+    }
+    if options.json_sidecar {
+        sidecar::write(&output, detection, img.dimensions(), out_img.dimensions())?;
+    }
+    if options.xmp_sidecar {
+        let matrix = control_point_matrix(photo.corners, photo.warped_size)?;
+        xmp::write_sidecar(&output, detection, &photo.corners, &matrix, options)?;
+    }
+    pool::recycle(out_img);
 
-    let a = Matrix::from_data(ArrayStorage([
-        [x0, x1, x2, x3, 0.0, 0.0, 0.0, 0.0],
-        [y0, y1, y2, y3, 0.0, 0.0, 0.0, 0.0],
-        [1.0, 1.0, 1.0, 1.0, 0.0, 0.0, 0.0, 0.0],
-        [0.0, 0.0, 0.0, 0.0, x0, x1, x2, x3],
-        [0.0, 0.0, 0.0, 0.0, y0, y1, y2, y3],
-        [0.0, 0.0, 0.0, 0.0, 1.0, 1.0, 1.0, 1.0],
-        [
-            -x0 * u0,
-            -x1 * u1,
-            -x2 * u2,
-            -x3 * u3,
-            -x0 * v0,
-            -x1 * v1,
-            -x2 * v2,
-            -x3 * v3,
-        ],
-        [
-            -y0 * u0,
-            -y1 * u1,
-            -y2 * u2,
-            -y3 * u3,
-            -y0 * v0,
-            -y1 * v1,
-            -y2 * v2,
-            -y3 * v3,
-        ],
-    ]));
-    let b = Matrix::from_data(ArrayStorage([[u0, u1, u2, u3, v0, v1, v2, v3]]));
-
-    let svd = a
-        .try_svd(true, true, f64::EPSILON, 1048576)
-        .context("SVD failed")?;
-    let x = svd
-        .solve(&b, 0.125)
-        .map_err(|e| anyhow!("Unable to solve for projection: {:?}", e))?;
-    let x = x.column(0);
-
-    Ok(Projection::from_matrix([
-        x[0] as f32,
-        x[1] as f32,
-        x[2] as f32,
-        x[3] as f32,
-        x[4] as f32,
-        x[5] as f32,
-        x[6] as f32,
-        x[7] as f32,
-        1.0,
-    ])
-    .unwrap())
+    Ok((output, write))
 }
 
-/// Find the position of the black pixel closest to a corner of the image.
-///
-/// # Arguments
+/// Metadata read from a screenshot to copy to its outputs.
+#[derive(Clone, Debug, Default)]
+struct SourceMetadata {
+    /// The EXIF fields that still describe cropped photos.
+    exif: exif::Exif,
+    /// The color profile of the screenshot, if it has one.
+    icc: Option<Vec<u8>>,
+    /// The XMP packet of the screenshot, such as the world and author of a VRChat print.
+    xmp: Option<Vec<u8>>,
+    /// The file name of the screenshot.
+    name: Option<String>,
+}
+
+impl SourceMetadata {
+    /// The metadata to add to an output.
+    ///
+    /// Outputs of screenshots without a color profile are tagged as sRGB, so that viewers do
+    /// not guess.
+    fn for_output(&self, exif: exif::Exif) -> mux::Metadata {
+        mux::Metadata {
+            exif: (!exif.is_empty()).then(|| exif.to_tiff()),
+            icc: Some(self.icc.clone().unwrap_or_else(icc::srgb)),
+            xmp: self.xmp.clone(),
+        }
+    }
+}
+
+/// Convert a decoded screenshot to RGB.
 ///
-/// * `threshold` - The image to search.
-/// * `flip_x` - `true` if the search should start from the right.
-/// * `flip_y` - `true` if the search should start from the bottom.
-fn find_nearest_to_corner<Image: GenericImageView<Pixel = P>, P: HasBlack + PartialEq>(
-    threshold: &Image,
-    flip_x: bool,
-    flip_y: bool,
-) -> Option<(u32, u32)> {
-    #[derive(Debug)]
-    struct Nearest {
-        square_distance: usize,
-        x: u32,
-        y: u32,
-    }
-    let mut nearest = None;
-    for i in 0..std::cmp::max(threshold.width(), threshold.height()) {
-        let i_squared = i as usize * i as usize;
-        match &nearest {
-            Some(Nearest {
-                square_distance, ..
-            }) if *square_distance < i_squared => break,
-            _ => {}
+/// Screenshots with transparency lose it in their own buffer, so that an 8K screenshot is not
+/// held twice while it is converted.
+fn into_rgb8(img: image::DynamicImage) -> RgbImage {
+    match img {
+        image::DynamicImage::ImageRgba8(rgba) => {
+            let (width, height) = rgba.dimensions();
+            let pixels = width as usize * height as usize;
+            let mut data = rgba.into_raw();
+            for i in 0..pixels {
+                data.copy_within(i * 4..i * 4 + 3, i * 3);
+            }
+            data.truncate(pixels * 3);
+            data.shrink_to_fit();
+            RgbImage::from_raw(width, height, data).unwrap()
         }
+        img => img.into_rgb8(),
+    }
+}
 
-        if i < threshold.height() {
-            let real_y = if flip_y {
-                threshold.height() - 1 - i
-            } else {
-                i
-            };
-            for x in 0..std::cmp::min(i + 1, threshold.width()) {
-                let real_x = if flip_x { threshold.width() - 1 - x } else { x };
-                if threshold.get_pixel(real_x, real_y) == P::black() {
-                    let square_distance = x as usize * x as usize + i_squared;
-                    nearest = Some(match nearest {
-                        Some(
-                            v
-                            @
-                            Nearest {
-                                square_distance: c, ..
-                            },
-                        ) if c < square_distance => v,
-                        _ => Nearest {
-                            square_distance,
-                            x: real_x,
-                            y: real_y,
-                        },
-                    });
+/// Open a screenshot the right way up, ready for detection.
+///
+/// The EXIF orientation of the file is applied, since `image` ignores it, and lens distortion is
+/// removed if `options` has any.
+///
+/// The capture time in the names of VRChat screenshots is added to the metadata if the file has
+/// none in its EXIF. The text chunks of PNG files, where VRChat prints keep their world and
+/// author, are kept as XMP or as the matching EXIF fields.
+///
+/// Returns the screenshot and the metadata to copy to its outputs.
+///
+/// # Errors
+///
+/// An error is returned if the file cannot be read or decoded.
+fn open(path: &Path, options: &CropOptions) -> anyhow::Result<(RgbImage, SourceMetadata)> {
+    let data = contents::read(path)
+        .map_err(qdcrop::Error::Io)
+        .context("Could not open input")?;
+    decode(path, &data, options)
+}
+
+/// Open a screenshot like `open` from the contents of `path` that were already read.
+///
+/// # Errors
+///
+/// An error is returned if the file cannot be decoded.
+fn decode(
+    path: &Path,
+    data: &[u8],
+    options: &CropOptions,
+) -> anyhow::Result<(RgbImage, SourceMetadata)> {
+    let tiff = exif::find(data);
+    let img = image::io::Reader::new(io::Cursor::new(data))
+        .with_guessed_format()
+        .map_err(qdcrop::Error::Io)
+        .context("Could not open input")?
+        .decode()
+        .map_err(qdcrop::Error::DecodeError)
+        .context("Could not open input")?;
+    let mut img = into_rgb8(img);
+    img = match tiff.and_then(exif::orientation) {
+        Some(2) => {
+            image::imageops::flip_horizontal_in_place(&mut img);
+            img
+        }
+        Some(3) => {
+            image::imageops::rotate180_in_place(&mut img);
+            img
+        }
+        Some(4) => {
+            image::imageops::flip_vertical_in_place(&mut img);
+            img
+        }
+        Some(5) => image::imageops::flip_horizontal(&image::imageops::rotate90(&img)),
+        Some(6) => image::imageops::rotate90(&img),
+        Some(7) => image::imageops::flip_horizontal(&image::imageops::rotate270(&img)),
+        Some(8) => image::imageops::rotate270(&img),
+        _ => img,
+    };
+    if let Some(distortion) = options.distortion {
+        img = distortion::undistort(&img, distortion);
+    }
+    let source = if options.strip_metadata {
+        SourceMetadata::default()
+    } else {
+        let mut exif = tiff.map(exif::Exif::read).unwrap_or_default();
+        let vrchat_time = path
+            .file_name()
+            .and_then(|name| capture::CaptureTime::from_vrchat_name(&name.to_string_lossy()));
+        if let Some(time) = vrchat_time {
+            if !exif.has_exif(exif::tag::DATE_TIME_ORIGINAL) {
+                exif.set_exif(
+                    exif::tag::DATE_TIME_ORIGINAL,
+                    exif::Value::Ascii(time.exif()),
+                );
+            }
+        }
+        let mut xmp = None;
+        for (keyword, text) in pngtext::read(data) {
+            let tag = match keyword.as_str() {
+                pngtext::XMP_KEYWORD => {
+                    xmp = Some(text.into_bytes());
+                    continue;
                 }
+                "Title" | "Description" => exif::tag::IMAGE_DESCRIPTION,
+                "Author" => exif::tag::ARTIST,
+                "Copyright" => exif::tag::COPYRIGHT,
+                _ => continue,
+            };
+            if !exif.has_ifd0(tag) {
+                exif.set_ifd0(tag, exif::Value::Ascii(text));
             }
         }
-        if i < threshold.width() {
-            let real_x = if flip_x { threshold.width() - 1 - i } else { i };
-            for y in 0..std::cmp::min(i, threshold.height()) {
-                let real_y = if flip_y {
-                    threshold.height() - 1 - y
-                } else {
-                    y
-                };
-                if threshold.get_pixel(real_x, real_y) == P::black() {
-                    let square_distance = i_squared + y as usize * y as usize;
-                    nearest = Some(match nearest {
-                        Some(
-                            v
-                            @
-                            Nearest {
-                                square_distance: c, ..
-                            },
-                        ) if c < square_distance => v,
-                        _ => Nearest {
-                            square_distance,
-                            x: real_x,
-                            y: real_y,
-                        },
-                    });
+        SourceMetadata {
+            exif,
+            icc: icc::find(data),
+            xmp,
+            name: path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned()),
+        }
+    };
+    Ok((img, source))
+}
+
+/// How outputs are named when they are not given with `-o`.
+#[derive(Clone, Copy, Debug, Default)]
+struct Naming<'a> {
+    /// The template given to `--name`, if any.
+    template: Option<&'a str>,
+    /// Whether outputs are put in `YYYY/MM-DD` folders for the day they were taken.
+    by_date: bool,
+}
+
+/// Choose the path of the output for an input that was not given an output, relative to the
+/// output directory.
+///
+/// Without a template, the name is the name of the input with the extension `.webp`. In a
+/// template, `{stem}` is the name of the input without its extension, and `{date}` and `{time}`
+/// are when it was taken, from its VRChat name or else its modification time in UTC. The same
+/// time chooses the folders when `naming.by_date` is set.
+fn output_name(input: &Path, naming: Naming) -> PathBuf {
+    let stem = input.file_stem().unwrap_or_default();
+    let time = capture::CaptureTime::of(input);
+    let mut name = match naming.template {
+        None => stem.to_owned(),
+        Some(template) => {
+            let text = template
+                .replace("{stem}", &stem.to_string_lossy())
+                .replace("{date}", &time.map(|t| t.date()).unwrap_or_default())
+                .replace("{time}", &time.map(|t| t.time()).unwrap_or_default());
+            text.into()
+        }
+    };
+    name.push(".webp");
+    match (naming.by_date, time) {
+        (true, Some(time)) => Path::new(&format!("{:04}", time.year))
+            .join(format!("{:02}-{:02}", time.month, time.day))
+            .join(name),
+        (true, None) => Path::new("unknown date").join(name),
+        (false, _) => PathBuf::from(name),
+    }
+}
+
+/// Give a file the modification time of another, and on Windows its creation time too.
+///
+/// # Errors
+///
+/// An error is returned if the times cannot be read or set.
+fn copy_times(from: &Path, to: &Path) -> anyhow::Result<()> {
+    let error = "Could not read the time of the input";
+    let metadata = std::fs::metadata(from).context(error)?;
+    let times = std::fs::FileTimes::new().set_modified(metadata.modified().context(error)?);
+    #[cfg(windows)]
+    let times = {
+        use std::os::windows::fs::FileTimesExt;
+        times.set_created(metadata.created().context(error)?)
+    };
+    std::fs::OpenOptions::new()
+        .write(true)
+        .open(to)
+        .and_then(|file| file.set_times(times))
+        .context("Could not set the time of the output")
+}
+
+/// A screenshot that was read and is ready to crop.
+struct Screenshot<'a> {
+    /// The path to the screenshot.
+    input: &'a Path,
+    /// The path to the output webp file.
+    output: PathBuf,
+    /// The options for this screenshot, with the corners of its sidecar if one was used.
+    options: Cow<'a, CropOptions>,
+    /// The contents of the file.
+    data: contents::Contents,
+}
+
+/// Read a screenshot to crop, the first stage of `crop`.
+///
+/// Returns `None` if the job was skipped because of a conflict.
+///
+/// # Errors
+///
+/// An error message is returned if the sidecar or the image cannot be read.
+fn read_screenshot<'a>(
+    input: &'a Path,
+    output: &Path,
+    options: Cow<'a, CropOptions>,
+) -> anyhow::Result<Option<Screenshot<'a>>> {
+    let sidecar = if options.use_sidecar {
+        sidecar::read(output)?
+    } else {
+        None
+    };
+    let options = match sidecar {
+        Some(corners) => Cow::Owned(CropOptions {
+            corners,
+            ..options.into_owned()
+        }),
+        None => options,
+    };
+    let is_numbered = options.multiple || options.corners.len() > 1;
+    let first = if is_numbered {
+        Cow::Owned(numbered(output, 1))
+    } else {
+        Cow::Borrowed(output)
+    };
+    if !options.conflict.should_process(input, &first)? {
+        return Ok(None);
+    }
+
+    let extension = input
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase());
+    // No build of qdcrop can decode these, so say so rather than that the format is unknown.
+    if let Some("heic" | "heif") = extension.as_deref() {
+        bail!(
+            "{} is a HEIF/HEIC picture, which qdcrop cannot read. Convert it to PNG or JPEG first.",
+            input.to_string_lossy()
+        );
+    }
+    let data = contents::read(input)
+        .map_err(qdcrop::Error::Io)
+        .context("Could not open input")?;
+    Ok(Some(Screenshot {
+        input,
+        output: output.to_owned(),
+        options,
+        data,
+    }))
+}
+
+/// Crop a screenshot that was read, the second stage of `crop`.
+///
+/// Outputs are handed to `writer` when there is one, and their writes are returned in `writes`.
+///
+/// # Errors
+///
+/// An error message is returned if the image cannot be decoded, transformed, or saved.
+fn crop_screenshot(
+    screenshot: Screenshot,
+    writer: Option<&pipeline::Writer>,
+) -> anyhow::Result<Cropped> {
+    let Screenshot {
+        input,
+        output,
+        options,
+        data,
+    } = screenshot;
+    let (output, options) = (output.as_path(), options.as_ref());
+    let is_numbered = options.multiple || options.corners.len() > 1;
+    let (img, source) = timings::time(Stage::Decode, || decode(input, &data, options))?;
+    drop(data);
+    if let Some(dir) = &options.debug_dir {
+        let mask = border::masks(&img, &image::imageops::grayscale(&img), options)
+            .next()
+            .unwrap();
+        debug::save_threshold(dir, input, &mask)?;
+    }
+    if options.warp_only && options.corners.is_empty() {
+        bail!(
+            "No corners were given. Use --corners, --matrix, --corners-from, --annotations, or --use-sidecar."
+        );
+    }
+    let detections = find_photos(&img, options)?;
+    if let Some(detection) = detections
+        .iter()
+        .find(|d| d.confidence < options.min_confidence)
+        .copied()
+    {
+        match options.low_confidence {
+            LowConfidence::Report => bail!(
+                "Detection confidence {:.2} is below {:.2}",
+                detection.confidence,
+                options.min_confidence
+            ),
+            LowConfidence::Copy => {
+                let mut output = output.to_owned();
+                if let Some(extension) = input.extension() {
+                    output.set_extension(extension);
                 }
+                let (output, mut file) = options.conflict.create(&output)?;
+                let mut source = File::open(input).context("Could not open input")?;
+                io::copy(&mut source, &mut file).context("Could not write output")?;
+                return Ok(Cropped {
+                    outputs: vec![output],
+                    size: img.dimensions(),
+                    detections,
+                    writes: Vec::new(),
+                });
             }
         }
     }
+    if !is_numbered && is_already_cropped(&img, &detections[0], options) {
+        eprintln!(
+            "{} is already cropped; converted it without warping",
+            input.to_string_lossy()
+        );
+    }
+    if let Some(dir) = &options.debug_dir {
+        let quads: Vec<_> = detections.iter().map(|d| d.corners).collect();
+        debug::save_corners(dir, input, &img, &quads)?;
+    }
 
-    nearest.map(|n| (n.x, n.y))
+    let saved = if is_numbered {
+        detections
+            .iter()
+            .enumerate()
+            .map(|(i, detection)| {
+                let output = numbered(output, i + 1);
+                save(&img, &source, detection, &output, options, writer)
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?
+    } else {
+        vec![save(
+            &img,
+            &source,
+            &detections[0],
+            output,
+            options,
+            writer,
+        )?]
+    };
+    let (outputs, writes): (Vec<_>, Vec<_>) = saved.into_iter().unzip();
+    if options.preserve_times {
+        for output in &outputs {
+            copy_times(input, output)?;
+        }
+    }
+    Ok(Cropped {
+        outputs,
+        size: img.dimensions(),
+        detections,
+        writes: writes.into_iter().flatten().collect(),
+    })
 }
 
 /// Unperspective and crop an image file.
@@ -188,87 +556,881 @@ fn find_nearest_to_corner<Image: GenericImageView<Pixel = P>, P: HasBlack + Part
 /// # Arguments
 ///
 /// * `input` - The path to the input file.
-/// * `output` - The path to the output webp file.
+/// * `output` - The path to the output webp file. When several photos are cropped from one
+///   screenshot, they are numbered from 1 with `numbered`.
+/// * `options` - Settings that control detection and output.
+///
+/// Returns what was cropped, or `None` if the job was skipped because of a conflict.
 ///
 /// # Errors
 ///
 /// An error message is returned if the image cannot be loaded, transformed, or saved.
-fn crop<PI: AsRef<Path>, PO: AsRef<Path>>(input: PI, output: PO) -> anyhow::Result<()> {
-    let img = image::open(input).context("Could not open input")?;
-    let luma = img.to_luma8();
-    let img = img.into_rgb8();
-
-    let threshold = imageproc::contrast::adaptive_threshold(&luma, 2);
-    let closest = [
-        find_nearest_to_corner(&threshold, false, false).context("No interesting points")?,
-        find_nearest_to_corner(&threshold, true, false).unwrap(),
-        find_nearest_to_corner(&threshold, true, true).unwrap(),
-        find_nearest_to_corner(&threshold, false, true).unwrap(),
-    ];
-
-    let height = std::cmp::max(closest[3].1 - closest[0].1, closest[2].1 - closest[1].1) as f64;
-    let width = std::cmp::max(closest[1].0 - closest[0].0, closest[2].0 - closest[3].0) as f64;
-    let height_aspect = 9.0 * width / 16.0;
-    let width_aspect = 16.0 * height / 9.0;
-    let (width, height) = if height_aspect < height {
-        (width_aspect, height)
-    } else {
-        (width, height_aspect)
-    };
-
-    const MAX_HEIGHT: f64 = 1024.0;
-    const MAX_WIDTH: f64 = 1024.0 * 16.0 / 9.0;
-    let height_ratio = MAX_HEIGHT / height;
-    let width_ratio = MAX_WIDTH / width;
-    let (width, height) = if height_ratio <= width_ratio && height_ratio < 1.0 {
-        (width * height_ratio, MAX_HEIGHT)
-    } else if width_ratio <= height_ratio && width_ratio < 1.0 {
-        (MAX_WIDTH, height * width_ratio)
-    } else {
-        (width, height)
-    };
+fn crop<PI: AsRef<Path>, PO: AsRef<Path>>(
+    input: PI,
+    output: PO,
+    options: &CropOptions,
+) -> anyhow::Result<Option<Cropped>> {
+    let screenshot = read_screenshot(input.as_ref(), output.as_ref(), Cow::Borrowed(options))?;
+    screenshot
+        .map(|screenshot| crop_screenshot(screenshot, None))
+        .transpose()
+}
 
-    let (width, height) = (width.round() as u32, height.round() as u32);
-
-    let projection =
-        from_control_points(closest.map(|p| (p.0 as f32, p.1 as f32)), (width, height))?;
-    let mut out_img = ImageBuffer::new(width, height);
-    imageproc::geometric_transformations::warp_into(
-        &img,
-        &projection,
-        Interpolation::Bicubic,
-        Rgb([0, 0, 0]),
-        &mut out_img,
-    );
+/// Crop new screenshots in `dirs` until interrupted, with the output settings in `settings`.
+///
+/// # Errors
+///
+/// An error is returned if a folder cannot be watched or the output folder cannot be locked.
+fn watch_dirs(
+    dirs: &[PathBuf],
+    settings: &config::Settings,
+    battery_saver: Option<&power::Saver>,
+    options: &CropOptions,
+) -> anyhow::Result<()> {
+    let output_dir = settings
+        .value_of_os("output-dir")
+        .map_or_else(|| PathBuf::from("."), PathBuf::from);
+    watch::run(
+        dirs,
+        &watch::Watch {
+            output_dir: &output_dir,
+            naming: Naming {
+                template: settings.value_of("name"),
+                by_date: settings.is_present("organize-by-date")?,
+            },
+            lock: !settings.is_present("no-lock")?,
+            notify: settings.is_present("notify")?,
+            webhook: settings.value_of("webhook"),
+            battery_saver,
+            options,
+        },
+    )
+}
 
-    let encoded = Encoder::from_image(&DynamicImage::ImageRgb8(out_img))
-        .unwrap()
-        .encode(95.0);
-    let mut file = File::create(output).context("Could not create output")?;
-    file.write_all(&encoded).context("Could not write output")?;
-    file.flush().context("Could not write output")?;
+/// What to do with the screenshots given on the command line.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Command {
+    /// Find photos and warp them. This is what qdcrop does without a command.
+    Crop,
+    /// Find photos and print their corners.
+    Detect,
+    /// Warp photos at corners that were given, without finding them.
+    Warp,
+}
 
-    Ok(())
+/// The options of the commands that crop screenshots.
+fn crop_args(source_help: &str) -> Vec<clap::Arg<'_, '_>> {
+    vec![
+        clap::Arg::with_name("input")
+            .multiple(true),
+        clap::Arg::with_name("output")
+            .short("o")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1),
+        clap::Arg::with_name("output-dir")
+            .long("output-dir")
+            .takes_value(true)
+            .help("Where to save outputs when -o is not given"),
+        clap::Arg::with_name("name")
+            .long("name")
+            .takes_value(true)
+            .value_name("TEMPLATE")
+            .help("Name outputs that are not given with -o after this template, with {stem} for the name of the screenshot and {date} and {time} for when it was taken"),
+        clap::Arg::with_name("organize-by-date")
+            .long("organize-by-date")
+            .help("Put outputs that are not given with -o in YYYY/MM-DD folders for the day their screenshots were taken"),
+        clap::Arg::with_name("rounded-corners")
+            .long("rounded-corners")
+            .help("Extend the straight sides of photos with rounded corners to find the true corners"),
+        clap::Arg::with_name("distortion")
+            .long("distortion")
+            .takes_value(true)
+            .allow_hyphen_values(true)
+            .value_name("K1,K2")
+            .help("Remove radial lens distortion before detecting corners, with OpenCV's k1 and k2 coefficients"),
+        clap::Arg::with_name("margin")
+            .long("margin")
+            .takes_value(true)
+            .allow_hyphen_values(true)
+            .default_value("0")
+            .help("Move the edges of photos outward by this many pixels or percent of the photo before warping, or inward if negative"),
+        clap::Arg::with_name("deskew-only")
+            .long("deskew-only")
+            .help("Only turn photos upright and crop them, without correcting perspective"),
+        clap::Arg::with_name("no-warp")
+            .long("no-warp")
+            .conflicts_with("deskew-only")
+            .help("Cut out the rectangle around each photo without correcting its perspective"),
+        clap::Arg::with_name("inscribed")
+            .long("inscribed")
+            .help("Crop outputs to the largest rectangle inside the screenshot, so no black fill is left where the photo goes past its edges"),
+        clap::Arg::with_name("skip-cropped")
+            .long("skip-cropped")
+            .help("Keep pictures that are already cropped, with the photo filling the picture and no border, as they are instead of warping them"),
+        clap::Arg::with_name("trim-edges")
+            .long("trim-edges")
+            .help("Cut off thin dark lines left by the frame along the edges of outputs"),
+        clap::Arg::with_name("rotate")
+            .long("rotate")
+            .takes_value(true)
+            .possible_values(&["0", "90", "180", "270"])
+            .default_value("0")
+            .help("Turn outputs clockwise by this many degrees"),
+        clap::Arg::with_name("flip-h")
+            .long("flip-h")
+            .help("Mirror outputs left to right, such as photos taken in a mirror"),
+        clap::Arg::with_name("flip-v")
+            .long("flip-v")
+            .help("Mirror outputs top to bottom"),
+        clap::Arg::with_name("straighten")
+            .long("straighten")
+            .help("Turn photos slightly so they are level with the border around them"),
+        clap::Arg::with_name("strip-metadata")
+            .long("strip-metadata")
+            .conflicts_with("xmp")
+            .help("Write outputs without any EXIF and copy no metadata from screenshots, such as before posting them publicly"),
+        clap::Arg::with_name("sidecar")
+            .long("sidecar")
+            .help("Write the corners, confidence, and size of each photo to a JSON file named after its output, like photo.webp.json"),
+        clap::Arg::with_name("use-sidecar")
+            .long("use-sidecar")
+            .help("Use the corners in the JSON sidecars of existing outputs instead of detecting them"),
+        clap::Arg::with_name("xmp")
+            .long("xmp")
+            .help("Write an XMP sidecar next to each output recording the corners, projection, and settings used"),
+        clap::Arg::with_name("preserve-times")
+            .long("preserve-times")
+            .help("Give outputs the modification time of their screenshots, and the creation time on Windows"),
+        clap::Arg::with_name("auto-rotate")
+            .long("auto-rotate")
+            .help("Turn outputs whose text is sideways or upside down (needs the ocr feature)"),
+        clap::Arg::with_name("multiple")
+            .long("multiple")
+            .help("Crop every photo in each screenshot, saving them as name_1.webp, name_2.webp, ..."),
+        clap::Arg::with_name("quality")
+            .long("quality")
+            .takes_value(true)
+            .value_name("0-100")
+            .default_value("95")
+            .help("The WebP quality of outputs"),
+        clap::Arg::with_name("on-conflict")
+            .long("on-conflict")
+            .takes_value(true)
+            .possible_values(ConflictPolicy::NAMES)
+            .default_value("overwrite")
+            .help("What to do when an output file already exists"),
+        clap::Arg::with_name("threshold-mode")
+            .long("threshold-mode")
+            .takes_value(true)
+            .possible_values(ThresholdMode::NAMES)
+            .default_value("adaptive")
+            .help("How to find the border pixels"),
+        clap::Arg::with_name("threshold-radius")
+            .long("threshold-radius")
+            .takes_value(true)
+            .value_name("pixels")
+            .default_value("2")
+            .help("How far --threshold-mode adaptive compares each pixel with its neighbors. Raise it for high resolution screenshots with soft borders"),
+        clap::Arg::with_name("black-threshold")
+            .long("black-threshold")
+            .takes_value(true)
+            .value_name("0-255")
+            .help("Also count pixels this dark or darker as border pixels"),
+        clap::Arg::with_name("border-color")
+            .long("border-color")
+            .takes_value(true)
+            .value_name("color")
+            .default_value("black")
+            .help("The color of the frame around the photo: black, auto, or a hex color like #ffffff"),
+        clap::Arg::with_name("border-tolerance")
+            .long("border-tolerance")
+            .takes_value(true)
+            .default_value("40")
+            .help("How far (0-255 in each channel) a pixel may be from --border-color and still be part of the frame"),
+        clap::Arg::with_name("detector")
+            .long("detector")
+            .takes_value(true)
+            .value_name("detector,...")
+            .default_value("nearest")
+            .help("How to find the corners of the photo: nearest, hough, contour, edges, ransac, or onnx. When several are given, each is tried in order until one is confident"),
+        clap::Arg::with_name("aspect")
+            .long("aspect")
+            .takes_value(true)
+            .value_name("W:H")
+            .default_value("16:9")
+            .help("The proportions of the photos, like 4:3 or 1:1, or auto to measure each photo from its perspective"),
+        clap::Arg::with_name("orientation")
+            .long("orientation")
+            .takes_value(true)
+            .possible_values(Orientation::NAMES)
+            .default_value("auto")
+            .help("Whether photos are landscape or portrait. auto turns --aspect to match the shape of each photo"),
+        clap::Arg::with_name("portrait")
+            .long("portrait")
+            .help("The same as --orientation portrait"),
+        clap::Arg::with_name("max-width")
+            .long("max-width")
+            .takes_value(true)
+            .value_name("pixels")
+            .help("Shrink outputs wider than this. Defaults to --max-height at --aspect"),
+        clap::Arg::with_name("max-height")
+            .long("max-height")
+            .takes_value(true)
+            .value_name("pixels")
+            .help("Shrink outputs taller than this. Defaults to 1024, or --max-width at --aspect"),
+        clap::Arg::with_name("no-downscale")
+            .long("no-downscale")
+            .conflicts_with_all(&["max-width", "max-height"])
+            .help("Keep the resolution of the photo in the screenshot instead of shrinking outputs to 1024 pixels tall"),
+        clap::Arg::with_name("scale-mode")
+            .long("scale-mode")
+            .takes_value(true)
+            .possible_values(ScaleMode::NAMES)
+            .default_value("auto")
+            .help("Whether to warp directly to the output size or resize afterward"),
+        clap::Arg::with_name("resize-filter")
+            .long("resize-filter")
+            .takes_value(true)
+            .possible_values(ResizeFilter::NAMES)
+            .default_value("lanczos3")
+            .help("The filter used to resize after warping"),
+        clap::Arg::with_name("backend")
+            .long("backend")
+            .takes_value(true)
+            .possible_values(Backend::NAMES)
+            .default_value("auto")
+            .help("Where to warp: cpu, simd for SSE4.1/AVX2, gpu for the graphics card (needs the gpu feature), or auto for the fastest of cpu and simd"),
+        clap::Arg::with_name("interpolation")
+            .long("interpolation")
+            .takes_value(true)
+            .possible_values(Interpolation::NAMES)
+            .default_value("bicubic")
+            .help("How the warp samples the screenshot: nearest is fastest, bilinear avoids ringing around text, lanczos avoids aliasing when shrinking"),
+        clap::Arg::with_name("corners")
+            .long("corners")
+            .takes_value(true)
+            .value_name("x0,y0,x1,y1,x2,y2,x3,y3")
+            .help("Use these corners, clockwise from the top left, instead of detecting them"),
+        clap::Arg::with_name("matrix")
+            .long("matrix")
+            .takes_value(true)
+            .allow_hyphen_values(true)
+            .conflicts_with("corners")
+            .value_name("a,b,c,d,e,f,g,h")
+            .help("Use this projection from the screenshot to the output, row by row, instead of detecting corners. The output size is --max-width by --max-height."),
+        clap::Arg::with_name("model")
+            .long("model")
+            .takes_value(true)
+            .value_name("model.onnx")
+            .help("The ONNX model used by --detector onnx"),
+        clap::Arg::with_name("exclude")
+            .long("exclude")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .value_name("x,y,width,height")
+            .help("Ignore a part of the screenshot, such as an overlay, when looking for corners. May be repeated."),
+        clap::Arg::with_name("debug-dir")
+            .long("debug-dir")
+            .takes_value(true)
+            .help("Save images showing how each photo was detected to this directory"),
+        clap::Arg::with_name("detect-size")
+            .long("detect-size")
+            .takes_value(true)
+            .value_name("pixels")
+            .help("Find the photo in a copy of the screenshot shrunk to this size, for speed"),
+        clap::Arg::with_name("corner-window")
+            .long("corner-window")
+            .takes_value(true)
+            .value_name("percent")
+            .help("Look for the corners of the photo in windows of this percentage of the screenshot's width and height at its corners first, for speed"),
+        clap::Arg::with_name("min-area")
+            .long("min-area")
+            .takes_value(true)
+            .value_name("percent")
+            .default_value("5")
+            .help("Reject photos that cover less than this percentage of the picture"),
+        clap::Arg::with_name("min-confidence")
+            .long("min-confidence")
+            .takes_value(true)
+            .default_value("0")
+            .help("The lowest detection confidence, from 0 to 1, that is cropped"),
+        clap::Arg::with_name("low-confidence")
+            .long("low-confidence")
+            .takes_value(true)
+            .possible_values(LowConfidence::NAMES)
+            .default_value("report")
+            .help("What to do with pictures below --min-confidence"),
+        clap::Arg::with_name("verify-outputs")
+            .long("verify-outputs")
+            .help("Read each output back after writing it and check that it is correct"),
+        clap::Arg::with_name("tile-pyramid")
+            .long("tile-pyramid")
+            .help("Also write a Deep Zoom (DZI) tile pyramid of each output for web viewers"),
+        clap::Arg::with_name("contact-sheet")
+            .long("contact-sheet")
+            .takes_value(true)
+            .value_name("path")
+            .help("Also tile every cropped photo onto a WebP contact sheet, numbered when they need more than one"),
+        clap::Arg::with_name("sheet-grid")
+            .long("sheet-grid")
+            .takes_value(true)
+            .value_name("COLUMNSxROWS")
+            .default_value("4x4")
+            .help("How many photos go across and down each contact sheet"),
+        clap::Arg::with_name("sheet-cell")
+            .long("sheet-cell")
+            .takes_value(true)
+            .value_name("WIDTHxHEIGHT")
+            .default_value("480x270")
+            .help("The largest size of each photo on a contact sheet"),
+        clap::Arg::with_name("battery-saver")
+            .long("battery-saver")
+            .help("While running on battery, crop fewer screenshots at a time, encode faster, and pause watch mode"),
+        clap::Arg::with_name("notify")
+            .long("notify")
+            .help("Show a desktop notification when the screenshots are cropped"),
+        clap::Arg::with_name("webhook")
+            .long("webhook")
+            .takes_value(true)
+            .value_name("url")
+            .help("Post the cropped photos to a Discord webhook"),
+        clap::Arg::with_name("files-from")
+            .long("files-from")
+            .takes_value(true)
+            .value_name("FILE")
+            .help("Also convert the screenshots listed in this file, one on each line, or in standard input if it is -"),
+        clap::Arg::with_name("failed-list")
+            .long("failed-list")
+            .takes_value(true)
+            .value_name("FILE")
+            .default_value("qdcrop-failed.txt")
+            .help("Where to list the screenshots that failed, for --files-from"),
+        clap::Arg::with_name("fail-fast")
+            .long("fail-fast")
+            .help("Stop at the first screenshot that fails instead of converting the rest"),
+        clap::Arg::with_name("cache")
+            .long("cache")
+            .takes_value(true)
+            .value_name("FILE")
+            .help("Record cropped screenshots in this file and skip those that did not change since, unless the settings changed"),
+        clap::Arg::with_name("max-memory")
+            .long("max-memory")
+            .takes_value(true)
+            .value_name("SIZE")
+            .help("Crop fewer screenshots at the same time so that they fit in this much memory, such as 4G or 512M"),
+        clap::Arg::with_name("timings")
+            .long("timings")
+            .help("Report how long decoding, thresholding, detection, warping, and encoding took for each screenshot and in total"),
+        clap::Arg::with_name("no-lock")
+            .long("no-lock")
+            .help("Do not lock the output directories against other qdcrop runs"),
+        clap::Arg::with_name("vrchat")
+            .long("vrchat")
+            .conflicts_with("auto-source")
+            .help("Crop every new photo in VRChat's photo folder, including a folder chosen in VRChat's settings. Photos that were already cropped are skipped unless --on-conflict is given."),
+        clap::Arg::with_name("auto-source")
+            .long("auto-source")
+            .takes_value(true)
+            .help("Also crop every screenshot in a game's screenshot folder")
+            .long_help(source_help),
+        clap::Arg::with_name("watch")
+            .long("watch")
+            .help("After cropping the screenshots of --auto-source or --vrchat, keep running and crop new ones as they are saved, until stopped with Ctrl+C"),
+        clap::Arg::with_name("export-annotations")
+            .long("export-annotations")
+            .takes_value(true)
+            .value_name("path")
+            .help("Save the detected corners for labeling tools, as one COCO file or a directory of LabelMe files"),
+        clap::Arg::with_name("annotation-format")
+            .long("annotation-format")
+            .takes_value(true)
+            .possible_values(annotations::Format::NAMES)
+            .default_value("coco")
+            .help("The format of --export-annotations"),
+        clap::Arg::with_name("annotations")
+            .long("annotations")
+            .takes_value(true)
+            .value_name("path")
+            .help("Use corrected corners from a COCO file, a LabelMe file, or a directory of LabelMe files, and only crop the pictures they cover"),
+        clap::Arg::with_name("corners-from")
+            .long("corners-from")
+            .takes_value(true)
+            .value_name("path")
+            .conflicts_with("annotations")
+            .help("Use the corners printed by qdcrop detect, from a file or - for standard input, and only crop the pictures they cover"),
+        clap::Arg::with_name("allow-fallback")
+            .long("allow-fallback")
+            .help("Continue without options that need features missing from this build of qdcrop"),
+    ]
 }
 
 fn main() -> anyhow::Result<()> {
+    let source_help = format!(
+        "Also crop every screenshot in a game's screenshot folder. One of: {}, steam:<app id> for one game's Steam screenshots, or folder:<path> for any folder",
+        source::SOURCES
+            .iter()
+            .map(|s| s.name())
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
     let matches = clap::App::new("qdcrop")
         .author("nil")
         .about("Straighten and remove borders from your Questダンス集会 pictures.")
-        .arg(clap::Arg::with_name("input").required(true).multiple(true))
+        .args(&crop_args(&source_help))
         .arg(
-            clap::Arg::with_name("output")
-                .short("o")
-                .takes_value(true)
-                .multiple(true)
-                .number_of_values(1),
+            clap::Arg::with_name("portable")
+                .long("portable")
+                .global(true)
+                .help("Keep settings and other files in a qdcrop-data folder next to qdcrop instead of your user folder"),
+        )
+        .setting(clap::AppSettings::SubcommandsNegateReqs)
+        .subcommand(
+            clap::SubCommand::with_name("crop")
+                .about("Find the photos in screenshots and crop them. This is the default when no command is given.")
+                .args(&crop_args(&source_help)),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("detect")
+                .about("Find the photos in screenshots and print their corners without writing any pictures.")
+                .args(&crop_args(&source_help)),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("warp")
+                .about("Crop screenshots at the corners given by --corners, --matrix, --corners-from, --annotations, or --use-sidecar, without finding them.")
+                .args(&crop_args(&source_help)),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("selftest")
+                .about("Check that every enabled backend produces the same output.")
+                .arg(clap::Arg::with_name("input").multiple(true))
+                .arg(
+                    clap::Arg::with_name("tolerance")
+                        .long("tolerance")
+                        .takes_value(true)
+                        .default_value("1.0")
+                        .help("The largest acceptable mean difference per channel between backends"),
+                )
+                .arg(
+                    clap::Arg::with_name("resize-tolerance")
+                        .long("resize-tolerance")
+                        .takes_value(true)
+                        .default_value("8.0")
+                        .help("The largest acceptable mean difference per channel between the fused warp and two pass resizing"),
+                ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("selfcheck")
+                .about("Crop a built-in set of synthetic pictures and check every step of the results."),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("stats")
+                .about("Summarize a screenshot archive and estimate how long cropping it would take.")
+                .arg(clap::Arg::with_name("dir").required(true))
+                .arg(
+                    clap::Arg::with_name("sample")
+                        .long("sample")
+                        .takes_value(true)
+                        .default_value("20")
+                        .help("How many pictures to crop to estimate the time and size of a full run"),
+                ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("watch")
+                .about("Crop every new screenshot as soon as it is saved in a folder, until stopped with Ctrl+C.")
+                .arg(clap::Arg::with_name("dir").required(true)),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("serve")
+                .about("Crop screenshots uploaded over HTTP or dragged into a page in the browser, until stopped with Ctrl+C.")
+                .arg(
+                    clap::Arg::with_name("listen")
+                        .long("listen")
+                        .takes_value(true)
+                        .value_name("address")
+                        .default_value("127.0.0.1:8080")
+                        .help("The address and port to listen on"),
+                ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("gallery")
+                .about("Write an HTML gallery of the cropped photos in a folder, grouped by the day they were taken.")
+                .arg(clap::Arg::with_name("dir").required(true))
+                .arg(
+                    clap::Arg::with_name("title")
+                        .long("title")
+                        .takes_value(true)
+                        .help("The title of the gallery, instead of the name of the folder"),
+                ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("init")
+                .about("Answer a few questions to set up qdcrop for your screenshots."),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("install-shell")
+                .about("Add Crop with qdcrop to the right-click menu of pictures in Windows Explorer."),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("uninstall-shell")
+                .about("Remove Crop with qdcrop from the right-click menu of pictures."),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("config")
+                .about("Manage the default settings used when options are not given.")
+                .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    clap::SubCommand::with_name("edit")
+                        .about("Open the configuration file in $VISUAL or $EDITOR"),
+                )
+                .subcommand(
+                    clap::SubCommand::with_name("show").about("Print the configuration file"),
+                )
+                .subcommand(
+                    clap::SubCommand::with_name("path")
+                        .about("Print the path of the configuration file"),
+                ),
         )
         .get_matches();
 
-    let mut input = matches.values_of_os("input").unwrap();
+    // --portable may be given after a subcommand.
+    let mut portable = false;
+    let mut level = Some(&matches);
+    while let Some(m) = level {
+        portable |= m.is_present("portable");
+        level = m.subcommand().1;
+    }
+    config::set_portable(portable);
+    if matches.subcommand_matches("init").is_some() {
+        return init::run();
+    }
+    if matches.subcommand_matches("install-shell").is_some() {
+        return shell::install();
+    }
+    if matches.subcommand_matches("uninstall-shell").is_some() {
+        return shell::uninstall();
+    }
+    if let Some(matches) = matches.subcommand_matches("config") {
+        return config::run(matches);
+    }
+
+    if let Some(matches) = matches.subcommand_matches("selftest") {
+        let inputs: Vec<_> = matches
+            .values_of_os("input")
+            .unwrap_or_default()
+            .map(Path::new)
+            .collect();
+        let tolerances = selftest::Tolerances {
+            backend: matches
+                .value_of("tolerance")
+                .unwrap()
+                .parse()
+                .context("Invalid tolerance")?,
+            resize: matches
+                .value_of("resize-tolerance")
+                .unwrap()
+                .parse()
+                .context("Invalid resize tolerance")?,
+        };
+        if !selftest::run(&inputs, &tolerances)? {
+            eprintln!("Self test failed");
+            process::exit(1);
+        }
+        return Ok(());
+    }
+
+    if let Some(matches) = matches.subcommand_matches("gallery") {
+        return gallery::run(
+            Path::new(matches.value_of_os("dir").unwrap()),
+            matches.value_of("title"),
+        );
+    }
+
+    if matches.subcommand_matches("selfcheck").is_some() {
+        if !selfcheck::run()? {
+            eprintln!("Self check failed");
+            process::exit(1);
+        }
+        return Ok(());
+    }
+
+    let (command, matches) = match matches.subcommand() {
+        ("crop", Some(matches)) => (Command::Crop, matches),
+        ("detect", Some(matches)) => (Command::Detect, matches),
+        ("warp", Some(matches)) => (Command::Warp, matches),
+        _ => (Command::Crop, &matches),
+    };
+    let settings = config::Settings::new(matches, config::Config::load()?);
+    let allow_fallback = settings.is_present("allow-fallback")?;
+    // The power status is checked as the work goes on, since a long batch or a watched folder
+    // can outlast the charger.
+    let battery_saver = if settings.is_present("battery-saver")? {
+        Some(power::Saver::new(rayon::current_num_threads()))
+    } else {
+        None
+    };
+    let aspect: AspectMode = settings.value_of("aspect").unwrap().parse()?;
+    let mut options = CropOptions {
+        aspect,
+        orientation: if settings.is_present("portrait")? {
+            Orientation::Portrait
+        } else {
+            settings.value_of("orientation").unwrap().parse()?
+        },
+        conflict: if settings.is_present("vrchat")? && !settings.is_given("on-conflict") {
+            ConflictPolicy::Skip
+        } else {
+            settings.value_of("on-conflict").unwrap().parse()?
+        },
+        threshold_mode: settings.value_of("threshold-mode").unwrap().parse()?,
+        threshold_radius: settings
+            .value_of("threshold-radius")
+            .unwrap()
+            .parse()
+            .context("Invalid threshold radius")?,
+        black_threshold: settings
+            .value_of("black-threshold")
+            .map(|v| v.parse().context("Invalid black threshold"))
+            .transpose()?,
+        border_color: settings.value_of("border-color").unwrap().parse()?,
+        border_tolerance: settings
+            .value_of("border-tolerance")
+            .unwrap()
+            .parse()
+            .context("Invalid border tolerance")?,
+        detectors: features::available(
+            "detector",
+            settings.value_of("detector").unwrap().split(','),
+            Detector::NAMES,
+            allow_fallback,
+        )?
+        .into_iter()
+        .map(|name| Ok(Arc::new(name.parse::<Detector>()?) as Arc<dyn CornerDetector>))
+        .collect::<anyhow::Result<_>>()?,
+        max_size: if settings.is_present("no-downscale")? {
+            MaxSize::NONE
+        } else {
+            MaxSize::from_limits(
+                settings
+                    .value_of("max-width")
+                    .map(|v| v.parse().context("Invalid maximum width"))
+                    .transpose()?,
+                settings
+                    .value_of("max-height")
+                    .map(|v| v.parse().context("Invalid maximum height"))
+                    .transpose()?,
+                match aspect {
+                    AspectMode::Fixed(aspect) => aspect,
+                    AspectMode::Auto => Aspect::default(),
+                },
+            )
+        },
+        scale_mode: settings.value_of("scale-mode").unwrap().parse()?,
+        resize_filter: settings.value_of("resize-filter").unwrap().parse()?,
+        interpolation: settings.value_of("interpolation").unwrap().parse()?,
+        backend: features::available(
+            "backend",
+            settings.value_of("backend"),
+            &iter::once("auto")
+                .chain(Backend::ALL.iter().map(|backend| backend.name()))
+                .collect::<Vec<_>>(),
+            allow_fallback,
+        )?
+        .first()
+        .map_or(Ok(Backend::detect()), |b| b.parse())?,
+        corners: settings
+            .value_of("corners")
+            .map(parse_quad)
+            .transpose()?
+            .into_iter()
+            .collect(),
+        model: settings
+            .value_of_os("model")
+            .map(|path| onnx::Model::load(Path::new(path)).map(Arc::new))
+            .transpose()?,
+        exclude: settings
+            .values_of("exclude")
+            .into_iter()
+            .map(parse_region)
+            .collect::<anyhow::Result<_>>()?,
+        debug_dir: settings.value_of_os("debug-dir").map(PathBuf::from),
+        min_area: settings
+            .value_of("min-area")
+            .unwrap()
+            .parse()
+            .context("Invalid minimum area")?,
+        min_confidence: settings
+            .value_of("min-confidence")
+            .unwrap()
+            .parse()
+            .context("Invalid minimum confidence")?,
+        low_confidence: settings.value_of("low-confidence").unwrap().parse()?,
+        verify_outputs: settings.is_present("verify-outputs")?,
+        tile_pyramid: settings.is_present("tile-pyramid")?,
+        preserve_times: settings.is_present("preserve-times")?,
+        xmp_sidecar: settings.is_present("xmp")?,
+        json_sidecar: settings.is_present("sidecar")?,
+        use_sidecar: settings.is_present("use-sidecar")?,
+        strip_metadata: settings.is_present("strip-metadata")?,
+        fast_encode: false,
+        multiple: settings.is_present("multiple")?,
+        warp_only: command == Command::Warp,
+        rounded_corners: settings.is_present("rounded-corners")?,
+        straighten: settings.is_present("straighten")?,
+        distortion: settings
+            .value_of("distortion")
+            .map(str::parse)
+            .transpose()?,
+        margin: settings.value_of("margin").unwrap().parse()?,
+        deskew_only: settings.is_present("deskew-only")?,
+        no_warp: settings.is_present("no-warp")?,
+        inscribed: settings.is_present("inscribed")?,
+        trim_edges: settings.is_present("trim-edges")?,
+        rotate: settings
+            .value_of("rotate")
+            .unwrap()
+            .parse()
+            .context("Invalid rotation")?,
+        flip_horizontal: settings.is_present("flip-h")?,
+        flip_vertical: settings.is_present("flip-v")?,
+        auto_rotate: settings.is_present("auto-rotate")?
+            && features::flag("auto-rotate", &features::OCR, allow_fallback)?,
+        quality: settings
+            .value_of("quality")
+            .unwrap()
+            .parse()
+            .context("Quality must be a number from 0 to 100")?,
+        detect_size: settings
+            .value_of("detect-size")
+            .map(|v| v.parse().context("Invalid detection size"))
+            .transpose()?,
+        corner_window: settings
+            .value_of("corner-window")
+            .map(|v| v.parse().context("Invalid corner window"))
+            .transpose()?,
+        skip_cropped: settings.is_present("skip-cropped")?,
+    };
+    if let Some(matrix) = settings.value_of("matrix") {
+        let size = match options.max_size {
+            MaxSize {
+                width: Some(width),
+                height: Some(height),
+            } => (width, height),
+            _ => bail!("--matrix cannot be used with --no-downscale. Give the size of the output with --max-width and --max-height."),
+        };
+        options.corners = vec![parse_matrix(matrix, size)?];
+    }
+    options.validate()?;
+    let onnx = Detector::Onnx.name();
+    if options.detectors.iter().any(|d| d.name() == onnx) && options.model.is_none() {
+        eprintln!("Warning: --detector onnx needs --model. The nearest detector is used instead.");
+    }
+
+    if let Some(matches) = matches.subcommand_matches("stats") {
+        let sample = matches
+            .value_of("sample")
+            .unwrap()
+            .parse()
+            .context("Invalid sample size")?;
+        return stats::run(
+            Path::new(matches.value_of_os("dir").unwrap()),
+            sample,
+            &options,
+        );
+    }
+
+    if let Some(watch) = matches.subcommand_matches("watch") {
+        return watch_dirs(
+            &[PathBuf::from(watch.value_of_os("dir").unwrap())],
+            &settings,
+            battery_saver.as_ref(),
+            &options,
+        );
+    }
+
+    if let Some(serve) = matches.subcommand_matches("serve") {
+        let address = serve
+            .value_of("listen")
+            .unwrap()
+            .parse()
+            .context("Invalid listen address")?;
+        return serve::run(address, &options);
+    }
+
+    let mut inputs: Vec<_> = matches
+        .values_of_os("input")
+        .unwrap_or_default()
+        .map(PathBuf::from)
+        .collect();
+    if let Some(path) = matches.value_of_os("files-from") {
+        inputs.extend(filelist::read(Path::new(path))?);
+    }
+    let annotated = match (
+        matches.value_of_os("annotations"),
+        matches.value_of_os("corners-from"),
+    ) {
+        (Some(path), _) => Some(annotations::import(Path::new(path))?),
+        (None, Some(path)) => Some(detect::import(Path::new(path))?),
+        (None, None) => None,
+    };
+    // A source from the configuration is only used when no inputs are given.
+    let auto_source = if settings.is_present("vrchat")? {
+        Some("vrchat")
+    } else if (inputs.is_empty() && annotated.is_none())
+        || matches.occurrences_of("auto-source") > 0
+    {
+        settings.value_of("auto-source")
+    } else {
+        None
+    };
+    // With --watch, the folders of the source are watched once the screenshots already in them
+    // are cropped.
+    let mut watched = Vec::new();
+    if let Some(name) = auto_source {
+        let source = source::find(name).with_context(|| format!("Unknown source {}", name))?;
+        inputs.extend(source::discover(&*source)?);
+        if command == Command::Crop && settings.is_present("watch")? {
+            watched = source::existing_directories(&*source);
+            if inputs.is_empty() {
+                return watch_dirs(&watched, &settings, battery_saver.as_ref(), &options);
+            }
+        }
+        if inputs.is_empty() {
+            eprintln!("No screenshots were found.");
+            process::exit(1);
+        }
+    }
+    if let Some(annotated) = &annotated {
+        // Only re-render the screenshots that have corrected corners.
+        if inputs.is_empty() {
+            inputs = annotated.keys().cloned().collect();
+            inputs.sort();
+        } else {
+            inputs.retain(|input| annotated.contains_key(&annotations::key(input)));
+        }
+    }
+    if inputs.is_empty() {
+        eprintln!("No input files were given. Run qdcrop init to set up a screenshot folder, or qdcrop --help for usage.");
+        process::exit(1);
+    }
+    if command == Command::Detect {
+        let failed = detect::run(&inputs, &options);
+        if failed > 0 {
+            eprintln!("Failed to detect {} inputs", failed);
+            process::exit(1);
+        }
+        return Ok(());
+    }
+    let output_dir = settings.value_of_os("output-dir").map(Path::new);
+    let naming = Naming {
+        template: settings.value_of("name"),
+        by_date: settings.is_present("organize-by-date")?,
+    };
+
     let mut output = matches.values_of_os("output").unwrap_or_default();
-    let jobs: Vec<_> = if input.len() > 1 {
-        if output.len() > 1 && output.len() != input.len() {
+    let jobs: Vec<_> = if inputs.len() > 1 {
+        if output.len() > 1 && output.len() != inputs.len() {
             eprintln!("When multiple inputs and outputs are specified, there must be an equal number of inputs and outputs.");
             process::exit(1);
         }
@@ -276,19 +1438,20 @@ fn main() -> anyhow::Result<()> {
             let base = output
                 .next()
                 .map(|o| Path::new(o))
+                .or(output_dir)
                 .unwrap_or_else(|| Path::new("."));
-            input
+            inputs
+                .iter()
                 .map(|i| {
-                    let i = Path::new(i);
-                    let mut p = base.join(i.file_name().unwrap());
-                    p.set_extension("webp");
-                    (i, Cow::Owned(p))
+                    let i = i.as_path();
+                    (i, Cow::Owned(base.join(output_name(i, naming))))
                 })
                 .collect()
         } else {
-            input
+            inputs
+                .iter()
                 .zip(output)
-                .map(|(i, o)| (Path::new(i), Cow::Borrowed(Path::new(o))))
+                .map(|(i, o)| (i.as_path(), Cow::Borrowed(Path::new(o))))
                 .collect()
         }
     } else {
@@ -296,37 +1459,291 @@ fn main() -> anyhow::Result<()> {
             eprintln!("When one input is specified, at most one output can be specified.");
             process::exit(1);
         }
-        let input = Path::new(input.next().unwrap());
+        let input = inputs[0].as_path();
         let output = output
             .next()
             .map(|v| Cow::Borrowed(Path::new(v)))
             .unwrap_or_else(|| {
-                let mut p = PathBuf::from(input.file_name().unwrap());
-                p.set_extension("webp");
-                Cow::Owned(p)
+                let name = output_name(input, naming);
+                Cow::Owned(
+                    output_dir
+                        .map(|dir| dir.join(&name))
+                        .unwrap_or_else(|| PathBuf::from(name)),
+                )
             });
         vec![(input, output)]
     };
 
-    let failed = jobs
-        .into_par_iter()
+    let mut dirs: Vec<_> = jobs
+        .iter()
+        .map(|(_, output)| match output.parent() {
+            Some(parent) if parent != Path::new("") => parent.to_owned(),
+            _ => PathBuf::from("."),
+        })
+        .collect();
+    dirs.sort();
+    dirs.dedup();
+    if naming.by_date {
+        for dir in &dirs {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("Could not create {}", dir.to_string_lossy()))?;
+        }
+    }
+    // The notification only names the output folder when there is one.
+    let notify_dir = match dirs.as_slice() {
+        [dir] => Some(dir.clone()),
+        _ => None,
+    };
+    let locks = if settings.is_present("no-lock")? {
+        Vec::new()
+    } else {
+        dirs.iter()
+            .filter(|dir| dir.is_dir())
+            .map(|dir| lock::SessionLock::acquire(dir))
+            .collect::<anyhow::Result<Vec<_>>>()?
+    };
+
+    let show_timings = settings.is_present("timings")?;
+    let budget = settings
+        .value_of("max-memory")
+        .map(memory::parse_size)
+        .transpose()
+        .context("Invalid --max-memory")?
+        .map(memory::Budget::new);
+    let mut total_timings = (0, Timings::default());
+    // Edited sidecars change the corners without changing the screenshot or the settings, so
+    // the cache is not used with them.
+    let mut cache = match settings.value_of_os("cache") {
+        Some(path) if !options.use_sidecar => Some(cache::Cache::load(Path::new(path))),
+        _ => None,
+    };
+    let jobs: Vec<_> = jobs
+        .into_iter()
         .map(|(input, output)| {
-            if let Err(error) = crop(input, output) {
-                eprintln!(
-                    "Error while converting {}: {}",
-                    input.to_string_lossy(),
-                    error
-                );
-                false
+            let options = match annotated
+                .as_ref()
+                .and_then(|a| a.get(&annotations::key(input)))
+            {
+                Some(quads) => Cow::Owned(CropOptions {
+                    corners: quads.clone(),
+                    ..options.clone()
+                }),
+                None => Cow::Borrowed(&options),
+            };
+            let hash = cache.as_ref().map(|_| cache::options_hash(&options));
+            (input, output, options, hash)
+        })
+        .collect();
+    let (unchanged, jobs): (Vec<_>, Vec<_>) =
+        jobs.into_iter().partition(|(input, output, _, hash)| {
+            matches!(
+                (&cache, hash),
+                (Some(cache), Some(hash)) if cache.is_current(input, output, *hash)
+            )
+        });
+    for (input, _, _, _) in &unchanged {
+        eprintln!(
+            "Skipped {} because it did not change since it was cropped",
+            input.to_string_lossy()
+        );
+    }
+    let fail_fast = settings.is_present("fail-fast")?;
+    let queued: Vec<&Path> = jobs.iter().map(|(input, _, _, _)| *input).collect();
+    let mut results: Vec<_> = pipeline::run(
+        jobs,
+        |(input, output, options, hash)| {
+            // While on battery, fewer screenshots are cropped at a time and encoded faster.
+            let slot = battery_saver.as_ref().and_then(|saver| saver.throttle());
+            let options = match slot {
+                Some(_) => Cow::Owned(CropOptions {
+                    fast_encode: true,
+                    ..options.into_owned()
+                }),
+                None => options,
+            };
+            let screenshot = read_screenshot(input, &output, options);
+            // The permit is taken here, outside the thread pool, and kept until the screenshot
+            // is cropped.
+            let permit = match (&budget, &screenshot) {
+                (Some(budget), Ok(Some(screenshot))) => {
+                    Some(budget.acquire(memory::footprint(&screenshot.data)))
+                }
+                _ => None,
+            };
+            (input, output, hash, screenshot, (slot, permit))
+        },
+        |(input, output, hash, screenshot, permit), writer| {
+            let crop = || {
+                screenshot.and_then(|screenshot| {
+                    screenshot
+                        .map(|screenshot| crop_screenshot(screenshot, Some(writer)))
+                        .transpose()
+                })
+            };
+            let (result, timings) = if show_timings {
+                let (result, timings) = timings::measure(crop);
+                (result, Some(timings))
             } else {
-                true
+                (crop(), None)
+            };
+            drop(permit);
+            (input, output, hash, result, timings)
+        },
+        |(_, _, _, result, _)| fail_fast && result.is_err(),
+    )
+    .into_iter()
+    .map(|(input, output, hash, result, timings)| {
+        if let (Some(timings), Ok(Some(_))) = (timings, &result) {
+            eprintln!("{}: {}", input.to_string_lossy(), timings);
+            total_timings.0 += 1;
+            total_timings.1 += timings;
+        }
+        let result = result.and_then(|mut cropped| {
+            if let Some(cropped) = &mut cropped {
+                for write in cropped.writes.drain(..) {
+                    write.wait()?;
+                }
+            }
+            Ok(cropped)
+        });
+        (input, output, hash, result)
+    })
+    .map(|(input, output, hash, result)| match result {
+        Ok(Some(cropped)) => {
+            if let (Some(cache), Some(hash)) = (&mut cache, hash) {
+                cache.record(input, &output, hash, &cropped.outputs);
             }
+            (input, Ok(Some(cropped)))
+        }
+        Ok(None) => {
+            eprintln!(
+                "Skipped {} because {} already exists",
+                input.to_string_lossy(),
+                output.to_string_lossy()
+            );
+            (input, Ok(None))
+        }
+        Err(error) => {
+            eprintln!(
+                "Error while converting {}: {}",
+                input.to_string_lossy(),
+                error
+            );
+            (input, Err(()))
+        }
+    })
+    .collect();
+    pool::clear();
+    let converted: HashSet<&Path> = results.iter().map(|(input, _)| *input).collect();
+    let stopped: Vec<&Path> = queued
+        .into_iter()
+        .filter(|input| !converted.contains(input))
+        .collect();
+    if !stopped.is_empty() {
+        eprintln!(
+            "Stopped after the first error; {} inputs were not converted",
+            stopped.len()
+        );
+    }
+    results.extend(
+        unchanged
+            .into_iter()
+            .map(|(input, _, _, _)| (input, Ok(None))),
+    );
+    if let Some(cache) = &cache {
+        cache.save()?;
+    }
+    if show_timings && total_timings.0 > 0 {
+        let (count, total) = total_timings;
+        eprintln!("Total for {} screenshots: {}", count, total);
+        eprintln!(
+            "Average: {:.1} ms per screenshot",
+            total.total().as_secs_f64() * 1000.0 / count as f64
+        );
+    }
+    let failed = results.iter().filter(|(_, r)| r.is_err()).count();
+    let retry: Vec<&Path> = results
+        .iter()
+        .filter(|(_, r)| r.is_err())
+        .map(|(input, _)| *input)
+        .chain(stopped.iter().copied())
+        .collect();
+    let path = Path::new(settings.value_of_os("failed-list").unwrap());
+    if retry.is_empty() {
+        // A list left by an earlier run would retry screenshots that are fine now.
+        filelist::remove(path)?;
+    } else {
+        filelist::write(path, &retry)?;
+        eprintln!(
+            "Listed the inputs to retry in {}; retry them with --files-from {}",
+            path.to_string_lossy(),
+            path.to_string_lossy()
+        );
+    }
+    if settings.is_present("notify")? {
+        let summary = notification::Summary {
+            cropped: results
+                .iter()
+                .filter(|(_, r)| matches!(r, Ok(Some(_))))
+                .count(),
+            skipped: results
+                .iter()
+                .filter(|(_, r)| matches!(r, Ok(None)))
+                .count(),
+            failed,
+        };
+        notification::send(&summary, notify_dir.as_deref());
+    }
+    if let Some(path) = settings.value_of_os("export-annotations") {
+        let annotations: Vec<_> = results
+            .iter()
+            .filter_map(|(input, result)| match result {
+                Ok(Some(cropped)) => Some(annotations::Annotation {
+                    input,
+                    size: cropped.size,
+                    quads: cropped.detections.iter().map(|d| d.corners).collect(),
+                }),
+                _ => None,
+            })
+            .collect();
+        let format = settings.value_of("annotation-format").unwrap().parse()?;
+        annotations::export(Path::new(path), format, &annotations)?;
+    }
+    let outputs: Vec<_> = results
+        .iter()
+        .flat_map(|(_, result)| match result {
+            Ok(Some(cropped)) => cropped.outputs.as_slice(),
+            _ => &[],
         })
-        .filter(|success| !success)
-        .count();
+        .cloned()
+        .collect();
+    if let (Some(path), false) = (settings.value_of_os("contact-sheet"), outputs.is_empty()) {
+        let (columns, rows) = sheet::parse_pair(settings.value_of("sheet-grid").unwrap())
+            .context("Invalid contact sheet grid")?;
+        let (cell_width, cell_height) = sheet::parse_pair(settings.value_of("sheet-cell").unwrap())
+            .context("Invalid contact sheet cell size")?;
+        let layout = sheet::Layout {
+            columns,
+            rows,
+            cell_width,
+            cell_height,
+        };
+        for sheet in sheet::write(&outputs, Path::new(path), &layout, &options)? {
+            eprintln!("Wrote contact sheet {}", sheet.to_string_lossy());
+        }
+    }
+    if let Some(url) = settings.value_of("webhook") {
+        webhook::post(url, &outputs)?;
+    }
+    drop(locks);
     if failed > 0 {
         eprintln!("Failed to convert {} inputs", failed);
-        process::exit(1);
+        if watched.is_empty() {
+            process::exit(1);
+        }
+    }
+    if !watched.is_empty() {
+        return watch_dirs(&watched, &settings, battery_saver.as_ref(), &options);
     }
 
     Ok(())
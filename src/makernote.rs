@@ -0,0 +1,114 @@
+use std::fmt::Write;
+
+use image::RgbImage;
+use qdcrop::{CropOptions, Detection};
+
+use crate::exif::{tag, Exif, Value};
+
+/// The mean and standard deviation of each channel of an image.
+#[derive(Clone, Copy, Debug)]
+pub struct ColorStatistics {
+    pub mean: [f64; 3],
+    pub stddev: [f64; 3],
+}
+
+impl ColorStatistics {
+    /// Measure an image.
+    pub fn of(img: &RgbImage) -> Self {
+        let mut sum = [0u64; 3];
+        let mut sum_squares = [0u64; 3];
+        for pixel in img.pixels() {
+            for (c, v) in pixel.0.iter().enumerate() {
+                sum[c] += *v as u64;
+                sum_squares[c] += *v as u64 * *v as u64;
+            }
+        }
+        let count = std::cmp::max(img.width() as u64 * img.height() as u64, 1) as f64;
+        let mean = sum.map(|s| s as f64 / count);
+        let stddev = [0, 1, 2].map(|c| {
+            (sum_squares[c] as f64 / count - mean[c] * mean[c])
+                .max(0.0)
+                .sqrt()
+        });
+        ColorStatistics { mean, stddev }
+    }
+}
+
+/// Describe how an output image was produced, as compact `key=value` lines.
+///
+/// # Arguments
+///
+/// * `detection` - The corners that were warped and their confidence.
+/// * `options` - The settings used.
+/// * `img` - The output image.
+/// * `source` - The file name of the screenshot, if it is known.
+pub fn describe(
+    detection: &Detection,
+    options: &CropOptions,
+    img: &RgbImage,
+    source: Option<&str>,
+) -> String {
+    let mut text = format!("qdcrop {}\n", env!("CARGO_PKG_VERSION"));
+    if let Some(source) = source {
+        writeln!(text, "source={}", source).unwrap();
+    }
+    let corners: Vec<_> = detection
+        .corners
+        .iter()
+        .map(|(x, y)| format!("{:.1},{:.1}", x, y))
+        .collect();
+    let stats = ColorStatistics::of(img);
+    writeln!(text, "corners={}", corners.join(",")).unwrap();
+    writeln!(text, "confidence={:.3}", detection.confidence).unwrap();
+    writeln!(text, "size={}x{}", img.width(), img.height()).unwrap();
+    match detection.detector {
+        Some(detector) => writeln!(
+            text,
+            "detector={} threshold={:?}",
+            detector, options.threshold_mode
+        )
+        .unwrap(),
+        None => writeln!(text, "detector=manual").unwrap(),
+    }
+    writeln!(
+        text,
+        "scale={:?} filter={:?}",
+        options.scale_mode, options.resize_filter
+    )
+    .unwrap();
+    writeln!(
+        text,
+        "mean={:.1},{:.1},{:.1}",
+        stats.mean[0], stats.mean[1], stats.mean[2]
+    )
+    .unwrap();
+    writeln!(
+        text,
+        "stddev={:.1},{:.1},{:.1}",
+        stats.stddev[0], stats.stddev[1], stats.stddev[2]
+    )
+    .unwrap();
+    text
+}
+
+/// Build an EXIF block naming qdcrop as the software, with `describe` in the maker note.
+///
+/// The fields in `source`, read from the screenshot named `source_name`, are kept.
+pub fn exif(
+    detection: &Detection,
+    options: &CropOptions,
+    img: &RgbImage,
+    source: &Exif,
+    source_name: Option<&str>,
+) -> Exif {
+    let mut exif = source.clone();
+    exif.set_ifd0(
+        tag::SOFTWARE,
+        Value::Ascii(format!("qdcrop {}", env!("CARGO_PKG_VERSION"))),
+    );
+    exif.set_exif(
+        tag::MAKER_NOTE,
+        Value::Undefined(describe(detection, options, img, source_name).into_bytes()),
+    );
+    exif
+}
@@ -0,0 +1,106 @@
+use std::{
+    io,
+    sync::{Condvar, Mutex},
+};
+
+use anyhow::{anyhow, Context};
+use qdcrop::pool;
+
+/// The memory used while cropping a screenshot for each of its pixels: the decoded image before
+/// it is converted to RGB, the grayscale copy, the threshold mask, and the photos warped from it.
+const BYTES_PER_PIXEL: u64 = 12;
+
+/// Parse an amount of memory such as `4G`, `512M`, or `512MB`, in powers of 1024. A number
+/// without a unit is in bytes.
+pub fn parse_size(s: &str) -> anyhow::Result<u64> {
+    let s = s.trim();
+    let digits = s
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(s.len());
+    let (number, unit) = s.split_at(digits);
+    let number: f64 = number
+        .parse()
+        .with_context(|| format!("Invalid amount of memory: {}", s))?;
+    let unit = unit.trim().to_ascii_uppercase();
+    let shift = match unit.strip_suffix('B').unwrap_or(&unit) {
+        "" => 0,
+        "K" => 10,
+        "M" => 20,
+        "G" => 30,
+        "T" => 40,
+        _ => return Err(anyhow!("Unknown unit of memory: {}", unit)),
+    };
+    Ok((number * (1u64 << shift) as f64) as u64)
+}
+
+/// Estimate how much memory cropping a screenshot takes from the contents of its file, without
+/// decoding it.
+///
+/// Files whose size cannot be read from their header are counted by their length alone.
+pub fn footprint(data: &[u8]) -> u64 {
+    let dimensions = image::io::Reader::new(io::Cursor::new(data))
+        .with_guessed_format()
+        .ok()
+        .and_then(|reader| reader.into_dimensions().ok());
+    let pixels = dimensions.map_or(0, |(width, height)| width as u64 * height as u64);
+    data.len() as u64 + pixels * BYTES_PER_PIXEL
+}
+
+/// A limit on the memory of the screenshots that are cropped at the same time.
+pub struct Budget {
+    /// The most memory to use, in bytes.
+    limit: u64,
+    /// The memory used by the screenshots that are being cropped.
+    used: Mutex<u64>,
+    /// Notified when a screenshot is done and its memory is free.
+    freed: Condvar,
+}
+
+impl Budget {
+    /// A budget of `limit` bytes.
+    pub fn new(limit: u64) -> Self {
+        Budget {
+            limit,
+            used: Mutex::new(0),
+            freed: Condvar::new(),
+        }
+    }
+
+    /// Wait until `bytes` fit in the budget and reserve them until the permit is dropped.
+    ///
+    /// A screenshot larger than the whole budget is cropped once nothing else is, rather than
+    /// never.
+    ///
+    /// This must not be called on the rayon pool. A thread that holds a permit can run other
+    /// tasks while it waits inside rayon, and if one of them waited here for that permit,
+    /// nothing would ever free it.
+    pub fn acquire(&self, bytes: u64) -> Permit<'_> {
+        let mut used = self.used.lock().unwrap();
+        while *used > 0 && *used + bytes > self.limit {
+            used = self.freed.wait(used).unwrap();
+        }
+        *used += bytes;
+        // Buffers kept for reuse count against the budget too, so they are dropped to make
+        // room.
+        pool::trim(self.limit.saturating_sub(*used) as usize);
+        Permit {
+            budget: self,
+            bytes,
+        }
+    }
+}
+
+/// Memory reserved in a `Budget`.
+pub struct Permit<'a> {
+    /// The budget it was reserved in.
+    budget: &'a Budget,
+    /// How much was reserved.
+    bytes: u64,
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        *self.budget.used.lock().unwrap() -= self.bytes;
+        self.budget.freed.notify_all();
+    }
+}
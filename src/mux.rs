@@ -0,0 +1,116 @@
+use anyhow::bail;
+
+/// Metadata to add to a WebP file.
+#[derive(Clone, Debug, Default)]
+pub struct Metadata {
+    /// An EXIF block, as little endian TIFF.
+    pub exif: Option<Vec<u8>>,
+    /// An ICC color profile.
+    pub icc: Option<Vec<u8>>,
+    /// An XMP packet.
+    pub xmp: Option<Vec<u8>>,
+}
+
+impl Metadata {
+    fn is_empty(&self) -> bool {
+        self.exif.is_none() && self.icc.is_none() && self.xmp.is_none()
+    }
+}
+
+/// Append a RIFF chunk to `out`, padded to an even length.
+fn write_chunk(out: &mut Vec<u8>, fourcc: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(fourcc);
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    out.extend_from_slice(data);
+    if data.len() % 2 == 1 {
+        out.push(0);
+    }
+}
+
+/// Split a WebP file into its chunks.
+fn chunks(webp: &[u8]) -> anyhow::Result<Vec<([u8; 4], &[u8])>> {
+    if webp.len() < 12 || &webp[0..4] != b"RIFF" || &webp[8..12] != b"WEBP" {
+        bail!("Not a WebP file");
+    }
+    let mut chunks = Vec::new();
+    let mut rest = &webp[12..];
+    while rest.len() >= 8 {
+        let fourcc = [rest[0], rest[1], rest[2], rest[3]];
+        let size = u32::from_le_bytes([rest[4], rest[5], rest[6], rest[7]]) as usize;
+        if rest.len() < 8 + size {
+            bail!("Truncated WebP chunk");
+        }
+        chunks.push((fourcc, &rest[8..8 + size]));
+        rest = &rest[std::cmp::min(8 + size + size % 2, rest.len())..];
+    }
+    Ok(chunks)
+}
+
+/// Add metadata to an encoded WebP image.
+///
+/// Metadata requires the extended file format, so a simple file is converted by adding a `VP8X`
+/// chunk that describes the canvas and which metadata is present.
+///
+/// # Arguments
+///
+/// * `webp` - The encoded image, as produced by the encoder.
+/// * `size` - The width and height of the image.
+/// * `metadata` - The metadata to add.
+///
+/// # Errors
+///
+/// An error is returned if `webp` is not a WebP file.
+pub fn mux(webp: &[u8], size: (u32, u32), metadata: &Metadata) -> anyhow::Result<Vec<u8>> {
+    if metadata.is_empty() {
+        return Ok(webp.to_vec());
+    }
+
+    let image: Vec<_> = chunks(webp)?
+        .into_iter()
+        .filter(|(fourcc, _)| matches!(fourcc, b"VP8 " | b"VP8L" | b"ALPH"))
+        .collect();
+    if image.is_empty() {
+        bail!("WebP file has no image data");
+    }
+
+    const XMP_FLAG: u8 = 0x04;
+    const EXIF_FLAG: u8 = 0x08;
+    const ALPHA_FLAG: u8 = 0x10;
+    const ICC_FLAG: u8 = 0x20;
+    let mut flags = 0;
+    if metadata.icc.is_some() {
+        flags |= ICC_FLAG;
+    }
+    if metadata.exif.is_some() {
+        flags |= EXIF_FLAG;
+    }
+    if metadata.xmp.is_some() {
+        flags |= XMP_FLAG;
+    }
+    if image.iter().any(|(fourcc, _)| fourcc == b"ALPH") {
+        flags |= ALPHA_FLAG;
+    }
+    let mut vp8x = vec![flags, 0, 0, 0];
+    vp8x.extend_from_slice(&(size.0 - 1).to_le_bytes()[..3]);
+    vp8x.extend_from_slice(&(size.1 - 1).to_le_bytes()[..3]);
+
+    let mut body = b"WEBP".to_vec();
+    write_chunk(&mut body, b"VP8X", &vp8x);
+    if let Some(icc) = &metadata.icc {
+        write_chunk(&mut body, b"ICCP", icc);
+    }
+    for (fourcc, data) in &image {
+        write_chunk(&mut body, fourcc, data);
+    }
+    if let Some(exif) = &metadata.exif {
+        write_chunk(&mut body, b"EXIF", exif);
+    }
+    if let Some(xmp) = &metadata.xmp {
+        write_chunk(&mut body, b"XMP ", xmp);
+    }
+
+    let mut out = b"RIFF".to_vec();
+    out.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    out.extend_from_slice(&body);
+    Ok(out)
+}
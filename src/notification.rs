@@ -0,0 +1,62 @@
+use std::path::Path;
+
+use notify_rust::Notification;
+
+/// How many screenshots a run cropped and how many failed.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Summary {
+    /// Screenshots that were cropped.
+    pub cropped: usize,
+    /// Screenshots that were skipped because their outputs already existed.
+    pub skipped: usize,
+    /// Screenshots that could not be cropped.
+    pub failed: usize,
+}
+
+impl Summary {
+    /// The title of the notification, such as `Cropped 12 screenshots, 1 failed`.
+    fn title(&self) -> String {
+        let plural = |n: usize| if n == 1 { "" } else { "s" };
+        let mut title = format!(
+            "Cropped {} screenshot{}",
+            self.cropped,
+            plural(self.cropped)
+        );
+        if self.skipped > 0 {
+            title += &format!(", skipped {}", self.skipped);
+        }
+        if self.failed > 0 {
+            title += &format!(", {} failed", self.failed);
+        }
+        title
+    }
+}
+
+/// Show a desktop notification summarizing a run.
+///
+/// # Arguments
+///
+/// * `summary` - What the run did.
+/// * `output_dir` - Where the photos were saved, if they were all saved in one folder.
+///
+/// A notification that cannot be shown is only reported, since the photos are already saved.
+pub fn send(summary: &Summary, output_dir: Option<&Path>) {
+    let mut body = match output_dir {
+        Some(dir) => format!("Saved in {}", dir.to_string_lossy()),
+        None => String::new(),
+    };
+    if summary.failed > 0 {
+        if !body.is_empty() {
+            body.push('\n');
+        }
+        body += "See the qdcrop window for the errors.";
+    }
+    let result = Notification::new()
+        .appname("qdcrop")
+        .summary(&summary.title())
+        .body(&body)
+        .show();
+    if let Err(error) = result {
+        eprintln!("Could not show a notification: {}", error);
+    }
+}
@@ -0,0 +1,109 @@
+use image::RgbImage;
+
+/// The lowest orientation confidence reported by Tesseract that is acted on.
+#[cfg(feature = "ocr")]
+const MIN_CONFIDENCE: f32 = 5.0;
+
+/// A Tesseract instance loaded with the orientation and script detection model.
+#[cfg(feature = "ocr")]
+struct Tesseract(*mut tesseract_sys::TessBaseAPI);
+
+#[cfg(feature = "ocr")]
+impl Tesseract {
+    fn new() -> anyhow::Result<Self> {
+        use std::ffi::CString;
+
+        let language = CString::new("osd").unwrap();
+        // SAFETY: The handle is deleted if initialization fails, and otherwise owned by the result.
+        unsafe {
+            let handle = tesseract_sys::TessBaseAPICreate();
+            if tesseract_sys::TessBaseAPIInit3(handle, std::ptr::null(), language.as_ptr()) != 0 {
+                tesseract_sys::TessBaseAPIDelete(handle);
+                anyhow::bail!(
+                    "Could not load Tesseract's osd data. Install it or set TESSDATA_PREFIX."
+                );
+            }
+            Ok(Tesseract(handle))
+        }
+    }
+}
+
+#[cfg(feature = "ocr")]
+impl Drop for Tesseract {
+    fn drop(&mut self) {
+        // SAFETY: The handle was created by `new` and is not used again.
+        unsafe {
+            tesseract_sys::TessBaseAPIEnd(self.0);
+            tesseract_sys::TessBaseAPIDelete(self.0);
+        }
+    }
+}
+
+#[cfg(feature = "ocr")]
+thread_local! {
+    /// Loading the model is slow, so each thread keeps its own instance.
+    static TESSERACT: std::cell::RefCell<Option<Tesseract>> = const { std::cell::RefCell::new(None) };
+}
+
+/// Find how far an image must be turned clockwise for the text in it to be upright.
+///
+/// Returns `None` if there is not enough text to tell, or if the text is already upright.
+///
+/// # Errors
+///
+/// An error is returned if Tesseract cannot be loaded.
+#[cfg(feature = "ocr")]
+pub fn correction(img: &RgbImage) -> anyhow::Result<Option<u32>> {
+    TESSERACT.with(|tesseract| {
+        let mut tesseract = tesseract.borrow_mut();
+        if tesseract.is_none() {
+            *tesseract = Some(Tesseract::new()?);
+        }
+        let handle = tesseract.as_ref().unwrap().0;
+        let (mut degrees, mut confidence, mut script_confidence) = (0, 0.0, 0.0);
+        let mut script = std::ptr::null();
+        // SAFETY: The image outlives the call, and its size and stride match its buffer.
+        let found = unsafe {
+            tesseract_sys::TessBaseAPISetImage(
+                handle,
+                img.as_raw().as_ptr(),
+                img.width() as i32,
+                img.height() as i32,
+                3,
+                img.width() as i32 * 3,
+            );
+            let found = tesseract_sys::TessBaseAPIDetectOrientationScript(
+                handle,
+                &mut degrees,
+                &mut confidence,
+                &mut script,
+                &mut script_confidence,
+            );
+            tesseract_sys::TessBaseAPIClear(handle);
+            found
+        };
+        if found == 0 || confidence < MIN_CONFIDENCE {
+            return Ok(None);
+        }
+        // Tesseract reports how far the text is turned counterclockwise.
+        Ok(match (360 - degrees as u32) % 360 {
+            0 => None,
+            rotation => Some(rotation),
+        })
+    })
+}
+
+#[cfg(not(feature = "ocr"))]
+pub fn correction(_img: &RgbImage) -> anyhow::Result<Option<u32>> {
+    Ok(None)
+}
+
+/// Turn an image clockwise by a multiple of 90 degrees.
+pub fn rotate(img: &RgbImage, degrees: u32) -> RgbImage {
+    match degrees {
+        90 => image::imageops::rotate90(img),
+        180 => image::imageops::rotate180(img),
+        270 => image::imageops::rotate270(img),
+        _ => img.clone(),
+    }
+}
@@ -0,0 +1,114 @@
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use image::GrayImage;
+
+use crate::Quad;
+
+/// The width and height of the image given to the model.
+#[cfg(feature = "ml")]
+const INPUT_SIZE: u32 = 256;
+
+/// A corner detection model.
+///
+/// The model takes a float32 tensor of shape `[1, 1, 256, 256]` holding the screenshot in
+/// grayscale, stretched to a square, with values from 0 to 1. It returns a float32 tensor of 8
+/// values: the corners of the photo, clockwise from the top left, as `x0, y0, x1, y1, ...` in
+/// fractions of the screenshot's width and height.
+pub struct Model {
+    /// The file the model was loaded from.
+    path: PathBuf,
+    #[cfg(feature = "ml")]
+    plan: tract_onnx::prelude::TypedRunnableModel<tract_onnx::prelude::TypedModel>,
+}
+
+impl fmt::Debug for Model {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Model")
+            .field("path", &self.path)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Model {
+    /// Load a model from an ONNX file.
+    ///
+    /// # Errors
+    ///
+    /// An error is returned if the file is not a model with the expected input, or if qdcrop
+    /// was built without the `ml` feature.
+    #[cfg(feature = "ml")]
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        use anyhow::Context;
+        use tract_onnx::prelude::*;
+
+        let size = INPUT_SIZE as usize;
+        let plan = tract_onnx::onnx()
+            .model_for_path(path)
+            .with_context(|| format!("Could not load model {}", path.to_string_lossy()))?
+            .with_input_fact(0, f32::fact([1, 1, size, size]).into())?
+            .into_optimized()?
+            .into_runnable()?;
+        Ok(Model {
+            path: path.to_owned(),
+            plan,
+        })
+    }
+
+    #[cfg(not(feature = "ml"))]
+    pub fn load(_path: &Path) -> anyhow::Result<Self> {
+        let feature = &crate::features::ML;
+        Err(anyhow::anyhow!(
+            "--model needs {}, which is not in this build of qdcrop. Rebuild with `--features {}`, or use another --detector.",
+            feature.description,
+            feature.name
+        ))
+    }
+
+    /// The file the model was loaded from.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Find the corners of the photo in a screenshot.
+    ///
+    /// # Errors
+    ///
+    /// An error is returned if the model fails or does not return 8 values.
+    #[cfg(feature = "ml")]
+    pub fn detect(&self, luma: &GrayImage) -> anyhow::Result<Quad> {
+        use anyhow::bail;
+        use image::imageops::FilterType;
+        use tract_onnx::prelude::*;
+
+        let (width, height) = luma.dimensions();
+        let small = image::imageops::resize(luma, INPUT_SIZE, INPUT_SIZE, FilterType::Triangle);
+        let size = INPUT_SIZE as usize;
+        let input: Tensor =
+            tract_ndarray::Array4::from_shape_fn((1, 1, size, size), |(_, _, y, x)| {
+                small.get_pixel(x as u32, y as u32).0[0] as f32 / 255.0
+            })
+            .into();
+        let outputs = self.plan.run(tvec!(input.into()))?;
+        let values: Vec<f32> = outputs[0].to_array_view::<f32>()?.iter().copied().collect();
+        if values.len() != 8 {
+            bail!("The model returned {} values instead of 8", values.len());
+        }
+        Ok([0, 1, 2, 3].map(|i| {
+            (
+                values[i * 2] * width as f32,
+                values[i * 2 + 1] * height as f32,
+            )
+        }))
+    }
+
+    #[cfg(not(feature = "ml"))]
+    pub fn detect(&self, _luma: &GrayImage) -> anyhow::Result<Quad> {
+        Err(anyhow::anyhow!(
+            "The model cannot be run without {}",
+            crate::features::ML.description
+        ))
+    }
+}
@@ -0,0 +1,164 @@
+use std::{
+    fs::File,
+    io::{self, Write},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Mutex,
+    },
+    thread,
+};
+
+use anyhow::Context;
+use rayon::iter::{ParallelBridge, ParallelIterator};
+
+/// How many threads read inputs. Reading is mostly waiting on the disk, so a few are enough to
+/// keep the workers busy.
+const READERS: usize = 2;
+/// How many threads write outputs.
+const WRITERS: usize = 2;
+/// How many inputs may be read ahead of the workers, for each worker.
+const READ_AHEAD: usize = 2;
+/// How many outputs may wait to be written, for each worker.
+const WRITE_BEHIND: usize = 2;
+
+/// An output that is being written by a `Writer`.
+pub struct PendingWrite {
+    /// Where the output is written.
+    path: PathBuf,
+    /// Receives the result of the write once it is done.
+    done: mpsc::Receiver<io::Result<()>>,
+}
+
+impl PendingWrite {
+    /// Wait until the output is written.
+    ///
+    /// # Errors
+    ///
+    /// An error is returned if the output could not be written.
+    pub fn wait(self) -> anyhow::Result<()> {
+        self.done
+            .recv()
+            .unwrap_or_else(|_| Err(io::ErrorKind::BrokenPipe.into()))
+            .with_context(|| format!("Could not write {}", self.path.to_string_lossy()))
+    }
+}
+
+/// A file to write and where to send the result.
+struct WriteJob {
+    /// The output, already created.
+    file: File,
+    /// The encoded output.
+    data: Vec<u8>,
+    /// Where the result of the write is sent.
+    done: mpsc::Sender<io::Result<()>>,
+}
+
+/// Hands encoded outputs to the writer threads of `run`, so that workers can go on to the next
+/// screenshot while they are written.
+pub struct Writer {
+    /// The queue of the writer threads.
+    sender: mpsc::SyncSender<WriteJob>,
+}
+
+impl Writer {
+    /// Write `data` to `file`, which was created at `path`, on a writer thread.
+    pub fn write(&self, path: PathBuf, file: File, data: Vec<u8>) -> PendingWrite {
+        let (done, receiver) = mpsc::channel();
+        let job = WriteJob { file, data, done };
+        if let Err(mpsc::SendError(mut job)) = self.sender.send(job) {
+            // The writers are gone, so write it here instead.
+            let result = job.file.write_all(&job.data).and_then(|_| job.file.flush());
+            let _ = job.done.send(result);
+        }
+        PendingWrite {
+            path,
+            done: receiver,
+        }
+    }
+}
+
+/// Run a batch in three stages: `read` on a few reader threads, `process` on the rayon pool, and
+/// the writes that `process` hands to its `Writer` on a few writer threads.
+///
+/// Each stage works on the next jobs while the others are busy, with a bounded number of jobs
+/// between them, so that the disk and the CPU are used at the same time without holding the
+/// whole batch in memory.
+///
+/// `read` may block, for example to wait for memory, since the reader threads are not part of
+/// the rayon pool. `process` should not.
+///
+/// Once a result matches `stop_after`, no more jobs are read or processed, though jobs that
+/// were already being processed are finished.
+///
+/// Returns the results of `process` in the order of `jobs`, without the jobs that were stopped.
+/// Writes may still be pending until their `PendingWrite` is waited on.
+pub fn run<J, P, R>(
+    jobs: Vec<J>,
+    read: impl Fn(J) -> P + Sync,
+    process: impl Fn(P, &Writer) -> R + Sync,
+    stop_after: impl Fn(&R) -> bool + Sync,
+) -> Vec<R>
+where
+    J: Send,
+    P: Send,
+    R: Send,
+{
+    let workers = rayon::current_num_threads();
+    let (read_sender, read_receiver) = mpsc::sync_channel(workers * READ_AHEAD);
+    let (write_sender, write_receiver) = mpsc::sync_channel::<WriteJob>(workers * WRITE_BEHIND);
+    let write_receiver = Mutex::new(write_receiver);
+    let jobs = Mutex::new(jobs.into_iter().enumerate());
+    let stopped = AtomicBool::new(false);
+    let (read, jobs, write_receiver, stopped) = (&read, &jobs, &write_receiver, &stopped);
+
+    thread::scope(|scope| {
+        for _ in 0..READERS {
+            let read_sender = read_sender.clone();
+            scope.spawn(move || loop {
+                if stopped.load(Ordering::Relaxed) {
+                    break;
+                }
+                let Some((i, job)) = jobs.lock().unwrap().next() else {
+                    break;
+                };
+                if read_sender.send((i, read(job))).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(read_sender);
+        for _ in 0..WRITERS {
+            scope.spawn(move || loop {
+                let Ok(mut job) = write_receiver.lock().unwrap().recv() else {
+                    break;
+                };
+                let result = job.file.write_all(&job.data).and_then(|_| job.file.flush());
+                drop(job.file);
+                let _ = job.done.send(result);
+            });
+        }
+
+        let writer = Writer {
+            sender: write_sender,
+        };
+        let mut results: Vec<_> = read_receiver
+            .into_iter()
+            .par_bridge()
+            .filter_map(|(i, prepared)| {
+                if stopped.load(Ordering::Relaxed) {
+                    return None;
+                }
+                let result = process(prepared, &writer);
+                if stop_after(&result) {
+                    stopped.store(true, Ordering::Relaxed);
+                }
+                Some((i, result))
+            })
+            .collect();
+        // Dropping the writer lets the writer threads finish once the queue is empty.
+        drop(writer);
+        results.sort_unstable_by_key(|(i, _)| *i);
+        results.into_iter().map(|(_, result)| result).collect()
+    })
+}
@@ -0,0 +1,59 @@
+/// The keyword of the text chunk that holds an XMP packet.
+pub const XMP_KEYWORD: &str = "XML:com.adobe.xmp";
+
+/// Read the text chunks of a PNG file, as keywords and their text.
+///
+/// `tEXt` chunks and uncompressed `iTXt` chunks are read. Compressed text is left out, and a
+/// file that is not a PNG has no text.
+pub fn read(file: &[u8]) -> Vec<(String, String)> {
+    let mut texts = Vec::new();
+    let Some(mut rest) = file.strip_prefix(b"\x89PNG\r\n\x1a\n") else {
+        return texts;
+    };
+    while rest.len() >= 12 {
+        let length = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+        let Some(data) = rest.get(8..8 + length) else {
+            break;
+        };
+        match &rest[4..8] {
+            b"tEXt" => {
+                if let Some((keyword, text)) = split_nul(data) {
+                    // tEXt is Latin-1.
+                    texts.push((latin1(keyword), latin1(text)));
+                }
+            }
+            b"iTXt" => {
+                // Keyword, compression flag, compression method, language, translated keyword.
+                let text = split_nul(data).and_then(|(keyword, rest)| {
+                    let (&compressed, rest) = rest.split_first()?;
+                    let (_method, rest) = rest.split_first()?;
+                    if compressed != 0 {
+                        return None;
+                    }
+                    let (_, rest) = split_nul(rest)?;
+                    let (_, text) = split_nul(rest)?;
+                    Some((latin1(keyword), String::from_utf8_lossy(text).into_owned()))
+                });
+                texts.extend(text);
+            }
+            b"IEND" => break,
+            _ => {}
+        }
+        rest = match rest.get(12 + length..) {
+            Some(rest) => rest,
+            None => break,
+        };
+    }
+    texts
+}
+
+/// Split bytes at the first NUL.
+fn split_nul(bytes: &[u8]) -> Option<(&[u8], &[u8])> {
+    let nul = bytes.iter().position(|b| *b == 0)?;
+    Some((&bytes[..nul], &bytes[nul + 1..]))
+}
+
+/// Decode Latin-1 text.
+fn latin1(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| *b as char).collect()
+}
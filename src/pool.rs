@@ -0,0 +1,125 @@
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Mutex,
+};
+
+use image::{GrayImage, ImageBuffer, Pixel, RgbImage};
+
+/// The most memory kept in buffers by default, in bytes. The oldest buffers are dropped when
+/// more are given back.
+const MAX_BYTES: usize = 256 << 20;
+
+/// The most memory kept in buffers, in bytes.
+static LIMIT: AtomicUsize = AtomicUsize::new(MAX_BYTES);
+
+/// Buffers that were given back.
+struct Buffers {
+    /// The buffers, oldest first, with the width, height, and number of channels of the image
+    /// they held.
+    buffers: Vec<((u32, u32, u8), Vec<u8>)>,
+    /// The total size of the buffers, in bytes.
+    bytes: usize,
+}
+
+impl Buffers {
+    /// Drop the oldest buffers until no more than `limit` bytes are kept.
+    fn trim(&mut self, limit: usize) {
+        let count = self
+            .buffers
+            .iter()
+            .scan(self.bytes, |bytes, (_, buffer)| {
+                let over = *bytes > limit;
+                *bytes -= buffer.len();
+                Some(over)
+            })
+            .take_while(|&over| over)
+            .count();
+        for (_, buffer) in self.buffers.drain(..count) {
+            self.bytes -= buffer.len();
+        }
+    }
+}
+
+/// The buffers of every thread.
+static BUFFERS: Mutex<Buffers> = Mutex::new(Buffers {
+    buffers: Vec::new(),
+    bytes: 0,
+});
+
+/// Take a buffer for an image, or allocate one if none was given back, cleared to 0.
+fn take(width: u32, height: u32, channels: u8) -> Vec<u8> {
+    let key = (width, height, channels);
+    let reused = {
+        let mut buffers = BUFFERS.lock().unwrap();
+        let position = buffers.buffers.iter().rposition(|(k, _)| *k == key);
+        position.map(|position| {
+            let buffer = buffers.buffers.remove(position).1;
+            buffers.bytes -= buffer.len();
+            buffer
+        })
+    };
+    match reused {
+        Some(mut buffer) => {
+            buffer.fill(0);
+            buffer
+        }
+        None => vec![0; width as usize * height as usize * channels as usize],
+    }
+}
+
+/// Set the most memory kept in buffers, in bytes, and drop buffers over it. The default is
+/// 256 MiB.
+pub fn set_limit(limit: usize) {
+    LIMIT.store(limit, Ordering::Relaxed);
+    BUFFERS.lock().unwrap().trim(limit);
+}
+
+/// The memory kept in buffers, in bytes.
+pub fn bytes() -> usize {
+    BUFFERS.lock().unwrap().bytes
+}
+
+/// Drop the oldest buffers until no more than `limit` bytes are kept, to make room for other
+/// work.
+pub fn trim(limit: usize) {
+    BUFFERS.lock().unwrap().trim(limit);
+}
+
+/// Drop every buffer, such as at the end of a batch.
+pub fn clear() {
+    trim(0);
+}
+
+/// A black grayscale image, in a reused buffer if one of the same size was given back.
+pub fn gray(width: u32, height: u32) -> GrayImage {
+    GrayImage::from_raw(width, height, take(width, height, 1)).unwrap()
+}
+
+/// A black RGB image, in a reused buffer if one of the same size was given back.
+pub fn rgb(width: u32, height: u32) -> RgbImage {
+    RgbImage::from_raw(width, height, take(width, height, 3)).unwrap()
+}
+
+/// A grayscale copy of an image, like `image::imageops::grayscale`, in a reused buffer.
+pub fn grayscale(img: &RgbImage) -> GrayImage {
+    let mut luma = gray(img.width(), img.height());
+    for (l, p) in luma.pixels_mut().zip(img.pixels()) {
+        *l = p.to_luma();
+    }
+    luma
+}
+
+/// Give back the buffer of an image that is no longer needed, so that the next image of the
+/// same size can use it.
+pub fn recycle<P: Pixel<Subpixel = u8>>(img: ImageBuffer<P, Vec<u8>>) {
+    let key = (img.width(), img.height(), P::CHANNEL_COUNT);
+    let buffer = img.into_raw();
+    let limit = LIMIT.load(Ordering::Relaxed);
+    if buffer.len() > limit {
+        return;
+    }
+    let mut buffers = BUFFERS.lock().unwrap();
+    buffers.bytes += buffer.len();
+    buffers.buffers.push((key, buffer));
+    buffers.trim(limit);
+}
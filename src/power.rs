@@ -0,0 +1,145 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Condvar, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+/// Check whether the computer is running on battery power.
+///
+/// Returns `false` if it is plugged in, has no battery, or the power status cannot be read.
+#[cfg(windows)]
+pub fn on_battery() -> bool {
+    #[repr(C)]
+    #[derive(Default)]
+    struct SystemPowerStatus {
+        ac_line_status: u8,
+        battery_flag: u8,
+        battery_life_percent: u8,
+        system_status_flag: u8,
+        battery_life_time: u32,
+        battery_full_life_time: u32,
+    }
+    extern "system" {
+        fn GetSystemPowerStatus(status: *mut SystemPowerStatus) -> i32;
+    }
+
+    let mut status = SystemPowerStatus::default();
+    // SAFETY: The status is a valid SYSTEM_POWER_STATUS to write into.
+    let ok = unsafe { GetSystemPowerStatus(&mut status) };
+    // 0 means offline, 1 online, and 255 unknown.
+    ok != 0 && status.ac_line_status == 0
+}
+
+#[cfg(target_os = "macos")]
+pub fn on_battery() -> bool {
+    std::process::Command::new("pmset")
+        .args(["-g", "batt"])
+        .output()
+        .map(|output| String::from_utf8_lossy(&output.stdout).contains("'Battery Power'"))
+        .unwrap_or(false)
+}
+
+#[cfg(not(any(windows, target_os = "macos")))]
+pub fn on_battery() -> bool {
+    let Ok(supplies) = std::fs::read_dir("/sys/class/power_supply") else {
+        return false;
+    };
+    let read = |path: std::path::PathBuf| {
+        std::fs::read_to_string(path)
+            .map(|s| s.trim().to_owned())
+            .unwrap_or_default()
+    };
+    let mut discharging = false;
+    for supply in supplies.flatten() {
+        let path = supply.path();
+        match read(path.join("type")).as_str() {
+            "Mains" if read(path.join("online")) == "1" => return false,
+            "Battery" if read(path.join("status")) == "Discharging" => discharging = true,
+            _ => {}
+        }
+    }
+    discharging
+}
+
+/// How long `Saver` trusts a power status before it checks again, since checking can take a
+/// process of its own.
+const CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Saves power while the computer runs on battery, checking the power status as it goes so that
+/// a long run notices when it is unplugged or plugged in.
+pub struct Saver {
+    /// When the power status was last checked, and whether it was on battery.
+    status: Mutex<Option<(Instant, bool)>>,
+    /// How many screenshots may be cropped at the same time while on battery.
+    limit: usize,
+    /// How many screenshots are being cropped while on battery.
+    running: Mutex<usize>,
+    /// Notified when one of them is done.
+    done: Condvar,
+    /// Whether the reduced limit was reported.
+    announced: AtomicBool,
+}
+
+impl Saver {
+    /// A saver that crops half as many screenshots at a time as there are `threads`.
+    pub fn new(threads: usize) -> Self {
+        Saver {
+            status: Mutex::new(None),
+            limit: std::cmp::max(threads / 2, 1),
+            running: Mutex::new(0),
+            done: Condvar::new(),
+            announced: AtomicBool::new(false),
+        }
+    }
+
+    /// Whether the computer is running on battery, as of at most `CHECK_INTERVAL` ago.
+    pub fn on_battery(&self) -> bool {
+        let mut status = self.status.lock().unwrap();
+        match *status {
+            Some((checked, on_battery)) if checked.elapsed() < CHECK_INTERVAL => on_battery,
+            _ => {
+                let on_battery = on_battery();
+                *status = Some((Instant::now(), on_battery));
+                on_battery
+            }
+        }
+    }
+
+    /// If the computer is running on battery, wait until fewer than half the threads are
+    /// cropping and hold a slot until the returned `Slot` is dropped. Returns `None` when it is
+    /// plugged in.
+    ///
+    /// Like `memory::Budget::acquire`, this must not be called on the rayon pool.
+    pub fn throttle(&self) -> Option<Slot<'_>> {
+        if !self.on_battery() {
+            return None;
+        }
+        if !self.announced.swap(true, Ordering::Relaxed) {
+            eprintln!(
+                "Running on battery: cropping {} screenshots at a time",
+                self.limit
+            );
+        }
+        let mut running = self.running.lock().unwrap();
+        while *running >= self.limit {
+            running = self.done.wait(running).unwrap();
+        }
+        *running += 1;
+        Some(Slot { saver: self })
+    }
+}
+
+/// A screenshot being cropped on battery, counted by a `Saver`.
+pub struct Slot<'a> {
+    /// The saver that counts it.
+    saver: &'a Saver,
+}
+
+impl Drop for Slot<'_> {
+    fn drop(&mut self) {
+        *self.saver.running.lock().unwrap() -= 1;
+        self.saver.done.notify_one();
+    }
+}
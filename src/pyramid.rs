@@ -0,0 +1,80 @@
+use std::{fs, path::Path};
+
+use anyhow::Context;
+use image::{imageops::FilterType, RgbImage};
+use webp::Encoder;
+
+/// The size of each tile, not including overlap.
+const TILE_SIZE: u32 = 254;
+/// How many pixels each tile shares with its neighbors.
+const OVERLAP: u32 = 1;
+/// The WebP quality used for tiles.
+const QUALITY: f32 = 90.0;
+
+/// Write one level of the pyramid as tiles.
+fn write_level(dir: &Path, img: &RgbImage) -> anyhow::Result<()> {
+    fs::create_dir_all(dir).context("Could not create tile directory")?;
+    let (width, height) = img.dimensions();
+    let columns = width.div_ceil(TILE_SIZE);
+    let rows = height.div_ceil(TILE_SIZE);
+    for column in 0..columns {
+        for row in 0..rows {
+            let x = (column * TILE_SIZE).saturating_sub(OVERLAP);
+            let y = (row * TILE_SIZE).saturating_sub(OVERLAP);
+            let right = std::cmp::min((column + 1) * TILE_SIZE + OVERLAP, width);
+            let bottom = std::cmp::min((row + 1) * TILE_SIZE + OVERLAP, height);
+            let tile = image::imageops::crop_imm(img, x, y, right - x, bottom - y).to_image();
+            let encoded =
+                Encoder::from_rgb(tile.as_raw(), tile.width(), tile.height()).encode(QUALITY);
+            fs::write(dir.join(format!("{}_{}.webp", column, row)), &*encoded)
+                .context("Could not write tile")?;
+        }
+    }
+    Ok(())
+}
+
+/// Write a Deep Zoom (DZI) tile pyramid of an image, for web deep zoom viewers.
+///
+/// The pyramid is described by `<output>.dzi`, and its tiles are saved in `<output>_files`.
+/// Level 0 is a single pixel, and each level doubles the size of the previous one until the
+/// full size of the image is reached.
+///
+/// # Arguments
+///
+/// * `output` - The path of the output image. Its extension is replaced.
+/// * `img` - The image to tile.
+///
+/// # Errors
+///
+/// An error is returned if a file cannot be written.
+pub fn write_dzi(output: &Path, img: &RgbImage) -> anyhow::Result<()> {
+    let (width, height) = img.dimensions();
+    let stem = output.file_stem().unwrap_or_default().to_string_lossy();
+    let files = output.with_file_name(format!("{}_files", stem));
+
+    let max_level = 32 - (std::cmp::max(width, height).max(1) - 1).leading_zeros();
+    let mut level_img = img.clone();
+    for level in (0..=max_level).rev() {
+        write_level(&files.join(level.to_string()), &level_img)?;
+        if level > 0 {
+            let (w, h) = level_img.dimensions();
+            level_img = image::imageops::resize(
+                &level_img,
+                w.div_ceil(2),
+                h.div_ceil(2),
+                FilterType::Triangle,
+            );
+        }
+    }
+
+    let dzi = format!(
+        concat!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+            "<Image xmlns=\"http://schemas.microsoft.com/deepzoom/2008\" Format=\"webp\" Overlap=\"{}\" TileSize=\"{}\">\n",
+            "  <Size Width=\"{}\" Height=\"{}\"/>\n",
+            "</Image>\n"
+        ),
+        OVERLAP, TILE_SIZE, width, height
+    );
+    fs::write(output.with_extension("dzi"), dzi).context("Could not write DZI descriptor")
+}
@@ -0,0 +1,104 @@
+use image::RgbImage;
+use pyo3::{create_exception, exceptions::PyException, prelude::*, types::PyBytes};
+
+use crate::{
+    crop_photo, detect_scaled, encode, Aspect, AspectMode, CropOptions, Detection, MaxSize, Quad,
+};
+
+create_exception!(
+    qdcrop,
+    QdcropError,
+    PyException,
+    "Cropping a screenshot failed."
+);
+
+/// Turn an error from qdcrop into a Python exception.
+fn error(error: impl ToString) -> PyErr {
+    QdcropError::new_err(error.to_string())
+}
+
+/// Decode a screenshot from the bytes of its file.
+fn decode(image: &[u8]) -> PyResult<RgbImage> {
+    let img = image::load_from_memory(image).map_err(|e| error(crate::Error::DecodeError(e)))?;
+    Ok(img.into_rgb8())
+}
+
+/// Find the corners of the most confident photo in a screenshot.
+fn find(img: &RgbImage, options: &CropOptions) -> PyResult<Detection> {
+    Ok(detect_scaled(img, options).map_err(error)?[0])
+}
+
+/// Find the corners of the photo in a screenshot, given as the bytes of a PNG or JPEG file.
+///
+/// Returns four (x, y) pairs, clockwise from the top left, in pixels of the screenshot.
+#[pyfunction]
+fn detect(image: &[u8]) -> PyResult<Vec<(f32, f32)>> {
+    let img = decode(image)?;
+    let options = CropOptions::default();
+    Ok(find(&img, &options)?.corners.to_vec())
+}
+
+/// Crop the photo out of a screenshot, given as the bytes of a PNG or JPEG file, and return it
+/// as the bytes of a WebP file.
+///
+/// The photo is cropped at `corners`, four (x, y) pairs as returned by `detect`, or else at the
+/// corners found in the screenshot. `aspect` is the proportions of the photo, like "4:3" or
+/// "auto". Outputs are shrunk to fit `max_width` and `max_height`, which default to 1024 pixels
+/// tall at `aspect`.
+#[pyfunction]
+#[pyo3(signature = (image, corners=None, quality=95.0, aspect=None, max_width=None, max_height=None))]
+fn crop<'py>(
+    py: Python<'py>,
+    image: &[u8],
+    corners: Option<Vec<(f32, f32)>>,
+    quality: f32,
+    aspect: Option<&str>,
+    max_width: Option<u32>,
+    max_height: Option<u32>,
+) -> PyResult<&'py PyBytes> {
+    let aspect: AspectMode = aspect
+        .map_or(Ok(AspectMode::default()), str::parse)
+        .map_err(error)?;
+    let limit_aspect = match aspect {
+        AspectMode::Fixed(aspect) => aspect,
+        AspectMode::Auto => Aspect::default(),
+    };
+    let mut options = CropOptions::builder()
+        .quality(quality)
+        .aspect(aspect)
+        .max_size(MaxSize::from_limits(max_width, max_height, limit_aspect))
+        .build()
+        .map_err(error)?;
+
+    let img = decode(image)?;
+    let detection = match corners {
+        Some(corners) => {
+            let corners: Quad = corners
+                .try_into()
+                .map_err(|_| error("Expected 4 corners"))?;
+            // Given corners are always warped, even at the edges of the screenshot.
+            options.corners = vec![corners];
+            Detection {
+                corners,
+                confidence: 1.0,
+                detector: None,
+            }
+        }
+        None => find(&img, &options)?,
+    };
+    // Warping and encoding do not touch Python objects, so other Python threads can run.
+    let encoded = py.allow_threads(|| {
+        let photo = crop_photo(&img, &detection, &options)?;
+        encode(&photo.image, &options)
+    });
+    Ok(PyBytes::new(py, &encoded.map_err(error)?))
+}
+
+/// Python bindings, built with maturin.
+#[pymodule]
+fn qdcrop(py: Python, m: &PyModule) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(detect, m)?)?;
+    m.add_function(wrap_pyfunction!(crop, m)?)?;
+    m.add("QdcropError", py.get_type::<QdcropError>())?;
+    Ok(())
+}
@@ -0,0 +1,210 @@
+use std::str::FromStr;
+
+use anyhow::anyhow;
+use image::{imageops::FilterType, RgbImage};
+
+/// The filter used to shrink an image after it has been warped.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ResizeFilter {
+    Triangle,
+    CatmullRom,
+    #[default]
+    Lanczos3,
+}
+
+impl FromStr for ResizeFilter {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "triangle" => Ok(ResizeFilter::Triangle),
+            "catmull-rom" => Ok(ResizeFilter::CatmullRom),
+            "lanczos3" => Ok(ResizeFilter::Lanczos3),
+            _ => Err(anyhow!("Unknown resize filter {}", s)),
+        }
+    }
+}
+
+impl ResizeFilter {
+    /// The names accepted by `from_str`.
+    pub const NAMES: &'static [&'static str] = &["triangle", "catmull-rom", "lanczos3"];
+
+    /// Every filter, in the same order as `NAMES`.
+    pub const ALL: &'static [ResizeFilter] = &[
+        ResizeFilter::Triangle,
+        ResizeFilter::CatmullRom,
+        ResizeFilter::Lanczos3,
+    ];
+
+    /// The name of the filter as shown to users.
+    pub fn name(self) -> &'static str {
+        match self {
+            ResizeFilter::Triangle => "triangle",
+            ResizeFilter::CatmullRom => "catmull-rom",
+            ResizeFilter::Lanczos3 => "lanczos3",
+        }
+    }
+
+    /// Resize an image to exactly `size`.
+    pub fn resize(self, img: &RgbImage, size: (u32, u32)) -> RgbImage {
+        let filter = match self {
+            ResizeFilter::Triangle => FilterType::Triangle,
+            ResizeFilter::CatmullRom => FilterType::CatmullRom,
+            ResizeFilter::Lanczos3 => FilterType::Lanczos3,
+        };
+        image::imageops::resize(img, size.0, size.1, filter)
+    }
+}
+
+/// How the warp reaches the output size.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ScaleMode {
+    /// Warp directly to the output size.
+    Fused,
+    /// Warp to the size of the photo in the screenshot, then resize to the output size.
+    TwoPass,
+    /// Warp to 2 to 4 times the output size, then resize to the output size.
+    ///
+    /// This keeps most of the benefit of `TwoPass` while bounding the size of the intermediate
+    /// image, which matters for very large screenshots.
+    Supersample,
+    /// Use `TwoPass` when the output is much smaller than the photo, and `Fused` otherwise.
+    ///
+    /// A fused bicubic warp only looks at a few source pixels for each output pixel, so it
+    /// aliases when it shrinks a lot.
+    #[default]
+    Auto,
+}
+
+impl FromStr for ScaleMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fused" => Ok(ScaleMode::Fused),
+            "two-pass" => Ok(ScaleMode::TwoPass),
+            "supersample" => Ok(ScaleMode::Supersample),
+            "auto" => Ok(ScaleMode::Auto),
+            _ => Err(anyhow!("Unknown scale mode {}", s)),
+        }
+    }
+}
+
+impl ScaleMode {
+    /// The names accepted by `from_str`.
+    pub const NAMES: &'static [&'static str] = &["fused", "two-pass", "supersample", "auto"];
+
+    /// The smallest shrink factor that `Auto` resizes separately.
+    const AUTO_THRESHOLD: f64 = 1.5;
+
+    /// The smallest and largest multiples of the output size that `Supersample` warps to.
+    const SUPERSAMPLE_FACTORS: (u32, u32) = (2, 4);
+
+    /// Choose the size to warp to before resizing to the output size.
+    ///
+    /// Returns `None` if the warp should produce the output size directly.
+    ///
+    /// # Arguments
+    ///
+    /// * `natural` - The size of the photo in the screenshot.
+    /// * `size` - The size of the output image.
+    pub fn intermediate_size(self, natural: (u32, u32), size: (u32, u32)) -> Option<(u32, u32)> {
+        if natural.0 <= size.0 || natural.1 <= size.1 {
+            return None;
+        }
+        let factor = f64::min(
+            natural.0 as f64 / size.0 as f64,
+            natural.1 as f64 / size.1 as f64,
+        );
+        match self {
+            ScaleMode::Fused => None,
+            ScaleMode::TwoPass => Some(natural),
+            ScaleMode::Supersample => {
+                let (min, max) = Self::SUPERSAMPLE_FACTORS;
+                // Never warp to more than the photo's own resolution.
+                let factor = (factor.ceil() as u32).clamp(min, max);
+                Some((
+                    std::cmp::min(size.0 * factor, natural.0),
+                    std::cmp::min(size.1 * factor, natural.1),
+                ))
+            }
+            ScaleMode::Auto => (factor >= Self::AUTO_THRESHOLD).then_some(natural),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{Rgb, RgbImage};
+
+    use super::{ResizeFilter, ScaleMode};
+    use crate::{output_size, warp, Aspect, CropOptions, MaxSize, Quad};
+
+    /// The lowest peak signal to noise ratio, in decibels, between the fused warp and the paths
+    /// that resize afterward. Smooth content looks the same either way, so a lower value means
+    /// that a path is broken rather than that it filters differently.
+    const MIN_PSNR: f64 = 28.0;
+
+    /// A screenshot with smooth detail, and the corners of a photo in it that is shrunk about
+    /// three times to fit the output.
+    fn synthetic() -> (RgbImage, Quad) {
+        let img = RgbImage::from_fn(1280, 720, |x, y| {
+            let wave = 128.0 + 100.0 * (x as f64 / 40.0).sin() * (y as f64 / 55.0).cos();
+            Rgb([wave as u8, (x * 255 / 1279) as u8, (y * 255 / 719) as u8])
+        });
+        let corners = [
+            (150.0, 90.0),
+            (1140.0, 60.0),
+            (1180.0, 670.0),
+            (110.0, 640.0),
+        ];
+        (img, corners)
+    }
+
+    /// The peak signal to noise ratio between two images of the same size, in decibels.
+    fn psnr(a: &RgbImage, b: &RgbImage) -> f64 {
+        assert_eq!(a.dimensions(), b.dimensions());
+        let squared: f64 = a
+            .as_raw()
+            .iter()
+            .zip(b.as_raw())
+            .map(|(&a, &b)| (a as f64 - b as f64).powi(2))
+            .sum();
+        let mse = squared / a.as_raw().len() as f64;
+        10.0 * (255.0 * 255.0 / mse.max(f64::EPSILON)).log10()
+    }
+
+    #[test]
+    fn resized_paths_match_fused_warp() {
+        let (img, corners) = synthetic();
+        let max_size = MaxSize {
+            width: Some(320.0),
+            height: Some(180.0),
+        };
+        let size = output_size(&corners, Aspect::default(), max_size);
+        let fused = CropOptions {
+            scale_mode: ScaleMode::Fused,
+            ..CropOptions::default()
+        };
+        let expected = warp(&img, corners, size, &fused).unwrap();
+
+        for mode in [ScaleMode::TwoPass, ScaleMode::Supersample] {
+            for &filter in ResizeFilter::ALL {
+                let options = CropOptions {
+                    scale_mode: mode,
+                    resize_filter: filter,
+                    ..CropOptions::default()
+                };
+                let actual = warp(&img, corners, size, &options).unwrap();
+                let psnr = psnr(&expected, &actual);
+                assert!(
+                    psnr >= MIN_PSNR,
+                    "{:?} with {} is {:.1} dB from the fused warp",
+                    mode,
+                    filter.name(),
+                    psnr
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,206 @@
+use std::{fs, path::Path};
+
+use anyhow::Context;
+use image::{Rgb, RgbImage};
+use qdcrop::{from_control_points, output_size, CropOptions, Quad};
+
+use crate::{crop, selftest};
+
+/// The size of the synthetic screenshots.
+const SIZE: (u32, u32) = (960, 540);
+/// The width of the black frame around each photo, as a fraction of the photo.
+const FRAME: f32 = 0.05;
+/// How far a detected corner may be from where the photo was drawn, in pixels.
+const CORNER_TOLERANCE: f32 = 4.0;
+/// The largest acceptable mean difference per channel between an output and the photo.
+const CONTENT_TOLERANCE: f64 = 8.0;
+
+/// A synthetic screenshot in the corpus.
+struct Case {
+    name: &'static str,
+    /// Where the photo is drawn, clockwise from the top left.
+    corners: Quad,
+}
+
+/// Photos framed the ways they are usually photographed in game.
+const CORPUS: &[Case] = &[
+    Case {
+        name: "straight",
+        corners: [(180.0, 90.0), (780.0, 90.0), (780.0, 450.0), (180.0, 450.0)],
+    },
+    Case {
+        name: "tilted",
+        corners: [
+            (200.0, 80.0),
+            (770.0, 110.0),
+            (750.0, 470.0),
+            (170.0, 440.0),
+        ],
+    },
+    Case {
+        name: "perspective",
+        corners: [
+            (240.0, 60.0),
+            (720.0, 100.0),
+            (760.0, 480.0),
+            (200.0, 460.0),
+        ],
+    },
+    Case {
+        name: "small",
+        corners: [
+            (340.0, 180.0),
+            (620.0, 190.0),
+            (615.0, 350.0),
+            (345.0, 345.0),
+        ],
+    },
+];
+
+/// The color of the photo at a point, where `u` and `v` go from 0 to 1 across it.
+fn photo(u: f32, v: f32) -> Rgb<u8> {
+    if !(FRAME..1.0 - FRAME).contains(&u) || !(FRAME..1.0 - FRAME).contains(&v) {
+        return Rgb([0, 0, 0]);
+    }
+    Rgb([
+        (64.0 + 160.0 * u) as u8,
+        (64.0 + 160.0 * v) as u8,
+        (200.0 - 120.0 * u) as u8,
+    ])
+}
+
+/// Draw a screenshot with the photo at `corners` on a plain background.
+fn render(corners: Quad) -> anyhow::Result<RgbImage> {
+    let projection = from_control_points(corners, (1, 1))?;
+    Ok(RgbImage::from_fn(SIZE.0, SIZE.1, |x, y| {
+        let (u, v) = projection * (x as f32 + 0.5, y as f32 + 0.5);
+        if (0.0..1.0).contains(&u) && (0.0..1.0).contains(&v) {
+            photo(u, v)
+        } else {
+            Rgb([200, 200, 200])
+        }
+    }))
+}
+
+/// A 64-bit FNV-1a hash of a file's bytes.
+fn hash(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Crop one synthetic screenshot twice and check the results.
+///
+/// Returns a description of what went wrong, if anything.
+fn check(case: &Case, dir: &Path, options: &CropOptions) -> anyhow::Result<Result<String, String>> {
+    let input = dir.join(format!("{}.png", case.name));
+    render(case.corners)?
+        .save(&input)
+        .context("Could not write test picture")?;
+
+    let mut hashes = Vec::new();
+    let mut cropped = None;
+    for run in 1..=2 {
+        let output = dir.join(format!("{}-{}.webp", case.name, run));
+        let result = match crop(&input, &output, options) {
+            Ok(Some(result)) => result,
+            Ok(None) => return Ok(Err("skipped".to_owned())),
+            Err(error) => return Ok(Err(format!("{:#}", error))),
+        };
+        let data = fs::read(&result.outputs[0]).context("Could not read output")?;
+        hashes.push(hash(&data));
+        cropped = Some((result, data));
+    }
+    let (result, data) = cropped.unwrap();
+
+    let found = result.detections[0].corners;
+    let error = found
+        .iter()
+        .zip(&case.corners)
+        .map(|(f, e)| (f.0 - e.0).hypot(f.1 - e.1))
+        .fold(0.0f32, f32::max);
+    if error > CORNER_TOLERANCE {
+        return Ok(Err(format!(
+            "corners are up to {:.1}px from where they should be",
+            error
+        )));
+    }
+
+    let decoded = webp::Decoder::new(&data)
+        .decode()
+        .context("Could not decode output")?
+        .to_image()
+        .into_rgb8();
+    let (width, height) = output_size(&found, options.aspect_of(&found, SIZE), options.max_size);
+    let expected = RgbImage::from_fn(width, height, |x, y| {
+        photo(
+            (x as f32 + 0.5) / width as f32,
+            (y as f32 + 0.5) / height as f32,
+        )
+    });
+    let difference = match selftest::compare(&expected, &decoded) {
+        Some(difference) => difference,
+        None => {
+            return Ok(Err(format!(
+                "output is {}x{}, expected {}x{}",
+                decoded.width(),
+                decoded.height(),
+                width,
+                height
+            )))
+        }
+    };
+    if difference.mean > CONTENT_TOLERANCE {
+        return Ok(Err(format!(
+            "output differs from the photo by {:.2} on average",
+            difference.mean
+        )));
+    }
+    if hashes[0] != hashes[1] {
+        return Ok(Err(format!(
+            "two runs wrote different files ({:016x} and {:016x})",
+            hashes[0], hashes[1]
+        )));
+    }
+    Ok(Ok(format!(
+        "corners within {:.1}px, mean difference {:.2}, output {:016x}",
+        error, difference.mean, hashes[0]
+    )))
+}
+
+/// Crop every picture in the built-in corpus and check the results.
+///
+/// Each picture is detected, warped, encoded, and written like a normal run, then the corners
+/// are compared to where the photo was drawn, the output is decoded and compared to the photo,
+/// and a second run must write the same bytes.
+///
+/// Returns `true` if every picture passed.
+///
+/// # Errors
+///
+/// An error is returned if the temporary files cannot be written.
+pub fn run() -> anyhow::Result<bool> {
+    let dir = std::env::temp_dir().join(format!("qdcrop-selfcheck-{}", std::process::id()));
+    fs::create_dir_all(&dir).context("Could not create a temporary directory")?;
+    let options = CropOptions {
+        quality: 95.0,
+        ..CropOptions::default()
+    };
+
+    let mut passed = true;
+    for case in CORPUS {
+        match check(case, &dir, &options) {
+            Ok(Ok(summary)) => println!("{}: ok ({})", case.name, summary),
+            Ok(Err(problem)) => {
+                println!("{}: FAILED ({})", case.name, problem);
+                passed = false;
+            }
+            Err(error) => {
+                let _ = fs::remove_dir_all(&dir);
+                return Err(error);
+            }
+        }
+    }
+    let _ = fs::remove_dir_all(&dir);
+    Ok(passed)
+}
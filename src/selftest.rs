@@ -0,0 +1,188 @@
+use std::path::Path;
+
+use anyhow::Context;
+use image::{Rgb, RgbImage};
+use imageproc::geometric_transformations::Projection;
+use qdcrop::{
+    backend::{Backend, Interpolation},
+    detect_corners, from_control_points, output_size,
+    resize::{ResizeFilter, ScaleMode},
+    warp, Aspect, CropOptions, MaxSize, Quad,
+};
+
+/// How much the output of a backend differs from the reference.
+pub struct Difference {
+    /// The mean absolute difference of all channels of all pixels.
+    pub mean: f64,
+    /// The largest absolute difference of any channel.
+    pub max: u8,
+}
+
+/// Compare two images channel by channel.
+///
+/// `None` is returned if the images are not the same size.
+pub fn compare(a: &RgbImage, b: &RgbImage) -> Option<Difference> {
+    if a.dimensions() != b.dimensions() {
+        return None;
+    }
+    let mut sum = 0u64;
+    let mut max = 0u8;
+    for (a, b) in a.as_raw().iter().zip(b.as_raw()) {
+        let d = if a > b { a - b } else { b - a };
+        sum += d as u64;
+        max = std::cmp::max(max, d);
+    }
+    let count = std::cmp::max(a.as_raw().len(), 1);
+    Some(Difference {
+        mean: sum as f64 / count as f64,
+        max,
+    })
+}
+
+/// Build a synthetic screenshot with plenty of detail for the warp to resample.
+///
+/// The photo is larger than the maximum output size so that resizing is tested too.
+fn synthetic() -> (RgbImage, Quad) {
+    let img = RgbImage::from_fn(3840, 2160, |x, y| {
+        let checker = if (x / 16 + y / 16) % 2 == 0 { 64 } else { 192 };
+        Rgb([checker, (x * 255 / 3839) as u8, (y * 255 / 2159) as u8])
+    });
+    let corners = [
+        (450.0, 270.0),
+        (3420.0, 180.0),
+        (3540.0, 2010.0),
+        (330.0, 1920.0),
+    ];
+    (img, corners)
+}
+
+/// Compare an image to the expected image and print the result.
+///
+/// Returns `true` if the mean difference is within `tolerance`.
+fn report(
+    name: &str,
+    label: &str,
+    expected: &RgbImage,
+    actual: anyhow::Result<RgbImage>,
+    tolerance: f64,
+) -> bool {
+    let actual = match actual {
+        Ok(actual) => actual,
+        Err(error) => {
+            println!("{}: {} FAILED ({})", name, label, error);
+            return false;
+        }
+    };
+    match compare(expected, &actual) {
+        Some(difference) if difference.mean <= tolerance => {
+            println!(
+                "{}: {} ok (mean difference {:.3}, max {})",
+                name, label, difference.mean, difference.max
+            );
+            true
+        }
+        Some(difference) => {
+            println!(
+                "{}: {} FAILED (mean difference {:.3} exceeds {:.3}, max {})",
+                name, label, difference.mean, tolerance, difference.max
+            );
+            false
+        }
+        None => {
+            println!(
+                "{}: {} FAILED (output is {}x{}, expected {}x{})",
+                name,
+                label,
+                actual.width(),
+                actual.height(),
+                expected.width(),
+                expected.height()
+            );
+            false
+        }
+    }
+}
+
+/// Run one image through every backend and compare the results to the reference.
+///
+/// The two pass resize path is also compared to the fused warp with every resize filter.
+///
+/// Returns `true` if every backend and filter agreed with the reference.
+fn check(
+    name: &str,
+    img: &RgbImage,
+    corners: Quad,
+    tolerances: &Tolerances,
+) -> anyhow::Result<bool> {
+    let size = output_size(&corners, Aspect::default(), MaxSize::default());
+    let projection: Projection = from_control_points(corners, size)?;
+
+    let (reference, others) = Backend::ALL.split_first().unwrap();
+    let expected = reference
+        .warp(img, &projection, size, Interpolation::default())
+        .with_context(|| format!("Reference backend {} failed", reference.name()))?;
+
+    let mut passed = true;
+    if others.is_empty() {
+        println!(
+            "{}: only the {} backend is enabled; nothing to compare",
+            name,
+            reference.name()
+        );
+    }
+    for backend in others {
+        let actual = backend.warp(img, &projection, size, Interpolation::default());
+        passed &= report(name, backend.name(), &expected, actual, tolerances.backend);
+    }
+
+    for filter in ResizeFilter::ALL {
+        let options = CropOptions {
+            scale_mode: ScaleMode::TwoPass,
+            resize_filter: *filter,
+            ..CropOptions::default()
+        };
+        let actual = warp(img, corners, size, &options).map_err(anyhow::Error::from);
+        let label = format!("two-pass {}", filter.name());
+        passed &= report(name, &label, &expected, actual, tolerances.resize);
+    }
+    Ok(passed)
+}
+
+/// The largest acceptable mean differences per channel.
+pub struct Tolerances {
+    /// Between backends, which should produce nearly identical output.
+    pub backend: f64,
+    /// Between the fused warp and the two pass resize, which use different filters.
+    pub resize: f64,
+}
+
+/// Check that every enabled backend produces the same output as the reference backend.
+///
+/// # Arguments
+///
+/// * `inputs` - Screenshots to test with. A synthetic image is used when this is empty.
+/// * `tolerances` - The largest acceptable mean differences per channel.
+///
+/// # Errors
+///
+/// An error is returned if an input cannot be loaded or the reference backend fails.
+pub fn run(inputs: &[&Path], tolerances: &Tolerances) -> anyhow::Result<bool> {
+    if inputs.is_empty() {
+        let (img, corners) = synthetic();
+        return check("synthetic", &img, corners, tolerances);
+    }
+
+    let mut passed = true;
+    for input in inputs {
+        let name = input.to_string_lossy();
+        let img = image::open(input)
+            .with_context(|| format!("Could not open {}", name))?
+            .into_rgb8();
+        let luma = image::imageops::grayscale(&img);
+        let corners = detect_corners(&img, &luma, &CropOptions::default())
+            .with_context(|| format!("Detection failed for {}", name))?
+            .corners;
+        passed &= check(&name, &img, corners, tolerances)?;
+    }
+    Ok(passed)
+}
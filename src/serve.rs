@@ -0,0 +1,209 @@
+use std::{io::Read, net::SocketAddr, thread};
+
+use image::RgbImage;
+use qdcrop::{crop_photo, encode, find_photos, parse_quad, CropOptions, Detection, Error};
+use serde_json::json;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::detect;
+
+/// The largest upload that is accepted, in bytes.
+const MAX_UPLOAD: u64 = 64 * 1024 * 1024;
+
+/// The page for cropping in the browser.
+const UI: &str = include_str!("ui.html");
+/// A response to send, with its status code, content type, and body.
+type Reply = (u16, &'static str, Vec<u8>);
+
+/// The part of a `multipart/form-data` body that holds the uploaded file.
+///
+/// The first part with a file name is used, or the first part if none has one. Returns `None` if
+/// the body is not valid.
+fn multipart_file<'a>(body: &'a [u8], boundary: &str) -> Option<&'a [u8]> {
+    let delimiter = format!("--{}", boundary);
+    let delimiter = delimiter.as_bytes();
+    let find = |haystack: &[u8], needle: &[u8]| {
+        haystack
+            .windows(needle.len())
+            .position(|window| window == needle)
+    };
+
+    let mut parts = Vec::new();
+    let mut rest = &body[find(body, delimiter)? + delimiter.len()..];
+    while !rest.starts_with(b"--") {
+        let headers_end = find(rest, b"\r\n\r\n")?;
+        let headers = String::from_utf8_lossy(&rest[..headers_end]).to_lowercase();
+        let content = &rest[headers_end + 4..];
+        let end = find(content, delimiter)?;
+        // The line break before the delimiter belongs to it.
+        let data = content[..end].strip_suffix(b"\r\n")?;
+        parts.push((headers.contains("filename="), data));
+        rest = &content[end + delimiter.len()..];
+    }
+    parts
+        .iter()
+        .find(|(is_file, _)| *is_file)
+        .or_else(|| parts.first())
+        .map(|(_, data)| *data)
+}
+
+/// Read the uploaded image from a request, either as the whole body or from a form.
+fn read_upload(request: &mut Request) -> Result<Vec<u8>, Reply> {
+    let content_type = request
+        .headers()
+        .iter()
+        .find(|h| h.field.equiv("Content-Type"))
+        .map(|h| h.value.as_str().to_owned())
+        .unwrap_or_default();
+    let mut body = Vec::new();
+    request
+        .as_reader()
+        .take(MAX_UPLOAD + 1)
+        .read_to_end(&mut body)
+        .map_err(|e| error_reply(400, "io", &format!("Could not read upload: {}", e)))?;
+    if body.len() as u64 > MAX_UPLOAD {
+        return Err(error_reply(413, "too_large", "The upload is too large"));
+    }
+
+    let boundary = content_type
+        .strip_prefix("multipart/form-data")
+        .and_then(|params| {
+            params
+                .split(';')
+                .find_map(|p| p.trim().strip_prefix("boundary="))
+        })
+        .map(|boundary| boundary.trim_matches('"').to_owned());
+    match boundary {
+        Some(boundary) => multipart_file(&body, &boundary)
+            .map(<[u8]>::to_vec)
+            .ok_or_else(|| error_reply(400, "invalid_upload", "Invalid multipart upload")),
+        None => Ok(body),
+    }
+}
+
+/// A JSON error response.
+fn error_reply(status: u16, kind: &str, message: &str) -> Reply {
+    let body = json!({ "error": { "kind": kind, "message": message } });
+    (status, "application/json", body.to_string().into_bytes())
+}
+
+/// The response for an error from qdcrop.
+fn reply_for(error: Error) -> Reply {
+    let status = match error {
+        Error::DecodeError(_) => 415,
+        Error::DetectionFailed(_) | Error::LowConfidence { .. } | Error::DegenerateQuad(_) => 422,
+        _ => 500,
+    };
+    error_reply(status, error.kind(), &error.to_string())
+}
+
+/// Find the photos in an uploaded screenshot.
+///
+/// Returns the screenshot and the photos, most confident first.
+fn find(upload: &[u8], options: &CropOptions) -> Result<(RgbImage, Vec<Detection>), Error> {
+    let img = image::load_from_memory(upload)
+        .map_err(Error::DecodeError)?
+        .into_rgb8();
+    let detections = find_photos(&img, options)?;
+    if let Some(detection) = detections
+        .iter()
+        .find(|d| d.confidence < options.min_confidence)
+    {
+        return Err(Error::LowConfidence {
+            confidence: detection.confidence,
+            min_confidence: options.min_confidence,
+        });
+    }
+    Ok((img, detections))
+}
+
+/// The value of a parameter in the query string of a URL.
+fn query_param<'a>(url: &'a str, name: &str) -> Option<&'a str> {
+    let (_, query) = url.split_once('?')?;
+    query
+        .split('&')
+        .find_map(|param| param.strip_prefix(name)?.strip_prefix('='))
+}
+
+/// Answer one request.
+fn handle(request: &mut Request, options: &CropOptions) -> Reply {
+    let url = request.url().to_owned();
+    let path = url.split('?').next().unwrap_or_default();
+    if *request.method() == Method::Get && path == "/" {
+        return (200, "text/html; charset=utf-8", UI.as_bytes().to_vec());
+    }
+    if *request.method() != Method::Post {
+        return error_reply(405, "method_not_allowed", "Upload a screenshot with POST");
+    }
+    // Corners chosen in the page replace detection.
+    let with_corners;
+    let options = match query_param(&url, "corners") {
+        Some(corners) => match parse_quad(&corners.replace("%2C", ",")) {
+            Ok(corners) => {
+                with_corners = CropOptions {
+                    corners: vec![corners],
+                    ..options.clone()
+                };
+                &with_corners
+            }
+            Err(error) => return error_reply(400, "invalid_options", &format!("{:#}", error)),
+        },
+        None => options,
+    };
+    let upload = match read_upload(request) {
+        Ok(upload) => upload,
+        Err(reply) => return reply,
+    };
+    let result = match path {
+        "/crop" => find(&upload, options).and_then(|(img, detections)| {
+            let photo = crop_photo(&img, &detections[0], options)?;
+            Ok((200, "image/webp", encode(&photo.image, options)?))
+        }),
+        "/detect" => find(&upload, options).map(|(img, detections)| {
+            let body = json!({
+                "size": [img.width(), img.height()],
+                "photos": detect::photos(&detections),
+            });
+            (200, "application/json", body.to_string().into_bytes())
+        }),
+        _ => return error_reply(404, "not_found", "Use /crop or /detect"),
+    };
+    result.unwrap_or_else(reply_for)
+}
+
+/// Run the `serve` subcommand until it is interrupted.
+///
+/// `GET /` returns a page where screenshots can be dragged in, their corners adjusted, and their
+/// photos downloaded.
+///
+/// `POST /crop` takes a screenshot, as the request body or as a file in a form, and returns the
+/// most confident photo in it as WebP, or the photo at `?corners=x0,y0,...` if given. `POST /detect` returns the size of the screenshot and its
+/// photos as JSON, in the format of `qdcrop detect`. Errors are returned as JSON with the `kind`
+/// of error from `qdcrop::Error::kind` and its `message`.
+///
+/// # Errors
+///
+/// An error is returned if the address cannot be listened on.
+pub fn run(address: SocketAddr, options: &CropOptions) -> anyhow::Result<()> {
+    let server = Server::http(address)
+        .map_err(|e| anyhow::anyhow!("Could not listen on {}: {}", address, e))?;
+    eprintln!("Listening on http://{}. Press Ctrl+C to stop.", address);
+    let workers = thread::available_parallelism().map_or(1, |n| n.get());
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| {
+                for mut request in server.incoming_requests() {
+                    let (status, content_type, body) = handle(&mut request, options);
+                    let header = Header::from_bytes("Content-Type", content_type).unwrap();
+                    let response = Response::from_data(body)
+                        .with_status_code(status)
+                        .with_header(header);
+                    if let Err(error) = request.respond(response) {
+                        eprintln!("Could not send response: {}", error);
+                    }
+                }
+            });
+        }
+    });
+    Ok(())
+}
@@ -0,0 +1,149 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context};
+use image::{imageops::FilterType, Rgb, RgbImage};
+use qdcrop::{encode, CropOptions};
+use rayon::prelude::*;
+
+use crate::numbered;
+
+/// The space around each photo on a sheet.
+const GAP: u32 = 8;
+/// The color of the sheet behind the photos.
+const BACKGROUND: Rgb<u8> = Rgb([24, 24, 24]);
+
+/// The layout of contact sheets.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Layout {
+    /// The number of photos across each sheet.
+    pub columns: u32,
+    /// The number of photos down each sheet.
+    pub rows: u32,
+    /// The largest width of a photo on the sheet.
+    pub cell_width: u32,
+    /// The largest height of a photo on the sheet.
+    pub cell_height: u32,
+}
+
+/// Parse two sizes written as `AxB`, such as `4x3`.
+///
+/// # Errors
+///
+/// An error is returned if the text is not two positive numbers separated by `x`.
+pub fn parse_pair(s: &str) -> anyhow::Result<(u32, u32)> {
+    let (a, b) = s
+        .split_once(['x', 'X'])
+        .with_context(|| format!("Expected two numbers like 4x3, but found {}", s))?;
+    let a: u32 = a.trim().parse().context("Invalid number")?;
+    let b: u32 = b.trim().parse().context("Invalid number")?;
+    if a == 0 || b == 0 {
+        bail!("{} must not contain 0", s);
+    }
+    Ok((a, b))
+}
+
+/// Read a cropped photo back, shrunk to fit in a cell.
+fn thumbnail(path: &Path, layout: &Layout) -> anyhow::Result<RgbImage> {
+    let img = if path
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("webp"))
+    {
+        let data = fs::read(path).context("Could not read photo")?;
+        webp::Decoder::new(&data)
+            .decode()
+            .context("Could not decode photo")?
+            .to_image()
+            .into_rgb8()
+    } else {
+        image::open(path)
+            .context("Could not decode photo")?
+            .into_rgb8()
+    };
+    let scale = f64::min(
+        layout.cell_width as f64 / img.width() as f64,
+        layout.cell_height as f64 / img.height() as f64,
+    );
+    if scale >= 1.0 {
+        return Ok(img);
+    }
+    Ok(image::imageops::resize(
+        &img,
+        std::cmp::max((img.width() as f64 * scale).round() as u32, 1),
+        std::cmp::max((img.height() as f64 * scale).round() as u32, 1),
+        FilterType::Triangle,
+    ))
+}
+
+/// Place photos on one sheet, centered in their cells.
+fn draw_sheet(photos: &[RgbImage], layout: &Layout) -> RgbImage {
+    let rows = std::cmp::min(layout.rows, (photos.len() as u32).div_ceil(layout.columns));
+    let mut sheet = RgbImage::from_pixel(
+        layout.columns * (layout.cell_width + GAP) + GAP,
+        rows * (layout.cell_height + GAP) + GAP,
+        BACKGROUND,
+    );
+    for (i, photo) in photos.iter().enumerate() {
+        let (column, row) = (i as u32 % layout.columns, i as u32 / layout.columns);
+        let x = GAP + column * (layout.cell_width + GAP) + (layout.cell_width - photo.width()) / 2;
+        let y = GAP + row * (layout.cell_height + GAP) + (layout.cell_height - photo.height()) / 2;
+        image::imageops::replace(&mut sheet, photo, x as i64, y as i64);
+    }
+    sheet
+}
+
+/// Tile cropped photos onto contact sheets for a quick overview of a batch.
+///
+/// # Arguments
+///
+/// * `outputs` - The photos, in the order they are placed on the sheets.
+/// * `path` - Where to save the sheet. When there are more photos than fit on one sheet, the
+///   sheets are numbered from 1 with `numbered`.
+/// * `layout` - How many photos go on each sheet and how large they are.
+/// * `options` - The settings used to encode the sheets.
+///
+/// Returns the sheets that were written. Photos that cannot be read are left out with a
+/// warning.
+///
+/// # Errors
+///
+/// An error is returned if a sheet cannot be encoded or written.
+pub fn write(
+    outputs: &[PathBuf],
+    path: &Path,
+    layout: &Layout,
+    options: &CropOptions,
+) -> anyhow::Result<Vec<PathBuf>> {
+    let photos: Vec<_> = outputs
+        .par_iter()
+        .filter_map(|output| match thumbnail(output, layout) {
+            Ok(photo) => Some(photo),
+            Err(error) => {
+                eprintln!(
+                    "Left {} out of the contact sheet: {:#}",
+                    output.to_string_lossy(),
+                    error
+                );
+                None
+            }
+        })
+        .collect();
+
+    let per_sheet = (layout.columns * layout.rows) as usize;
+    let count = photos.len().div_ceil(per_sheet);
+    let mut sheets = Vec::new();
+    for (i, chunk) in photos.chunks(per_sheet).enumerate() {
+        let sheet_path = if count > 1 {
+            numbered(path, i + 1)
+        } else {
+            path.to_owned()
+        };
+        let encoded = encode(&draw_sheet(chunk, layout), options)?;
+        fs::write(&sheet_path, &encoded)
+            .with_context(|| format!("Could not write {}", sheet_path.to_string_lossy()))?;
+        sheets.push(sheet_path);
+    }
+    Ok(sheets)
+}
@@ -0,0 +1,55 @@
+use std::{env, process::Command};
+
+use anyhow::{bail, Context};
+
+/// The registry key of the menu entry, shown for every kind of picture.
+const KEY: &str = r"HKCU\Software\Classes\SystemFileAssociations\image\shell\qdcrop";
+
+/// Run `reg` with arguments and fail if it does.
+fn reg(args: &[&str]) -> anyhow::Result<()> {
+    let status = Command::new("reg")
+        .args(args)
+        .arg("/f")
+        .status()
+        .context("Could not run reg")?;
+    if !status.success() {
+        bail!("reg {} failed", args[0]);
+    }
+    Ok(())
+}
+
+/// Add "Crop with qdcrop" to the right-click menu of pictures in Explorer, for the current user.
+///
+/// The entry runs this copy of qdcrop on the picture with the settings in the configuration
+/// file, so the photo is saved next to the picture unless `output-dir` is set there.
+///
+/// # Errors
+///
+/// An error is returned if this is not Windows or the registry cannot be written.
+pub fn install() -> anyhow::Result<()> {
+    if !cfg!(windows) {
+        bail!("The right-click menu can only be installed on Windows");
+    }
+    let exe = env::current_exe().context("Could not find qdcrop")?;
+    let exe = exe.to_string_lossy();
+    let command = format!("\"{}\" \"%1\"", exe);
+    reg(&["add", KEY, "/ve", "/d", "Crop with qdcrop"])?;
+    reg(&["add", KEY, "/v", "Icon", "/d", &exe])?;
+    reg(&["add", &format!(r"{}\command", KEY), "/ve", "/d", &command])?;
+    eprintln!("Added Crop with qdcrop to the right-click menu of pictures.");
+    Ok(())
+}
+
+/// Remove the menu entry added by `install`.
+///
+/// # Errors
+///
+/// An error is returned if this is not Windows or the registry cannot be written.
+pub fn uninstall() -> anyhow::Result<()> {
+    if !cfg!(windows) {
+        bail!("The right-click menu can only be uninstalled on Windows");
+    }
+    reg(&["delete", KEY])?;
+    eprintln!("Removed Crop with qdcrop from the right-click menu.");
+    Ok(())
+}
@@ -0,0 +1,98 @@
+use std::{
+    ffi::OsString,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context};
+use qdcrop::{Detection, Quad};
+use serde_json::{json, Value};
+
+use crate::numbered;
+
+/// The path of the sidecar of an output, which is the output's name with `.json` added.
+pub fn path(output: &Path) -> PathBuf {
+    let mut path = OsString::from(output);
+    path.push(".json");
+    PathBuf::from(path)
+}
+
+/// Write a JSON sidecar next to an output, recording where the photo was found.
+///
+/// # Arguments
+///
+/// * `output` - The output the sidecar describes.
+/// * `detection` - The detected corners of the photo.
+/// * `input_size` - The size of the screenshot the corners are in.
+/// * `output_size` - The size of the output.
+///
+/// # Errors
+///
+/// An error is returned if the sidecar cannot be written.
+pub fn write(
+    output: &Path,
+    detection: &Detection,
+    input_size: (u32, u32),
+    output_size: (u32, u32),
+) -> anyhow::Result<()> {
+    let json = json!({
+        "corners": detection.corners.map(|p| [p.0, p.1]),
+        "confidence": detection.confidence,
+        "detector": detection.detector,
+        "input_size": [input_size.0, input_size.1],
+        "output_size": [output_size.0, output_size.1],
+    });
+    let text = serde_json::to_string_pretty(&json)?;
+    fs::write(path(output), text).context("Could not write sidecar")
+}
+
+/// Read the corners from one sidecar.
+fn read_corners(path: &Path) -> anyhow::Result<Quad> {
+    let text = fs::read_to_string(path)
+        .with_context(|| format!("Could not read {}", path.to_string_lossy()))?;
+    let json: Value = serde_json::from_str(&text)
+        .with_context(|| format!("Invalid JSON in {}", path.to_string_lossy()))?;
+    let points = json["corners"]
+        .as_array()
+        .and_then(|corners| {
+            corners
+                .iter()
+                .map(|p| Some((p[0].as_f64()? as f32, p[1].as_f64()? as f32)))
+                .collect::<Option<Vec<_>>>()
+        })
+        .with_context(|| format!("Invalid corners in {}", path.to_string_lossy()))?;
+    match points.try_into() {
+        Ok(quad) => Ok(quad),
+        Err(points) => bail!(
+            "Expected 4 corners in {}, but found {}",
+            path.to_string_lossy(),
+            points.len()
+        ),
+    }
+}
+
+/// Read the corners of the photos from the sidecars of an output, which may have been edited.
+///
+/// The sidecar of `output` itself is used if it exists. Otherwise, the sidecars of numbered
+/// outputs, like `photo_1.webp.json`, are read in order until one is missing.
+///
+/// Returns `None` if there are no sidecars.
+///
+/// # Errors
+///
+/// An error is returned if a sidecar cannot be read or does not have four corners.
+pub fn read(output: &Path) -> anyhow::Result<Option<Vec<Quad>>> {
+    let single = path(output);
+    if single.exists() {
+        return Ok(Some(vec![read_corners(&single)?]));
+    }
+    let mut quads = Vec::new();
+    for number in 1.. {
+        let path = path(&numbered(output, number));
+        if !path.exists() {
+            break;
+        }
+        quads.push(read_corners(&path)?);
+    }
+    Ok((!quads.is_empty()).then_some(quads))
+}
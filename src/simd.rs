@@ -0,0 +1,207 @@
+#[cfg(target_arch = "x86")]
+use std::arch::x86::*;
+#[cfg(target_arch = "x86_64")]
+use std::arch::x86_64::*;
+
+use crate::backend::square_to_quad;
+use image::RgbImage;
+use imageproc::geometric_transformations::Projection;
+
+/// The widest instruction set this processor supports that the warp can use.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Level {
+    /// Eight pixels are projected at a time.
+    Avx2,
+    /// Four pixels are projected at a time.
+    Sse41,
+}
+
+fn level() -> Option<Level> {
+    if is_x86_feature_detected!("avx2") {
+        Some(Level::Avx2)
+    } else if is_x86_feature_detected!("sse4.1") {
+        Some(Level::Sse41)
+    } else {
+        None
+    }
+}
+
+/// Check whether this processor can run the SIMD warp.
+pub fn is_supported() -> bool {
+    level().is_some()
+}
+
+/// The bicubic weights of the four pixels around a point, the same as imageproc's.
+fn weights(t: f32) -> [f32; 4] {
+    let (t2, t3) = (t * t, t * t * t);
+    [
+        0.5 * (-t + 2.0 * t2 - t3),
+        1.0 + 0.5 * (-5.0 * t2 + 3.0 * t3),
+        0.5 * (t + 4.0 * t2 - 3.0 * t3),
+        0.5 * (-t2 + t3),
+    ]
+}
+
+/// Sample the source at a point with bicubic interpolation, all three channels at once.
+///
+/// Like imageproc, points whose 4x4 neighborhood is not entirely inside the source are black,
+/// and each row is clamped to 8 bits before the rows are blended.
+#[target_feature(enable = "sse4.1")]
+unsafe fn sample(img: &RgbImage, x: f32, y: f32, pixel: &mut [u8]) {
+    let (width, height) = img.dimensions();
+    let (fx, fy) = (x.floor(), y.floor());
+    if fx < 1.0 || fx + 3.0 >= width as f32 || fy < 1.0 || fy + 3.0 >= height as f32 {
+        pixel.fill(0);
+        return;
+    }
+    let (wx, wy) = (weights(x - fx), weights(y - fy));
+    let (left, top) = (fx as usize - 1, fy as usize - 1);
+    let raw = img.as_raw();
+    let zero = _mm_setzero_ps();
+    let max = _mm_set1_ps(255.0);
+
+    let mut column = zero;
+    for (j, wy) in wy.iter().enumerate() {
+        let offset = ((top + j) * width as usize + left) * 3;
+        let mut row = zero;
+        for (i, wx) in wx.iter().enumerate() {
+            // Four bytes are read, and the fourth, from the next pixel, is ignored. The
+            // neighborhood check keeps this inside the image.
+            let start = offset + i * 3;
+            let bytes = u32::from_le_bytes(raw[start..start + 4].try_into().unwrap());
+            let channels = _mm_cvtepi32_ps(_mm_cvtepu8_epi32(_mm_cvtsi32_si128(bytes as i32)));
+            row = _mm_add_ps(row, _mm_mul_ps(channels, _mm_set1_ps(*wx)));
+        }
+        let row = _mm_round_ps(
+            _mm_min_ps(_mm_max_ps(row, zero), max),
+            _MM_FROUND_TO_ZERO | _MM_FROUND_NO_EXC,
+        );
+        column = _mm_add_ps(column, _mm_mul_ps(row, _mm_set1_ps(*wy)));
+    }
+    let mut out = [0.0f32; 4];
+    _mm_storeu_ps(out.as_mut_ptr(), _mm_min_ps(_mm_max_ps(column, zero), max));
+    for (p, v) in pixel.iter_mut().zip(out) {
+        *p = v as u8;
+    }
+}
+
+/// Warp one row of a tile of the output with AVX2, starting at column `left`.
+///
+/// `m` maps output pixels to source pixels as described by `square_to_quad`.
+#[target_feature(enable = "avx2")]
+unsafe fn row_avx2(img: &RgbImage, m: &[f32; 8], left: f32, y: f32, row: &mut [u8]) {
+    let width = row.len() / 3;
+    let lanes = _mm256_setr_ps(0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0);
+    let mut xs = [0.0f32; 8];
+    let mut ys = [0.0f32; 8];
+    for start in (0..width).step_by(8) {
+        let x = _mm256_add_ps(_mm256_set1_ps(left + start as f32), lanes);
+        let source_x = _mm256_add_ps(
+            _mm256_mul_ps(_mm256_set1_ps(m[0]), x),
+            _mm256_set1_ps(m[1] * y + m[2]),
+        );
+        let source_y = _mm256_add_ps(
+            _mm256_mul_ps(_mm256_set1_ps(m[3]), x),
+            _mm256_set1_ps(m[4] * y + m[5]),
+        );
+        let w = _mm256_add_ps(
+            _mm256_mul_ps(_mm256_set1_ps(m[6]), x),
+            _mm256_set1_ps(m[7] * y + 1.0),
+        );
+        _mm256_storeu_ps(xs.as_mut_ptr(), _mm256_div_ps(source_x, w));
+        _mm256_storeu_ps(ys.as_mut_ptr(), _mm256_div_ps(source_y, w));
+        let pixels = row[start * 3..].chunks_exact_mut(3);
+        for ((pixel, x), y) in pixels.zip(xs).zip(ys) {
+            sample(img, x, y, pixel);
+        }
+    }
+}
+
+/// Warp one row of a tile of the output with SSE4.1, starting at column `left`.
+///
+/// `m` maps output pixels to source pixels as described by `square_to_quad`.
+#[target_feature(enable = "sse4.1")]
+unsafe fn row_sse41(img: &RgbImage, m: &[f32; 8], left: f32, y: f32, row: &mut [u8]) {
+    let width = row.len() / 3;
+    let lanes = _mm_setr_ps(0.0, 1.0, 2.0, 3.0);
+    let mut xs = [0.0f32; 4];
+    let mut ys = [0.0f32; 4];
+    for start in (0..width).step_by(4) {
+        let x = _mm_add_ps(_mm_set1_ps(left + start as f32), lanes);
+        let source_x = _mm_add_ps(
+            _mm_mul_ps(_mm_set1_ps(m[0]), x),
+            _mm_set1_ps(m[1] * y + m[2]),
+        );
+        let source_y = _mm_add_ps(
+            _mm_mul_ps(_mm_set1_ps(m[3]), x),
+            _mm_set1_ps(m[4] * y + m[5]),
+        );
+        let w = _mm_add_ps(
+            _mm_mul_ps(_mm_set1_ps(m[6]), x),
+            _mm_set1_ps(m[7] * y + 1.0),
+        );
+        _mm_storeu_ps(xs.as_mut_ptr(), _mm_div_ps(source_x, w));
+        _mm_storeu_ps(ys.as_mut_ptr(), _mm_div_ps(source_y, w));
+        let pixels = row[start * 3..].chunks_exact_mut(3);
+        for ((pixel, x), y) in pixels.zip(xs).zip(ys) {
+            sample(img, x, y, pixel);
+        }
+    }
+}
+
+/// Reverse a perspective projection with bicubic interpolation, using the widest SIMD
+/// instructions this processor supports.
+///
+/// Returns `None` if the processor does not support SSE4.1.
+///
+/// # Arguments
+///
+/// * `img` - The source image.
+/// * `projection` - The projection from source coordinates to output coordinates.
+/// * `size` - The width and height of the output image.
+pub fn warp(img: &RgbImage, projection: &Projection, size: (u32, u32)) -> Option<RgbImage> {
+    let level = level()?;
+    let inverse = projection.invert();
+    let (w, h) = (size.0 as f32, size.1 as f32);
+    let quad = [(0.0, 0.0), (w, 0.0), (w, h), (0.0, h)].map(|p| inverse * p);
+    // Scale the map from the unit square so that it takes output pixels.
+    let m = square_to_quad(&quad);
+    let m = [
+        m[0] / w,
+        m[1] / h,
+        m[2],
+        m[3] / w,
+        m[4] / h,
+        m[5],
+        m[6] / w,
+        m[7] / h,
+    ];
+
+    let map = |(x, y): (f32, f32)| {
+        let w = m[6] * x + m[7] * y + 1.0;
+        (
+            (m[0] * x + m[1] * y + m[2]) / w,
+            (m[3] * x + m[4] * y + m[5]) / w,
+        )
+    };
+
+    let mut out_img = crate::pool::rgb(size.0, size.1);
+    crate::for_each_tile(&mut out_img, |mut tile| {
+        // Tiles that sample nothing stay black, as `sample` would leave them.
+        if tile.is_outside(map, img.dimensions(), 2.0) {
+            return;
+        }
+        let left = tile.x as f32;
+        for (y, row) in tile.rows.iter_mut().enumerate() {
+            let y = (tile.y as usize + y) as f32;
+            // SAFETY: `level` checked that the processor supports these instructions.
+            unsafe {
+                match level {
+                    Level::Avx2 => row_avx2(img, &m, left, y, row),
+                    Level::Sse41 => row_sse41(img, &m, left, y, row),
+                }
+            }
+        }
+    });
+    Some(out_img)
+}
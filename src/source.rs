@@ -0,0 +1,391 @@
+use std::{
+    env,
+    ffi::OsStr,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context};
+
+/// A place where a game saves screenshots.
+pub trait Source: Sync {
+    /// The name used to select this source on the command line.
+    fn name(&self) -> &'static str;
+
+    /// Directories that may contain screenshots, most likely first.
+    ///
+    /// The directories do not need to exist.
+    fn directories(&self) -> Vec<PathBuf>;
+
+    /// Check whether a subdirectory should be left out of the search.
+    fn is_ignored(&self, _directory: &Path) -> bool {
+        false
+    }
+}
+
+impl<S: Source + ?Sized> Source for &S {
+    fn name(&self) -> &'static str {
+        (**self).name()
+    }
+
+    fn directories(&self) -> Vec<PathBuf> {
+        (**self).directories()
+    }
+
+    fn is_ignored(&self, directory: &Path) -> bool {
+        (**self).is_ignored(directory)
+    }
+}
+
+/// A game that saves screenshots to a folder in the user's Pictures folder.
+struct PicturesFolder {
+    name: &'static str,
+    /// The name of the folder inside Pictures.
+    folder: &'static str,
+    /// The Steam app ID, used to find the Pictures folder inside a Proton prefix.
+    app_id: u32,
+}
+
+impl Source for PicturesFolder {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn directories(&self) -> Vec<PathBuf> {
+        let mut directories: Vec<_> = pictures_directories()
+            .into_iter()
+            .map(|p| p.join(self.folder))
+            .collect();
+        // Proton keeps a separate Windows user profile for each game.
+        if cfg!(windows) {
+            return directories;
+        }
+        for library in steam_libraries() {
+            directories.push(
+                library
+                    .join("steamapps/compatdata")
+                    .join(self.app_id.to_string())
+                    .join("pfx/drive_c/users/steamuser/Pictures")
+                    .join(self.folder),
+            );
+        }
+        directories
+    }
+}
+
+/// VRChat, which saves photos to Pictures unless another folder is chosen in its settings.
+struct Vrchat {
+    pictures: PicturesFolder,
+}
+
+impl Vrchat {
+    /// Directories where VRChat may keep its `config.json`, where a custom photo folder is set
+    /// as `picture_output_folder`.
+    ///
+    /// Returns each directory with the root of the Windows drive C: that paths in the file are
+    /// relative to, which is the root of a Proton prefix on Linux.
+    fn config_directories(&self) -> Vec<(PathBuf, Option<PathBuf>)> {
+        const CONFIG_DIR: &str = "AppData/LocalLow/VRChat/VRChat";
+        if cfg!(windows) {
+            return home_directory()
+                .map(|home| (home.join(CONFIG_DIR), None))
+                .into_iter()
+                .collect();
+        }
+        steam_libraries()
+            .into_iter()
+            .map(|library| {
+                let drive_c = library
+                    .join("steamapps/compatdata")
+                    .join(self.pictures.app_id.to_string())
+                    .join("pfx/drive_c");
+                (
+                    drive_c.join("users/steamuser").join(CONFIG_DIR),
+                    Some(drive_c),
+                )
+            })
+            .collect()
+    }
+
+    /// The custom photo folders set in VRChat's settings.
+    fn custom_directories(&self) -> Vec<PathBuf> {
+        let mut directories = Vec::new();
+        for (config_dir, drive_c) in self.config_directories() {
+            let Ok(text) = fs::read_to_string(config_dir.join("config.json")) else {
+                continue;
+            };
+            let Ok(config) = serde_json::from_str::<serde_json::Value>(&text) else {
+                continue;
+            };
+            let Some(folder) = config["picture_output_folder"].as_str() else {
+                continue;
+            };
+            match &drive_c {
+                None => directories.push(PathBuf::from(folder)),
+                // Proton maps drive C: to the prefix and Z: to the Linux root.
+                Some(drive_c) => {
+                    let folder = folder.replace('\\', "/");
+                    if let Some(rest) = folder.strip_prefix("C:/").or(folder.strip_prefix("c:/")) {
+                        directories.push(drive_c.join(rest));
+                    } else if let Some(rest) =
+                        folder.strip_prefix("Z:").or(folder.strip_prefix("z:"))
+                    {
+                        directories.push(PathBuf::from(rest));
+                    }
+                }
+            }
+        }
+        directories
+    }
+}
+
+impl Source for Vrchat {
+    fn name(&self) -> &'static str {
+        self.pictures.name
+    }
+
+    fn directories(&self) -> Vec<PathBuf> {
+        let mut directories = self.custom_directories();
+        directories.extend(self.pictures.directories());
+        directories
+    }
+}
+
+static VRCHAT: Vrchat = Vrchat {
+    pictures: PicturesFolder {
+        name: "vrchat",
+        folder: "VRChat",
+        app_id: 438100,
+    },
+};
+
+static CHILLOUTVR: PicturesFolder = PicturesFolder {
+    name: "chilloutvr",
+    folder: "ChilloutVR",
+    app_id: 661130,
+};
+
+static RESONITE: PicturesFolder = PicturesFolder {
+    name: "resonite",
+    folder: "Resonite",
+    app_id: 2519830,
+};
+
+/// Screenshots taken with Steam's screenshot key.
+///
+/// Steam saves screenshots for each account and game to
+/// `userdata/<account>/760/remote/<app>/screenshots`, with smaller copies in a `thumbnails`
+/// subdirectory.
+struct SteamScreenshots {
+    /// The game to find screenshots for, or `None` for every game.
+    app_id: Option<u32>,
+}
+
+impl Source for SteamScreenshots {
+    fn name(&self) -> &'static str {
+        "steam"
+    }
+
+    fn directories(&self) -> Vec<PathBuf> {
+        let mut directories = Vec::new();
+        for steam in steam_directories() {
+            for account in subdirectories(&steam.join("userdata")) {
+                let remote = account.join("760/remote");
+                match self.app_id {
+                    Some(app_id) => {
+                        directories.push(remote.join(app_id.to_string()).join("screenshots"))
+                    }
+                    None => directories.extend(
+                        subdirectories(&remote)
+                            .into_iter()
+                            .map(|app| app.join("screenshots")),
+                    ),
+                }
+            }
+        }
+        directories
+    }
+
+    fn is_ignored(&self, directory: &Path) -> bool {
+        directory.file_name() == Some(OsStr::new("thumbnails"))
+    }
+}
+
+/// A folder chosen by the user.
+struct Folder {
+    path: PathBuf,
+}
+
+impl Source for Folder {
+    fn name(&self) -> &'static str {
+        "folder"
+    }
+
+    fn directories(&self) -> Vec<PathBuf> {
+        vec![self.path.clone()]
+    }
+}
+
+static STEAM: SteamScreenshots = SteamScreenshots { app_id: None };
+
+/// Every built in source.
+pub static SOURCES: &[&dyn Source] = &[&VRCHAT, &CHILLOUTVR, &RESONITE, &STEAM];
+
+/// Find a source by name.
+///
+/// Besides the names of the built in sources, `steam:<app id>` selects Steam screenshots for
+/// one game, and `folder:<path>` selects any folder.
+pub fn find(name: &str) -> Option<Box<dyn Source>> {
+    if let Some(path) = name.strip_prefix("folder:") {
+        return Some(Box::new(Folder {
+            path: PathBuf::from(path),
+        }));
+    }
+    if let Some(app_id) = name.strip_prefix("steam:") {
+        let app_id = app_id.parse().ok()?;
+        return Some(Box::new(SteamScreenshots {
+            app_id: Some(app_id),
+        }));
+    }
+    SOURCES
+        .iter()
+        .copied()
+        .find(|s| s.name() == name)
+        .map(|s| Box::new(s) as Box<dyn Source>)
+}
+
+/// List the subdirectories of a directory, or nothing if it cannot be read.
+fn subdirectories(directory: &Path) -> Vec<PathBuf> {
+    match directory.read_dir() {
+        Ok(entries) => entries
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|p| p.is_dir())
+            .collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// The user's home directory.
+fn home_directory() -> Option<PathBuf> {
+    let variable = if cfg!(windows) { "USERPROFILE" } else { "HOME" };
+    env::var_os(variable).map(PathBuf::from)
+}
+
+/// Directories that may be the user's Pictures folder.
+fn pictures_directories() -> Vec<PathBuf> {
+    let mut directories = Vec::new();
+    if cfg!(windows) {
+        // OneDrive takes over Pictures when folder backup is enabled.
+        if let Some(onedrive) = env::var_os("OneDrive") {
+            directories.push(Path::new(&onedrive).join("Pictures"));
+        }
+    } else if let Some(pictures) = env::var_os("XDG_PICTURES_DIR") {
+        directories.push(PathBuf::from(pictures));
+    }
+    if let Some(home) = home_directory() {
+        directories.push(home.join("Pictures"));
+    }
+    directories
+}
+
+/// Directories where Steam may be installed.
+fn steam_directories() -> Vec<PathBuf> {
+    let mut directories = Vec::new();
+    if cfg!(windows) {
+        if let Some(program_files) = env::var_os("ProgramFiles(x86)") {
+            directories.push(Path::new(&program_files).join("Steam"));
+        }
+    } else if let Some(home) = home_directory() {
+        directories.push(home.join(".steam/steam"));
+        directories.push(home.join(".local/share/Steam"));
+        directories.push(home.join(".var/app/com.valvesoftware.Steam/.local/share/Steam"));
+    }
+    directories
+}
+
+/// Directories where Steam may have installed games.
+///
+/// Besides the Steam directory itself, these are listed as `"path"` values in
+/// `steamapps/libraryfolders.vdf`.
+fn steam_libraries() -> Vec<PathBuf> {
+    let mut libraries = Vec::new();
+    for steam in steam_directories() {
+        if let Ok(folders) = fs::read_to_string(steam.join("steamapps/libraryfolders.vdf")) {
+            for line in folders.lines() {
+                let mut fields = line.split('"').skip(1).step_by(2);
+                if let (Some("path"), Some(path)) = (fields.next(), fields.next()) {
+                    libraries.push(PathBuf::from(path.replace("\\\\", "\\")));
+                }
+            }
+        }
+        libraries.push(steam);
+    }
+    libraries
+}
+
+/// Check whether a file looks like a screenshot.
+pub fn is_screenshot(path: &Path) -> bool {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some(extension) => ["png", "jpg", "jpeg"]
+            .iter()
+            .any(|e| extension.eq_ignore_ascii_case(e)),
+        None => false,
+    }
+}
+
+/// Add every screenshot in a directory and its subdirectories to `found`.
+fn walk(source: &dyn Source, directory: &Path, found: &mut Vec<PathBuf>) -> anyhow::Result<()> {
+    let entries = directory
+        .read_dir()
+        .with_context(|| format!("Could not read {}", directory.to_string_lossy()))?;
+    for entry in entries {
+        let entry =
+            entry.with_context(|| format!("Could not read {}", directory.to_string_lossy()))?;
+        let path = entry.path();
+        if path.is_dir() {
+            if !source.is_ignored(&path) {
+                walk(source, &path, found)?;
+            }
+        } else if is_screenshot(&path) {
+            found.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// The directories from `Source::directories` that exist, each listed once.
+pub fn existing_directories(source: &dyn Source) -> Vec<PathBuf> {
+    // The same directory is often reachable through more than one path, such as through
+    // Steam's ~/.steam/steam symlink.
+    let mut directories: Vec<_> = source
+        .directories()
+        .into_iter()
+        .filter_map(|d| d.canonicalize().ok())
+        .filter(|d| d.is_dir())
+        .collect();
+    directories.sort();
+    directories.dedup();
+    directories
+}
+
+/// Find every screenshot saved by a game.
+///
+/// Every directory from `existing_directories` is searched, including subdirectories.
+///
+/// # Errors
+///
+/// An error is returned if none of the directories exist or a directory cannot be read.
+pub fn discover(source: &dyn Source) -> anyhow::Result<Vec<PathBuf>> {
+    let directories = existing_directories(source);
+    if directories.is_empty() {
+        bail!("Could not find the {} screenshot folder", source.name());
+    }
+
+    let mut found = Vec::new();
+    for directory in directories {
+        walk(source, &directory, &mut found)?;
+    }
+    found.sort();
+    Ok(found)
+}
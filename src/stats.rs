@@ -0,0 +1,188 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+
+use image::imageops::FilterType;
+use qdcrop::{border, contour, detect_scaled, encode, output_size, warp, CropOptions};
+
+use crate::{capture::CaptureTime, open, source};
+
+/// The longest side of the copy used to count photos in a picture.
+const COUNT_SIZE: u32 = 640;
+
+/// The year and month of a time, as `YYYY-MM`.
+fn month(time: SystemTime) -> String {
+    if time < SystemTime::UNIX_EPOCH {
+        return "before 1970".to_owned();
+    }
+    let time = CaptureTime::from_system_time(time);
+    format!("{:04}-{:02}", time.year, time.month)
+}
+
+/// Format a duration for people, such as `1h 5m` or `42s`.
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    match seconds {
+        0..=59 => format!("{}s", seconds),
+        60..=3599 => format!("{}m {}s", seconds / 60, seconds % 60),
+        _ => format!("{}h {}m", seconds / 3600, seconds % 3600 / 60),
+    }
+}
+
+/// Format a size in bytes for people, such as `12.3 MB`.
+fn format_size(bytes: f64) -> String {
+    const UNITS: &[&str] = &["B", "KB", "MB", "GB", "TB"];
+    let mut size = bytes;
+    let mut unit = 0;
+    while size >= 1000.0 && unit + 1 < UNITS.len() {
+        size /= 1000.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+/// What was measured by processing one sample picture.
+struct Sample {
+    /// The number of photos found in the picture.
+    photos: usize,
+    /// How long cropping the picture took, not including writing it.
+    time: Duration,
+    /// The size of the encoded outputs.
+    bytes: usize,
+}
+
+/// Crop one picture without saving it, to measure how long it takes.
+fn measure(path: &Path, options: &CropOptions) -> anyhow::Result<Sample> {
+    let start = Instant::now();
+    let (img, _) = open(path, options)?;
+
+    let mut bytes = 0;
+    for detection in detect_scaled(&img, options)? {
+        let corners = options.warp_corners(&detection.corners, img.dimensions())?;
+        let size = output_size(
+            &corners,
+            options.aspect_of(&corners, img.dimensions()),
+            options.max_size,
+        );
+        let out_img = warp(&img, corners, size, options)?;
+        bytes += encode(&out_img, options)?.len();
+    }
+    let time = start.elapsed();
+
+    let scale = COUNT_SIZE as f64 / std::cmp::max(img.width(), img.height()) as f64;
+    let small = image::imageops::resize(
+        &img,
+        std::cmp::max((img.width() as f64 * scale).round() as u32, 1),
+        std::cmp::max((img.height() as f64 * scale).round() as u32, 1),
+        FilterType::Triangle,
+    );
+    let mask = border::masks(&small, &image::imageops::grayscale(&small), options)
+        .next()
+        .unwrap();
+    let photos = contour::detect_all(&mask).len();
+
+    Ok(Sample {
+        photos,
+        time,
+        bytes,
+    })
+}
+
+/// Print a table of counts, most common first.
+fn print_counts(title: &str, counts: &BTreeMap<String, usize>, by_count: bool) {
+    println!("{}:", title);
+    let mut rows: Vec<_> = counts.iter().collect();
+    if by_count {
+        rows.sort_by(|a, b| b.1.cmp(a.1));
+    }
+    for (key, count) in rows {
+        println!("  {:<12} {:>7}", key, count);
+    }
+}
+
+/// Run the `stats` subcommand.
+///
+/// # Arguments
+///
+/// * `dir` - The archive to scan.
+/// * `sample_size` - How many pictures to crop to estimate the time and size of a full run.
+/// * `options` - The settings a full run would use.
+///
+/// # Errors
+///
+/// An error is returned if the archive cannot be read.
+pub fn run(dir: &Path, sample_size: usize, options: &CropOptions) -> anyhow::Result<()> {
+    let name = format!("folder:{}", dir.to_string_lossy());
+    let mut files: Vec<PathBuf> = source::discover(&*source::find(&name).unwrap())?;
+    files.sort();
+    println!("{} pictures in {}", files.len(), dir.to_string_lossy());
+    if files.is_empty() {
+        return Ok(());
+    }
+
+    let mut resolutions = BTreeMap::new();
+    let mut months = BTreeMap::new();
+    let mut unreadable = 0;
+    for file in &files {
+        match image::image_dimensions(file) {
+            Ok((width, height)) => {
+                *resolutions
+                    .entry(format!("{}x{}", width, height))
+                    .or_insert(0) += 1
+            }
+            Err(_) => unreadable += 1,
+        }
+        let modified = fs::metadata(file).and_then(|m| m.modified());
+        let key = modified.map(month).unwrap_or_else(|_| "unknown".to_owned());
+        *months.entry(key).or_insert(0) += 1;
+    }
+    println!();
+    print_counts("Resolution", &resolutions, true);
+    if unreadable > 0 {
+        println!("  {:<12} {:>7}", "unreadable", unreadable);
+    }
+    println!();
+    print_counts("Month", &months, false);
+
+    let step = std::cmp::max(files.len() / std::cmp::max(sample_size, 1), 1);
+    let sample: Vec<_> = files.iter().step_by(step).take(sample_size).collect();
+    let mut measured = Vec::new();
+    let mut failed = 0;
+    for path in &sample {
+        match measure(path, options) {
+            Ok(result) => measured.push(result),
+            Err(_) => failed += 1,
+        }
+    }
+    println!();
+    println!("Sampled {} pictures:", sample.len());
+    if failed > 0 {
+        println!("  {} could not be cropped", failed);
+    }
+    if measured.is_empty() {
+        return Ok(());
+    }
+    let n = measured.len() as f64;
+    let photos = measured.iter().map(|s| s.photos).sum::<usize>() as f64 / n;
+    let time = measured
+        .iter()
+        .map(|s| s.time)
+        .sum::<Duration>()
+        .as_secs_f64()
+        / n;
+    let bytes = measured.iter().map(|s| s.bytes).sum::<usize>() as f64 / n;
+    let total = files.len() as f64;
+    let threads = rayon::current_num_threads() as f64;
+    println!("  Photos per picture:    {:.2}", photos);
+    println!("  Time per picture:      {:.2}s", time);
+    println!(
+        "  Estimated total time:  {} on {} threads",
+        format_duration(Duration::from_secs_f64(time * total / threads)),
+        threads
+    );
+    println!("  Estimated output size: {}", format_size(bytes * total));
+    Ok(())
+}
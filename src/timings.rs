@@ -0,0 +1,127 @@
+use std::{
+    cell::RefCell,
+    fmt,
+    ops::AddAssign,
+    time::{Duration, Instant},
+};
+
+/// A stage of cropping a screenshot.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stage {
+    /// Decoding the file and turning it the right way up.
+    Decode,
+    /// Making the images that detectors search for border pixels.
+    Threshold,
+    /// Finding photos, apart from thresholding.
+    Detection,
+    /// Warping photos to rectangles.
+    Warp,
+    /// Encoding outputs.
+    Encode,
+}
+
+impl Stage {
+    /// Every stage, in the order they run.
+    pub const ALL: [Stage; 5] = [
+        Stage::Decode,
+        Stage::Threshold,
+        Stage::Detection,
+        Stage::Warp,
+        Stage::Encode,
+    ];
+
+    /// The name of the stage as it is reported.
+    pub fn name(self) -> &'static str {
+        match self {
+            Stage::Decode => "decode",
+            Stage::Threshold => "threshold",
+            Stage::Detection => "detection",
+            Stage::Warp => "warp",
+            Stage::Encode => "encode",
+        }
+    }
+}
+
+/// How long each stage took.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Timings([Duration; 5]);
+
+impl Timings {
+    /// How long a stage took.
+    pub fn get(&self, stage: Stage) -> Duration {
+        self.0[stage as usize]
+    }
+
+    /// How long all stages took.
+    pub fn total(&self) -> Duration {
+        self.0.iter().sum()
+    }
+}
+
+impl AddAssign for Timings {
+    fn add_assign(&mut self, other: Timings) {
+        for (a, b) in self.0.iter_mut().zip(other.0) {
+            *a += b;
+        }
+    }
+}
+
+impl fmt::Display for Timings {
+    /// Formats as `decode 12.3 ms, threshold 4.5 ms, ...`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, stage) in Stage::ALL.into_iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            let ms = self.get(stage).as_secs_f64() * 1000.0;
+            write!(f, "{} {:.1} ms", stage.name(), ms)?;
+        }
+        Ok(())
+    }
+}
+
+/// What is being measured on a thread.
+#[derive(Default)]
+struct Recording {
+    /// The time of each stage so far, without the stages that ran inside it.
+    timings: Timings,
+    /// The time of stages that ran inside the stage that is running.
+    nested: Duration,
+}
+
+thread_local! {
+    /// The measurement of the `measure` call running on this thread, if any.
+    static RECORDING: RefCell<Option<Recording>> = const { RefCell::new(None) };
+}
+
+/// Run `f` as part of `stage`.
+///
+/// The time is only measured inside `measure`, so this costs nothing otherwise. Stages that run
+/// inside `f` are not counted twice.
+pub fn time<T>(stage: Stage, f: impl FnOnce() -> T) -> T {
+    let recording = RECORDING.with(|r| r.borrow().is_some());
+    if !recording {
+        return f();
+    }
+    let outer = RECORDING.with(|r| std::mem::take(&mut r.borrow_mut().as_mut().unwrap().nested));
+    let start = Instant::now();
+    let value = f();
+    let elapsed = start.elapsed();
+    RECORDING.with(|r| {
+        let mut r = r.borrow_mut();
+        let r = r.as_mut().unwrap();
+        let inner = std::mem::replace(&mut r.nested, outer + elapsed);
+        r.timings.0[stage as usize] += elapsed.saturating_sub(inner);
+    });
+    value
+}
+
+/// Run `f` and measure how long each stage took in it on this thread.
+///
+/// Calls can be nested, which happens when the thread pool runs other work while `f` waits.
+pub fn measure<T>(f: impl FnOnce() -> T) -> (T, Timings) {
+    let outer = RECORDING.with(|r| r.replace(Some(Recording::default())));
+    let value = f();
+    let recording = RECORDING.with(|r| r.replace(outer));
+    (value, recording.unwrap_or_default().timings)
+}
@@ -0,0 +1,52 @@
+use image::{GenericImageView, RgbImage};
+
+/// The most lines trimmed from each edge.
+const MAX_TRIM: u32 = 4;
+/// Lines at least this much darker than the photo inside them are left over from the frame.
+const DARKNESS: f64 = 0.6;
+/// Lines with a mean luminance of this or less are left over from the frame however dark the
+/// photo is.
+const BLACK: f64 = 24.0;
+
+/// The mean luminance of a line of pixels.
+fn luminance(pixels: impl Iterator<Item = [u8; 3]>) -> f64 {
+    let (mut sum, mut count) = (0.0, 0);
+    for [r, g, b] in pixels {
+        sum += 0.299 * r as f64 + 0.587 * g as f64 + 0.114 * b as f64;
+        count += 1;
+    }
+    sum / count.max(1) as f64
+}
+
+/// Count the dark lines at one edge of a photo.
+///
+/// `line` returns the mean luminance of the line at a distance from the edge.
+fn dark_lines(line: impl Fn(u32) -> f64) -> u32 {
+    let inside = line(MAX_TRIM);
+    (0..MAX_TRIM)
+        .take_while(|&i| {
+            let luminance = line(i);
+            luminance <= BLACK || luminance < inside * DARKNESS
+        })
+        .count() as u32
+}
+
+/// Cut off thin dark lines left along the edges of a warped photo by the frame.
+///
+/// Each edge loses at most a few lines, and only lines that are much darker than the photo
+/// next to them.
+pub fn trim(img: &RgbImage) -> RgbImage {
+    let (width, height) = img.dimensions();
+    if width <= MAX_TRIM * 4 || height <= MAX_TRIM * 4 {
+        return img.clone();
+    }
+    let row = |y: u32| luminance((0..width).map(|x| img.get_pixel(x, y).0));
+    let column = |x: u32| luminance((0..height).map(|y| img.get_pixel(x, y).0));
+
+    let top = dark_lines(row);
+    let bottom = dark_lines(|i| row(height - 1 - i));
+    let left = dark_lines(column);
+    let right = dark_lines(|i| column(width - 1 - i));
+    img.view(left, top, width - left - right, height - top - bottom)
+        .to_image()
+}
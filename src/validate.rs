@@ -0,0 +1,96 @@
+use anyhow::bail;
+
+use crate::{contour, Quad};
+
+/// The smallest angle a corner may have, in degrees. Smaller corners are nearly in a line with
+/// their neighbors.
+const MIN_ANGLE: f32 = 10.0;
+
+/// How close each corner must be to the corner of the picture for the photo to fill it, in
+/// pixels.
+const WHOLE_FRAME: f32 = 3.0;
+
+/// Format corners for error messages.
+fn describe(quad: &Quad) -> String {
+    quad.iter()
+        .map(|(x, y)| format!("({:.0}, {:.0})", x, y))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Check that corners can be warped into a sensible photo.
+///
+/// # Arguments
+///
+/// * `quad` - The corners, which should be clockwise from the top left.
+/// * `size` - The size of the screenshot the corners are in.
+/// * `min_area` - The smallest part of the screenshot the photo may cover, in percent.
+///
+/// # Errors
+///
+/// An error describing the problem is returned if the corners are counterclockwise, cross over
+/// each other, are nearly in a line, or enclose too little of the screenshot.
+pub fn validate(quad: &Quad, size: (u32, u32), min_area: f64) -> anyhow::Result<()> {
+    let mut clockwise = 0;
+    let mut counterclockwise = 0;
+    let mut sharpest = 180.0f32;
+    for (i, &corner) in quad.iter().enumerate() {
+        let (prev, next) = (quad[(i + 3) % 4], quad[(i + 1) % 4]);
+        let incoming = (corner.0 - prev.0, corner.1 - prev.1);
+        let outgoing = (next.0 - corner.0, next.1 - corner.1);
+        let lengths = incoming.0.hypot(incoming.1) * outgoing.0.hypot(outgoing.1);
+        if lengths <= f32::EPSILON {
+            bail!(
+                "Two of the corners found are the same point: {}",
+                describe(quad)
+            );
+        }
+        // Clockwise turns are positive on screen, where y points down.
+        let cross = incoming.0 * outgoing.1 - incoming.1 * outgoing.0;
+        if cross > 0.0 {
+            clockwise += 1;
+        } else if cross < 0.0 {
+            counterclockwise += 1;
+        }
+        let angle = (cross / lengths).clamp(-1.0, 1.0).asin().abs().to_degrees();
+        sharpest = sharpest.min(angle);
+    }
+    if clockwise != 4 && counterclockwise != 4 {
+        bail!(
+            "The corners found cross over each other or are nearly in a line: {}",
+            describe(quad)
+        );
+    }
+    if counterclockwise == 4 {
+        bail!(
+            "The corners found are counterclockwise instead of clockwise from the top left: {}",
+            describe(quad)
+        );
+    }
+    if sharpest < MIN_ANGLE {
+        bail!(
+            "Three of the corners found are nearly in a line: {}",
+            describe(quad)
+        );
+    }
+    let coverage = contour::area(quad) / (size.0 as f64 * size.1 as f64) * 100.0;
+    if coverage < min_area {
+        bail!(
+            "The corners found enclose only {:.1}% of the picture, less than the minimum of {}%: {}",
+            coverage,
+            min_area,
+            describe(quad)
+        );
+    }
+    Ok(())
+}
+
+/// Check whether corners are at the corners of the picture, meaning that it has no border and
+/// is already a cropped photo.
+pub fn is_whole_frame(quad: &Quad, size: (u32, u32)) -> bool {
+    let (width, height) = (size.0 as f32, size.1 as f32);
+    let frame = [(0.0, 0.0), (width, 0.0), (width, height), (0.0, height)];
+    quad.iter()
+        .zip(frame)
+        .all(|(c, f)| (c.0 - f.0).hypot(c.1 - f.1) <= WHOLE_FRAME)
+}
@@ -0,0 +1,87 @@
+use std::{fs, path::Path};
+
+use anyhow::{bail, Context};
+use image::RgbImage;
+
+/// The number of blocks along each side of a fingerprint.
+const GRID: u32 = 16;
+/// The largest difference allowed between block means. WebP is lossy, so the decoded image will
+/// not match exactly.
+const TOLERANCE: u8 = 8;
+
+/// A coarse summary of an image that survives lossy encoding.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Fingerprint {
+    width: u32,
+    height: u32,
+    /// The mean color of each block, row by row.
+    blocks: Vec<[u8; 3]>,
+}
+
+impl Fingerprint {
+    /// Summarize an image.
+    pub fn of(img: &RgbImage) -> Self {
+        let (width, height) = img.dimensions();
+        let mut sums = vec![([0u64; 3], 0u64); (GRID * GRID) as usize];
+        for (x, y, pixel) in img.enumerate_pixels() {
+            let block = (y * GRID / height * GRID + x * GRID / width) as usize;
+            let (sum, count) = &mut sums[block];
+            for (s, v) in sum.iter_mut().zip(pixel.0) {
+                *s += v as u64;
+            }
+            *count += 1;
+        }
+        let blocks = sums
+            .into_iter()
+            .map(|(sum, count)| sum.map(|s| (s / std::cmp::max(count, 1)) as u8))
+            .collect();
+        Fingerprint {
+            width,
+            height,
+            blocks,
+        }
+    }
+}
+
+/// Decode a written output and check that it still matches the image that was encoded.
+///
+/// # Errors
+///
+/// An error is returned if the file cannot be decoded, or if its size or content differ from
+/// `expected`.
+pub fn verify(path: &Path, expected: &Fingerprint) -> anyhow::Result<()> {
+    let data = fs::read(path).context("Could not read output back")?;
+    let decoded = webp::Decoder::new(&data)
+        .decode()
+        .context("Could not decode output")?
+        .to_image()
+        .into_rgb8();
+    let actual = Fingerprint::of(&decoded);
+    if (actual.width, actual.height) != (expected.width, expected.height) {
+        bail!(
+            "Output is {}x{} but should be {}x{}",
+            actual.width,
+            actual.height,
+            expected.width,
+            expected.height
+        );
+    }
+    let mismatched = actual
+        .blocks
+        .iter()
+        .zip(&expected.blocks)
+        .filter(|(a, e)| {
+            a.iter()
+                .zip(e.iter())
+                .any(|(a, e)| a.abs_diff(*e) > TOLERANCE)
+        })
+        .count();
+    if mismatched > 0 {
+        bail!(
+            "Output does not match what was encoded ({} of {} blocks differ)",
+            mismatched,
+            expected.blocks.len()
+        );
+    }
+    Ok(())
+}
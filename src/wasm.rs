@@ -0,0 +1,89 @@
+use image::{codecs::png::PngEncoder, ColorType, RgbImage};
+use wasm_bindgen::prelude::*;
+
+use crate::{crop_photo, detect_scaled, AspectMode, CropOptions, Detection, Quad};
+
+/// Decode a screenshot from the bytes of its file.
+fn decode(screenshot: &[u8]) -> Result<RgbImage, JsError> {
+    let img = image::load_from_memory(screenshot).map_err(crate::Error::DecodeError)?;
+    Ok(img.into_rgb8())
+}
+
+/// Build the settings for the browser, which only chooses the proportions of the photo.
+fn options(aspect: Option<String>) -> Result<CropOptions, JsError> {
+    let mut builder = CropOptions::builder();
+    if let Some(aspect) = aspect {
+        let aspect: AspectMode = aspect
+            .parse()
+            .map_err(|e: anyhow::Error| JsError::new(&e.to_string()))?;
+        builder = builder.aspect(aspect);
+    }
+    Ok(builder.build()?)
+}
+
+/// Find the most confident photo in a screenshot.
+fn find(img: &RgbImage, options: &CropOptions) -> Result<Detection, JsError> {
+    Ok(detect_scaled(img, options)?[0])
+}
+
+/// Find the corners of the photo in a screenshot.
+///
+/// Returns eight numbers, the x and y of each corner clockwise from the top left, in pixels of
+/// the screenshot.
+#[wasm_bindgen]
+pub fn detect(screenshot: &[u8], aspect: Option<String>) -> Result<Vec<f32>, JsError> {
+    let img = decode(screenshot)?;
+    let detection = find(&img, &options(aspect)?)?;
+    Ok(detection
+        .corners
+        .iter()
+        .flat_map(|&(x, y)| [x, y])
+        .collect())
+}
+
+/// Crop the photo out of a screenshot and return it as a PNG file.
+///
+/// # Arguments
+///
+/// * `screenshot` - The bytes of the screenshot file.
+/// * `corners` - Eight numbers as returned by `detect`, such as after the user moved them, or
+///   nothing to find the photo.
+/// * `aspect` - The proportions of the photo, like `4:3` or `auto`, or nothing for 16:9.
+#[wasm_bindgen]
+pub fn crop(
+    screenshot: &[u8],
+    corners: Option<Vec<f32>>,
+    aspect: Option<String>,
+) -> Result<Vec<u8>, JsError> {
+    let img = decode(screenshot)?;
+    let mut options = options(aspect)?;
+    let detection = match corners {
+        Some(corners) => {
+            let corners: [f32; 8] = corners
+                .try_into()
+                .map_err(|_| JsError::new("Expected 8 numbers for 4 corners"))?;
+            let [x0, y0, x1, y1, x2, y2, x3, y3] = corners;
+            let corners: Quad = [(x0, y0), (x1, y1), (x2, y2), (x3, y3)];
+            // Given corners are always warped, even at the edges of the screenshot.
+            options.corners = vec![corners];
+            Detection {
+                corners,
+                confidence: 1.0,
+                detector: None,
+            }
+        }
+        None => find(&img, &options)?,
+    };
+    let photo = crop_photo(&img, &detection, &options)?;
+
+    let mut png = Vec::new();
+    PngEncoder::new(&mut png)
+        .encode(
+            photo.image.as_raw(),
+            photo.image.width(),
+            photo.image.height(),
+            ColorType::Rgb8,
+        )
+        .map_err(|e| JsError::new(&format!("Could not encode output: {}", e)))?;
+    Ok(png)
+}
@@ -0,0 +1,198 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::mpsc,
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use notify::{event::ModifyKind, EventKind, RecursiveMode, Watcher};
+use qdcrop::{pool, CropOptions};
+
+use crate::{
+    crop, lock,
+    notification::{self, Summary},
+    output_name, power, source, webhook, Naming,
+};
+
+/// How long a screenshot must go unchanged before it is cropped, so that it is not read while
+/// the game is still writing it.
+const SETTLE_TIME: Duration = Duration::from_secs(1);
+
+/// Crop one new screenshot, report the result, and add it to `summary` and its photos to
+/// `outputs`.
+fn crop_new(
+    input: &Path,
+    output_dir: &Path,
+    naming: Naming,
+    options: &CropOptions,
+    summary: &mut Summary,
+    outputs: &mut Vec<PathBuf>,
+) {
+    let output = output_dir.join(output_name(input, naming));
+    if let Some(parent) = output.parent() {
+        if let Err(error) = fs::create_dir_all(parent) {
+            eprintln!("Could not create {}: {}", parent.to_string_lossy(), error);
+            summary.failed += 1;
+            return;
+        }
+    }
+    match crop(input, &output, options) {
+        Ok(Some(cropped)) => {
+            for output in &cropped.outputs {
+                eprintln!(
+                    "Cropped {} to {}",
+                    input.to_string_lossy(),
+                    output.to_string_lossy()
+                );
+            }
+            outputs.extend(cropped.outputs);
+            summary.cropped += 1;
+        }
+        Ok(None) => {
+            eprintln!(
+                "Skipped {} because {} already exists",
+                input.to_string_lossy(),
+                output.to_string_lossy()
+            );
+            summary.skipped += 1;
+        }
+        Err(error) => {
+            eprintln!(
+                "Error while converting {}: {}",
+                input.to_string_lossy(),
+                error
+            );
+            summary.failed += 1;
+        }
+    }
+}
+
+/// What to do with the screenshots that `run` finds.
+pub struct Watch<'a> {
+    /// Where to write the cropped photos.
+    pub output_dir: &'a Path,
+    /// How to name the outputs.
+    pub naming: Naming<'a>,
+    /// Whether to lock the output folder against other runs of qdcrop.
+    pub lock: bool,
+    /// Whether to show a desktop notification after each new screenshot is cropped.
+    pub notify: bool,
+    /// The URL of a Discord webhook to post the photos to.
+    pub webhook: Option<&'a str>,
+    /// If given, new screenshots wait while the computer is running on battery.
+    pub battery_saver: Option<&'a power::Saver>,
+    /// Settings that control detection and output.
+    pub options: &'a CropOptions,
+}
+
+/// Crop new screenshots in `dirs`, including their subfolders, until interrupted.
+///
+/// # Errors
+///
+/// An error is returned if a folder cannot be watched or the output folder cannot be locked.
+pub fn run(dirs: &[PathBuf], watch: &Watch) -> anyhow::Result<()> {
+    let Watch {
+        output_dir,
+        naming,
+        lock,
+        notify,
+        webhook,
+        battery_saver,
+        options,
+    } = *watch;
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher =
+        notify::recommended_watcher(sender).context("Could not start watching for files")?;
+    for dir in dirs {
+        watcher
+            .watch(dir, RecursiveMode::Recursive)
+            .with_context(|| format!("Could not watch {}", dir.to_string_lossy()))?;
+    }
+    fs::create_dir_all(output_dir).with_context(|| {
+        format!(
+            "Could not create output folder {}",
+            output_dir.to_string_lossy()
+        )
+    })?;
+    let _lock = if lock {
+        Some(lock::SessionLock::acquire(output_dir)?)
+    } else {
+        None
+    };
+    let names: Vec<_> = dirs.iter().map(|dir| dir.to_string_lossy()).collect();
+    eprintln!(
+        "Watching {} for new screenshots. Press Ctrl+C to stop.",
+        names.join(" and ")
+    );
+
+    // Screenshots that changed recently, and when they last changed.
+    let mut pending: HashMap<PathBuf, Instant> = HashMap::new();
+    let mut paused = false;
+    loop {
+        match receiver.recv_timeout(SETTLE_TIME / 4) {
+            Ok(Ok(event)) => {
+                if let EventKind::Create(_)
+                | EventKind::Modify(
+                    ModifyKind::Data(_) | ModifyKind::Name(_) | ModifyKind::Any,
+                ) = event.kind
+                {
+                    for path in event.paths {
+                        if source::is_screenshot(&path) {
+                            pending.insert(path, Instant::now());
+                        }
+                    }
+                }
+            }
+            Ok(Err(error)) => eprintln!("Error while watching for files: {}", error),
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+            Err(mpsc::RecvTimeoutError::Disconnected) => return Ok(()),
+        }
+
+        // Screenshots stay pending while on battery, and are cropped once it is plugged in.
+        let on_battery = battery_saver.is_some_and(|saver| saver.on_battery());
+        if on_battery != paused {
+            paused = on_battery;
+            if paused {
+                eprintln!("Running on battery: new screenshots will be cropped once plugged in");
+            } else {
+                eprintln!("Plugged in: cropping new screenshots again");
+            }
+        }
+        if paused {
+            continue;
+        }
+
+        let mut settled: Vec<_> = pending
+            .iter()
+            .filter(|(_, changed)| changed.elapsed() >= SETTLE_TIME)
+            .map(|(path, _)| path.clone())
+            .collect();
+        settled.sort();
+        let mut summary = Summary::default();
+        let mut outputs = Vec::new();
+        for input in settled {
+            pending.remove(&input);
+            // Renamed or deleted files also cause events.
+            if input.is_file() {
+                crop_new(
+                    &input,
+                    output_dir,
+                    naming,
+                    options,
+                    &mut summary,
+                    &mut outputs,
+                );
+            }
+        }
+        // Screenshots come a few at a time, so buffers are not kept between them.
+        pool::clear();
+        if notify && summary.cropped + summary.failed > 0 {
+            notification::send(&summary, Some(output_dir));
+        }
+        if let (Some(url), false) = (webhook, outputs.is_empty()) {
+            webhook::post_or_report(url, &outputs);
+        }
+    }
+}
@@ -0,0 +1,137 @@
+use std::{
+    fs,
+    path::PathBuf,
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{bail, Context};
+use serde_json::json;
+
+/// The most files Discord accepts in one message.
+const MAX_FILES: usize = 10;
+/// The most bytes Discord accepts in one message on servers without boosts.
+const MAX_BYTES: usize = 10 * 1000 * 1000;
+/// How many times a message is sent again when Discord asks to slow down.
+const MAX_RETRIES: usize = 5;
+
+/// Build a `multipart/form-data` body with the files of one message.
+///
+/// Returns the content type with its boundary and the body.
+fn form(files: &[(String, Vec<u8>)]) -> (String, Vec<u8>) {
+    let nanos = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos());
+    let boundary = format!("qdcrop-boundary-{:x}", nanos);
+    let attachments: Vec<_> = files
+        .iter()
+        .enumerate()
+        .map(|(i, (name, _))| json!({ "id": i, "filename": name }))
+        .collect();
+
+    let mut body = Vec::new();
+    body.extend_from_slice(
+        format!(
+            "--{}\r\nContent-Disposition: form-data; name=\"payload_json\"\r\nContent-Type: application/json\r\n\r\n{}\r\n",
+            boundary,
+            json!({ "attachments": attachments })
+        )
+        .as_bytes(),
+    );
+    for (i, (name, data)) in files.iter().enumerate() {
+        body.extend_from_slice(
+            format!(
+                "--{}\r\nContent-Disposition: form-data; name=\"files[{}]\"; filename=\"{}\"\r\nContent-Type: image/webp\r\n\r\n",
+                boundary,
+                i,
+                name.replace('"', "'")
+            )
+            .as_bytes(),
+        );
+        body.extend_from_slice(data);
+        body.extend_from_slice(b"\r\n");
+    }
+    body.extend_from_slice(format!("--{}--\r\n", boundary).as_bytes());
+    (format!("multipart/form-data; boundary={}", boundary), body)
+}
+
+/// Send one message, waiting and trying again when Discord is rate limiting.
+fn send(url: &str, files: &[(String, Vec<u8>)]) -> anyhow::Result<()> {
+    let (content_type, body) = form(files);
+    for _ in 0..MAX_RETRIES {
+        match ureq::post(url)
+            .set("Content-Type", &content_type)
+            .send_bytes(&body)
+        {
+            Ok(_) => return Ok(()),
+            Err(ureq::Error::Status(429, response)) => {
+                let wait = response
+                    .into_json::<serde_json::Value>()
+                    .ok()
+                    .and_then(|json| json["retry_after"].as_f64())
+                    .unwrap_or(1.0);
+                thread::sleep(Duration::from_secs_f64(wait.clamp(0.0, 60.0)));
+            }
+            Err(ureq::Error::Status(status, response)) => bail!(
+                "Discord refused the photos with status {}: {}",
+                status,
+                response.into_string().unwrap_or_default()
+            ),
+            Err(error) => return Err(error).context("Could not reach the webhook"),
+        }
+    }
+    bail!(
+        "Discord is rate limiting the webhook; gave up after {} tries",
+        MAX_RETRIES
+    )
+}
+
+/// Post cropped photos to a Discord webhook.
+///
+/// The photos are sent in as few messages as Discord allows, in order. Photos that are too
+/// large for a message are skipped with a warning.
+///
+/// # Arguments
+///
+/// * `url` - The URL of the webhook, from the integration settings of a Discord channel.
+/// * `outputs` - The photos to post.
+///
+/// # Errors
+///
+/// An error is returned if a photo cannot be read or Discord does not accept a message.
+pub fn post(url: &str, outputs: &[PathBuf]) -> anyhow::Result<()> {
+    let mut message: Vec<(String, Vec<u8>)> = Vec::new();
+    let mut size = 0;
+    for output in outputs {
+        let data = fs::read(output)
+            .with_context(|| format!("Could not read {}", output.to_string_lossy()))?;
+        if data.len() > MAX_BYTES {
+            eprintln!(
+                "Did not post {} because it is too large for Discord",
+                output.to_string_lossy()
+            );
+            continue;
+        }
+        if message.len() == MAX_FILES || size + data.len() > MAX_BYTES {
+            send(url, &message)?;
+            message.clear();
+            size = 0;
+        }
+        size += data.len();
+        let name = output
+            .file_name()
+            .map_or_else(|| "photo.webp".into(), |n| n.to_string_lossy());
+        message.push((name.into_owned(), data));
+    }
+    if !message.is_empty() {
+        send(url, &message)?;
+    }
+    Ok(())
+}
+
+/// Post photos with `post`, only reporting errors, for runs that keep going.
+pub fn post_or_report(url: &str, outputs: &[PathBuf]) {
+    if let Err(error) = post(url, outputs) {
+        eprintln!("Could not post to the webhook: {:#}", error);
+    }
+}
@@ -0,0 +1,111 @@
+use image::{GenericImageView, GrayImage};
+
+use crate::{
+    border, find_nearest_to_corner, pool, timings, validate, CornerDetector, CropOptions,
+    Detection, Detector,
+};
+
+/// How far around the outline of a photo is thresholded to measure confidence, in pixels.
+const BAND: u32 = 8;
+/// The length of the pieces of the outline that are thresholded one at a time, in pixels.
+const SEGMENT: f32 = 64.0;
+
+/// Threshold one rectangle of the screenshot into `threshold`, at the same place.
+///
+/// Pixels within the threshold radius of the edges of the rectangle may differ slightly from
+/// thresholding the whole screenshot, since their neighbors outside it are not seen.
+fn threshold_region(
+    luma: &GrayImage,
+    threshold: &mut GrayImage,
+    (x, y, width, height): (u32, u32, u32, u32),
+    options: &CropOptions,
+) {
+    let region = luma.view(x, y, width, height).to_image();
+    let region = timings::time(timings::Stage::Threshold, || {
+        border::threshold(&region, options)
+    });
+    image::imageops::replace(threshold, &region, x as i64, y as i64);
+}
+
+/// The rectangle around a line from `a` to `b`, widened by `BAND` and kept inside the image.
+fn band(a: (f32, f32), b: (f32, f32), (width, height): (u32, u32)) -> (u32, u32, u32, u32) {
+    let clamp = |v: f32, max: u32| (v.max(0.0) as u32).min(max);
+    let left = clamp(a.0.min(b.0) - BAND as f32, width);
+    let top = clamp(a.1.min(b.1) - BAND as f32, height);
+    let right = clamp(a.0.max(b.0) + BAND as f32 + 1.0, width);
+    let bottom = clamp(a.1.max(b.1) + BAND as f32 + 1.0, height);
+    (left, top, right - left, bottom - top)
+}
+
+/// Find the photo with the nearest detector, thresholding only windows at the corners of the
+/// screenshot instead of all of it.
+///
+/// Each window is `percent` of the width and height of the screenshot. Once the corners are
+/// found, a band along the outline between them is thresholded too, so that confidence is
+/// measured as usual.
+///
+/// Returns `None` if a window has no border pixels, or if the photo that was found is not
+/// valid or not confident enough, so that the whole screenshot is searched instead.
+pub(crate) fn detect_nearest(
+    luma: &GrayImage,
+    options: &CropOptions,
+    percent: f32,
+) -> Option<Detection> {
+    let (width, height) = luma.dimensions();
+    let window_width = ((width as f32 * percent / 100.0).ceil() as u32).clamp(1, width);
+    let window_height = ((height as f32 * percent / 100.0).ceil() as u32).clamp(1, height);
+    let mut threshold = pool::gray(width, height);
+    threshold.fill(255);
+
+    let windows =
+        [(false, false), (true, false), (true, true), (false, true)].map(|(flip_x, flip_y)| {
+            let x = if flip_x { width - window_width } else { 0 };
+            let y = if flip_y { height - window_height } else { 0 };
+            (x, y, flip_x, flip_y)
+        });
+    for (x, y, _, _) in windows {
+        threshold_region(
+            luma,
+            &mut threshold,
+            (x, y, window_width, window_height),
+            options,
+        );
+    }
+    for region in &options.exclude {
+        region.fill(&mut threshold, 255);
+    }
+    let mut corners = [(0.0, 0.0); 4];
+    for (corner, (x, y, flip_x, flip_y)) in corners.iter_mut().zip(windows) {
+        let window = threshold.view(x, y, window_width, window_height);
+        let (found_x, found_y) = find_nearest_to_corner(&window, flip_x, flip_y)?;
+        *corner = ((x + found_x) as f32, (y + found_y) as f32);
+    }
+    validate::validate(&corners, (width, height), options.min_area).ok()?;
+
+    for i in 0..4 {
+        let (a, b) = (corners[i], corners[(i + 1) % 4]);
+        let pieces = (((b.0 - a.0).hypot(b.1 - a.1) / SEGMENT).ceil() as usize).max(1);
+        for piece in 0..pieces {
+            let point = |t: f32| (a.0 + (b.0 - a.0) * t, a.1 + (b.1 - a.1) * t);
+            let from = point(piece as f32 / pieces as f32);
+            let to = point((piece + 1) as f32 / pieces as f32);
+            threshold_region(
+                luma,
+                &mut threshold,
+                band(from, to, (width, height)),
+                options,
+            );
+        }
+    }
+    for region in &options.exclude {
+        region.fill(&mut threshold, 255);
+    }
+
+    let detection = Detection {
+        corners,
+        confidence: Detector::Nearest.confidence(&threshold, &corners, options),
+        detector: Some(Detector::Nearest.name()),
+    };
+    pool::recycle(threshold);
+    (detection.confidence >= options.min_confidence).then_some(detection)
+}
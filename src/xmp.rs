@@ -0,0 +1,85 @@
+use std::{fmt::Write, fs, path::Path};
+
+use anyhow::Context;
+use qdcrop::{CropOptions, Detection, Quad};
+
+/// The namespace of qdcrop's own XMP properties.
+const NAMESPACE: &str = "https://github.com/nil-vr/qdcrop#";
+
+/// Format numbers for an XMP property.
+fn list(values: impl IntoIterator<Item = f32>, precision: usize) -> String {
+    values
+        .into_iter()
+        .map(|v| format!("{:.*}", precision, v))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Write an XMP sidecar recording how an output was made, next to the output.
+///
+/// The sidecar has the same name as the output with the extension `.xmp`, as photo managers
+/// expect. It records the qdcrop version, the detected and warped corners, the projection from
+/// the screenshot to the output, and the settings of the warp and the encoder, so the output can
+/// be made again with different settings.
+///
+/// # Arguments
+///
+/// * `output` - The output the sidecar describes.
+/// * `detection` - The detected corners of the photo.
+/// * `corners` - The corners that were warped, after `--margin` and `--deskew-only`.
+/// * `matrix` - The projection from screenshot coordinates to output coordinates, row by row.
+/// * `options` - The settings the output was made with.
+///
+/// # Errors
+///
+/// An error is returned if the sidecar cannot be written.
+pub fn write_sidecar(
+    output: &Path,
+    detection: &Detection,
+    corners: &Quad,
+    matrix: &[f32; 9],
+    options: &CropOptions,
+) -> anyhow::Result<()> {
+    let mut properties = String::new();
+    let mut property = |name: &str, value: String| {
+        writeln!(properties, "   <qdcrop:{0}>{1}</qdcrop:{0}>", name, value).unwrap();
+    };
+    property(
+        "DetectedCorners",
+        list(detection.corners.iter().flat_map(|p| [p.0, p.1]), 1),
+    );
+    property(
+        "WarpedCorners",
+        list(corners.iter().flat_map(|p| [p.0, p.1]), 1),
+    );
+    property("Confidence", format!("{:.3}", detection.confidence));
+    property(
+        "Detector",
+        detection.detector.unwrap_or("manual").to_owned(),
+    );
+    property("Matrix", list(matrix.iter().copied(), 9));
+    property("Interpolation", format!("{:?}", options.interpolation));
+    property("ScaleMode", format!("{:?}", options.scale_mode));
+    property("ResizeFilter", format!("{:?}", options.resize_filter));
+    property("Quality", format!("{}", options.quality));
+    property("FastEncode", format!("{}", options.fast_encode));
+
+    let text = format!(
+        r#"<?xpacket begin="" id="W5M0MpCehiHzreSzNTczkc9d"?>
+<x:xmpmeta xmlns:x="adobe:ns:meta/">
+ <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+  <rdf:Description rdf:about=""
+    xmlns:xmp="http://ns.adobe.com/xap/1.0/"
+    xmlns:qdcrop="{}">
+   <xmp:CreatorTool>qdcrop {}</xmp:CreatorTool>
+{}  </rdf:Description>
+ </rdf:RDF>
+</x:xmpmeta>
+<?xpacket end="w"?>
+"#,
+        NAMESPACE,
+        env!("CARGO_PKG_VERSION"),
+        properties
+    );
+    fs::write(output.with_extension("xmp"), text).context("Could not write XMP sidecar")
+}